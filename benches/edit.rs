@@ -98,21 +98,25 @@ impl RandomInput {
                 key: self.gen_random_key_from(VALID_CTRL_KEYS, VALID_CTRL_SPECIAL_KEYS),
                 ctrl: true,
                 alt: false,
+                shift: false,
             },
             5..=9 => InputSeq {
                 key: self.gen_random_key_from(VALID_ALT_KEYS, VALID_ALT_SPECIAL_KEYS),
                 ctrl: false,
                 alt: true,
+                shift: false,
             },
             10..=14 => InputSeq {
                 key: VALID_SPECIAL_KEYS[self.rng.gen_range(0, VALID_SPECIAL_KEYS.len())].clone(),
                 ctrl: false,
                 alt: false,
+                shift: false,
             },
             _ => InputSeq {
                 key: self.gen_normal_ascii_input(),
                 ctrl: false,
                 alt: false,
+                shift: false,
             },
         }
     }
@@ -2,7 +2,7 @@
 
 extern crate test;
 
-use kiro_editor::{Editor, InputSeq, KeySeq, Result, StdinRawMode};
+use kiro_editor::{Config, Editor, InputSeq, KeySeq, Result, StdinRawMode};
 use std::io;
 use std::path::Path;
 use test::Bencher;
@@ -31,7 +31,14 @@ fn with_term_open_plain_text(b: &mut Bencher) {
     b.iter(|| {
         let _stdin = StdinRawMode::new().unwrap();
         let files = &[Path::new("README.md")];
-        let mut editor = Editor::open(NeverInput, io::stdout(), Some((80, 24)), files).unwrap();
+        let mut editor = Editor::open(
+            NeverInput,
+            io::stdout(),
+            Some((80, 24)),
+            files,
+            Config::default(),
+        )
+        .unwrap();
         editor.first_paint().unwrap();
     });
 }
@@ -41,7 +48,14 @@ fn with_term_open_highlighted_code(b: &mut Bencher) {
     b.iter(|| {
         let _stdin = StdinRawMode::new().unwrap();
         let files = &[Path::new("src/editor.rs")];
-        let mut editor = Editor::open(NeverInput, io::stdout(), Some((80, 24)), files).unwrap();
+        let mut editor = Editor::open(
+            NeverInput,
+            io::stdout(),
+            Some((80, 24)),
+            files,
+            Config::default(),
+        )
+        .unwrap();
         editor.first_paint().unwrap();
     });
 }
@@ -30,12 +30,14 @@ const UP: InputSeq = InputSeq {
     key: KeySeq::Key(b'v'),
     ctrl: false,
     alt: true,
+    shift: false,
 };
 
 const DOWN: InputSeq = InputSeq {
     key: KeySeq::Key(b'v'),
     ctrl: true,
     alt: false,
+    shift: false,
 };
 
 impl Iterator for ScrollInput {
@@ -0,0 +1,232 @@
+// Configuration loaded from 'config.toml' in the config directory.
+//
+// Example of TOML file:
+//
+//   indent_style = "space"
+//   indent_size = 4
+//   tab_width = 8
+//   show_whitespace = true
+//   background = "light"
+//   # theme = "gruvbox" loads themes/gruvbox.toml instead of [theme] table
+//   trim_trailing_whitespace = true
+//   trim_trailing_blank_lines = true
+//
+//   [theme.comment]
+//   fg = "gray"
+//   italic = true
+//
+//   [keymap]
+//   "Ctrl-X Ctrl-S" = "save"
+//
+//   [formatter]
+//   rust = "rustfmt --edition 2018"
+//
+//   [save_hooks.markdown]
+//   trim_trailing_whitespace = false
+use crate::editorconfig::{EditorConfig, IndentSize, IndentStyle};
+use crate::error::{Error, Result};
+use crate::keymap::Keymap;
+use crate::language::{Language, SaveHooks};
+use crate::term_color::Background;
+use crate::theme::ThemeDefinition;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Directory to put user configurations. $XDG_CONFIG_HOME/kiro or ~/.config/kiro
+pub fn config_dir() -> Option<PathBuf> {
+    let base = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(env::var_os("HOME")?).join(".config"),
+    };
+    Some(base.join("kiro"))
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct ConfigFile {
+    indent_style: Option<IndentStyle>,
+    indent_size: Option<usize>,
+    tab_width: Option<usize>,
+    trim_trailing_whitespace: Option<bool>,
+    trim_trailing_blank_lines: Option<bool>,
+    show_whitespace: bool,
+    color: Option<bool>,
+    mouse: Option<bool>,
+    background: Option<String>,
+    theme: Option<toml::Value>,
+    keymap: BTreeMap<String, String>,
+    formatter: BTreeMap<String, String>,
+    save_hooks: BTreeMap<String, SaveHooksConfig>,
+}
+
+// Per-language override of edits on save. None means the global config
+#[derive(Deserialize, Default, Debug, Clone, Copy)]
+#[serde(default, deny_unknown_fields)]
+pub struct SaveHooksConfig {
+    pub trim_trailing_whitespace: Option<bool>,
+    pub trim_trailing_blank_lines: Option<bool>,
+}
+
+pub struct Config {
+    // Properties of text buffers in the same format as .editorconfig. Properties in .editorconfig
+    // and indentation detected from file content take precedence
+    pub editing: EditorConfig,
+    // Show tabs and trailing spaces on startup
+    pub show_whitespace: bool,
+    // When false, screen is rendered without colors
+    pub color: bool,
+    // When false, mouse events are not reported so that terminal's text selection works
+    pub mouse: bool,
+    // When None, background is detected by querying to terminal
+    pub background: Option<Background>,
+    pub theme: Option<ThemeDefinition>,
+    pub keymap: Keymap,
+    // Shell commands to format buffer on save per language. Buffer is given via stdin and
+    // formatted text is read from stdout
+    pub formatters: Vec<(Language, String)>,
+    // Edits on save for all languages. They are disabled by default. .editorconfig and per-language
+    // config take precedence
+    pub trim_trailing_whitespace: Option<bool>,
+    pub trim_trailing_blank_lines: Option<bool>,
+    pub save_hooks: Vec<(Language, SaveHooksConfig)>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            editing: EditorConfig::default(),
+            show_whitespace: false,
+            color: true,
+            mouse: true,
+            background: None,
+            theme: None,
+            keymap: Keymap::default(),
+            formatters: vec![],
+            trim_trailing_whitespace: None,
+            trim_trailing_blank_lines: None,
+            save_hooks: vec![],
+        }
+    }
+}
+
+impl Config {
+    pub fn parse(path: &Path) -> Result<Config> {
+        let invalid = |msg: String| Error::InvalidConfigFile(path.to_path_buf(), msg);
+        let content = fs::read_to_string(path).map_err(|e| invalid(e.to_string()))?;
+        let file: ConfigFile = toml::from_str(&content).map_err(|e| invalid(e.to_string()))?;
+
+        for (name, width) in &[
+            ("indent_size", file.indent_size),
+            ("tab_width", file.tab_width),
+        ] {
+            if *width == Some(0) {
+                return Err(invalid(format!("'{}' must be greater than 0", name)));
+            }
+        }
+
+        let background = file
+            .background
+            .map(|bg| bg.parse())
+            .transpose()
+            .map_err(|e: Error| invalid(e.to_string()))?;
+        let theme = match file.theme {
+            // Theme file is put in 'themes' directory next to the config file
+            Some(toml::Value::String(name)) => {
+                let dir = path.parent().unwrap_or_else(|| Path::new("."));
+                let theme_path = dir.join("themes").join(format!("{}.toml", name));
+                if name.is_empty() || name.contains(['/', '\\']) || !theme_path.is_file() {
+                    return Err(invalid(format!(
+                        "Theme '{}' not found at {:?}",
+                        name, theme_path
+                    )));
+                }
+                Some(ThemeDefinition::parse(&theme_path)?)
+            }
+            Some(table) => Some(
+                ThemeDefinition::from_toml(table)
+                    .map_err(|msg| invalid(format!("{} in [theme]", msg)))?,
+            ),
+            None => None,
+        };
+        let keymap = Keymap::from_entries(&file.keymap)
+            .map_err(|msg| invalid(format!("{} in [keymap]", msg)))?;
+        let formatters = file
+            .formatter
+            .into_iter()
+            .map(|(name, cmd)| match Language::from_name(&name) {
+                Some(_) if cmd.trim().is_empty() => Err(invalid(format!(
+                    "Empty command for '{}' in [formatter]",
+                    name
+                ))),
+                Some(lang) => Ok((lang, cmd)),
+                None => Err(invalid(format!(
+                    "Unknown language '{}' in [formatter]",
+                    name
+                ))),
+            })
+            .collect::<Result<_>>()?;
+        let save_hooks = file
+            .save_hooks
+            .into_iter()
+            .map(|(name, hooks)| match Language::from_name(&name) {
+                Some(lang) => Ok((lang, hooks)),
+                None => Err(invalid(format!(
+                    "Unknown language '{}' in [save_hooks]",
+                    name
+                ))),
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Config {
+            editing: EditorConfig {
+                indent_style: file.indent_style,
+                indent_size: file.indent_size.map(IndentSize::Width),
+                tab_width: file.tab_width,
+                ..EditorConfig::default()
+            },
+            show_whitespace: file.show_whitespace,
+            color: file.color.unwrap_or(true),
+            mouse: file.mouse.unwrap_or(true),
+            background,
+            theme,
+            keymap,
+            formatters,
+            trim_trailing_whitespace: file.trim_trailing_whitespace,
+            trim_trailing_blank_lines: file.trim_trailing_blank_lines,
+            save_hooks,
+        })
+    }
+
+    pub fn formatter(&self, lang: Language) -> Option<&str> {
+        self.formatters
+            .iter()
+            .find(|(l, _)| *l == lang)
+            .map(|(_, cmd)| cmd.as_str())
+    }
+
+    pub fn save_hooks(&self, lang: Language) -> SaveHooks {
+        let (whitespace, blank_lines) = match self.save_hooks.iter().find(|(l, _)| *l == lang) {
+            Some((_, c)) => (c.trim_trailing_whitespace, c.trim_trailing_blank_lines),
+            None => (None, None),
+        };
+        SaveHooks {
+            trim_trailing_whitespace: whitespace
+                .or(self.trim_trailing_whitespace)
+                .unwrap_or(false),
+            trim_trailing_blank_lines: blank_lines
+                .or(self.trim_trailing_blank_lines)
+                .unwrap_or(false),
+        }
+    }
+
+    // Load config.toml in the config directory. Default config is used when the file does not exist
+    pub fn load_user() -> Result<Config> {
+        match config_dir().map(|d| d.join("config.toml")) {
+            Some(path) if path.is_file() => Self::parse(&path),
+            _ => Ok(Config::default()),
+        }
+    }
+}
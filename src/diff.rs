@@ -0,0 +1,136 @@
+// Compute line-wise difference between two texts to modify only changed lines. Common lines at
+// start and end are skipped first, then longest common subsequence of remaining lines is
+// calculated when it is not too large.
+use std::ops::Range;
+
+// Size of LCS table is limited not to consume too much memory. When remaining lines are larger,
+// they are replaced as one hunk
+const MAX_TABLE_SIZE: usize = 1_000_000;
+
+// Lines in range of old text are replaced with lines in range of new text
+#[derive(Debug, PartialEq)]
+pub struct Hunk {
+    pub old: Range<usize>,
+    pub new: Range<usize>,
+}
+
+fn common_subsequence<S: AsRef<str>, T: AsRef<str>>(old: &[S], new: &[T]) -> Vec<(usize, usize)> {
+    let (n, m) = (old.len(), new.len());
+    // table[i * (m + 1) + j] is length of LCS of old[i..] and new[j..]
+    let mut table = vec![0u32; (n + 1) * (m + 1)];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            table[i * (m + 1) + j] = if old[i].as_ref() == new[j].as_ref() {
+                table[(i + 1) * (m + 1) + j + 1] + 1
+            } else {
+                std::cmp::max(table[(i + 1) * (m + 1) + j], table[i * (m + 1) + j + 1])
+            };
+        }
+    }
+
+    let mut pairs = vec![];
+    let (mut i, mut j) = (0, 0);
+    while i < n && j < m {
+        if old[i].as_ref() == new[j].as_ref() {
+            pairs.push((i, j));
+            i += 1;
+            j += 1;
+        } else if table[(i + 1) * (m + 1) + j] >= table[i * (m + 1) + j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    pairs
+}
+
+// Hunks to change old lines into new lines. Hunks are sorted by position
+pub fn diff_lines<S: AsRef<str>, T: AsRef<str>>(old: &[S], new: &[T]) -> Vec<Hunk> {
+    let prefix = old
+        .iter()
+        .zip(new.iter())
+        .take_while(|(o, n)| o.as_ref() == n.as_ref())
+        .count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(o, n)| o.as_ref() == n.as_ref())
+        .count();
+    let (old_end, new_end) = (old.len() - suffix, new.len() - suffix);
+    let (old_mid, new_mid) = (&old[prefix..old_end], &new[prefix..new_end]);
+
+    let common = if old_mid.len().saturating_mul(new_mid.len()) <= MAX_TABLE_SIZE {
+        common_subsequence(old_mid, new_mid)
+    } else {
+        vec![]
+    };
+
+    // Lines between common lines are hunks
+    let mut hunks = vec![];
+    let (mut i, mut j) = (0, 0);
+    for (ci, cj) in common
+        .into_iter()
+        .chain(Some((old_mid.len(), new_mid.len())))
+    {
+        if i < ci || j < cj {
+            hunks.push(Hunk {
+                old: prefix + i..prefix + ci,
+                new: prefix + j..prefix + cj,
+            });
+        }
+        i = ci + 1;
+        j = cj + 1;
+    }
+    hunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hunk(old: Range<usize>, new: Range<usize>) -> Hunk {
+        Hunk { old, new }
+    }
+
+    #[test]
+    fn diff_same_lines() {
+        assert!(diff_lines(&["a", "b"], &["a", "b"]).is_empty());
+        assert!(diff_lines::<&str, &str>(&[], &[]).is_empty());
+    }
+
+    #[test]
+    fn diff_changed_lines() {
+        for (old, new, expected) in [
+            (
+                vec!["a", "b", "c"],
+                vec!["a", "x", "c"],
+                vec![hunk(1..2, 1..2)],
+            ),
+            (vec!["a", "c"], vec!["a", "b", "c"], vec![hunk(1..1, 1..2)]),
+            (vec!["a", "b", "c"], vec!["a", "c"], vec![hunk(1..2, 1..1)]),
+            (vec![], vec!["a"], vec![hunk(0..0, 0..1)]),
+            (vec!["a"], vec![], vec![hunk(0..1, 0..0)]),
+            (
+                vec!["a", "b", "c", "d", "e"],
+                vec!["x", "b", "c", "y", "y", "e"],
+                vec![hunk(0..1, 0..1), hunk(3..4, 3..5)],
+            ),
+            (
+                vec!["fn f() {", "foo();", "}", "fn g() {", "bar();", "}"],
+                vec![
+                    "fn f() {",
+                    "    foo();",
+                    "}",
+                    "",
+                    "fn g() {",
+                    "    bar();",
+                    "}",
+                ],
+                vec![hunk(1..2, 1..2), hunk(3..3, 3..4), hunk(4..5, 5..6)],
+            ),
+        ] {
+            assert_eq!(diff_lines(&old, &new), expected, "{:?} {:?}", old, new);
+        }
+    }
+}
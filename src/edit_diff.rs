@@ -1,4 +1,5 @@
 use crate::row::Row;
+use std::cmp;
 
 #[derive(Debug, Clone, Copy)]
 pub enum UndoRedo {
@@ -6,6 +7,40 @@ pub enum UndoRedo {
     Redo,
 }
 
+// Range of lines modified by diffs. Lines after the range are not modified though they may be
+// shifted by inserted or deleted lines
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DirtyLines {
+    pub start: usize,
+    pub end: usize, // Inclusive
+}
+
+impl DirtyLines {
+    pub fn new(start: usize, end: usize) -> Self {
+        Self { start, end }
+    }
+
+    // Extend the range with a diff applied at line y. delta is the number of lines inserted (positive)
+    // or deleted (negative) by the diff. Lines after y are shifted by the delta.
+    pub fn extend(range: Option<Self>, y: usize, delta: isize) -> Self {
+        let mut range = range.unwrap_or_else(|| Self::new(y, y));
+        if range.end > y || delta > 0 && range.end == y {
+            range.end = range.end.saturating_add_signed(delta);
+        }
+        range.start = cmp::min(range.start, y);
+        range.end = cmp::max(range.end, y);
+        range
+    }
+}
+
+// Cursor position after removing line y. It is the top of buffer when the first line was removed
+fn end_of_previous_line(rows: &[Row], y: usize) -> (usize, usize) {
+    match y.checked_sub(1) {
+        Some(y) => (rows[y].len(), y),
+        None => (0, 0),
+    }
+}
+
 #[derive(Debug)]
 pub enum EditDiff {
     InsertChar(usize, usize, char),
@@ -20,8 +55,8 @@ pub enum EditDiff {
 }
 
 impl EditDiff {
-    pub fn apply(&self, rows: &mut Vec<Row>, which: UndoRedo) -> (usize, usize) {
-        // Returns cursor's next position (x, y)
+    pub fn apply(&self, rows: &mut Vec<Row>, which: UndoRedo, tab_stop: usize) -> (usize, usize) {
+        // Returns cursor's next position (x, y). tab_stop is used for rows newly created
         use UndoRedo::*;
         match *self {
             EditDiff::InsertChar(x, y, c) => match which {
@@ -94,7 +129,7 @@ impl EditDiff {
             },
             EditDiff::Newline => match which {
                 Redo => {
-                    rows.push(Row::empty());
+                    rows.push(Row::empty(tab_stop));
                     (0, rows.len() - 1)
                 }
                 Undo => {
@@ -105,12 +140,12 @@ impl EditDiff {
             },
             EditDiff::InsertLine(y, ref s) => match which {
                 Redo => {
-                    rows.insert(y, Row::new(s).unwrap());
+                    rows.insert(y, Row::new(s, tab_stop).unwrap());
                     (0, y)
                 }
                 Undo => {
                     rows.remove(y);
-                    (rows[y - 1].len(), y - 1)
+                    end_of_previous_line(rows, y)
                 }
             },
             EditDiff::DeleteLine(y, ref s) => match which {
@@ -120,13 +155,13 @@ impl EditDiff {
                     } else {
                         rows.remove(y);
                     }
-                    (rows[y - 1].len(), y - 1)
+                    end_of_previous_line(rows, y)
                 }
                 Undo => {
                     if y == rows.len() {
-                        rows.push(Row::new(s).unwrap());
+                        rows.push(Row::new(s, tab_stop).unwrap());
                     } else {
-                        rows.insert(y, Row::new(s).unwrap());
+                        rows.insert(y, Row::new(s, tab_stop).unwrap());
                     }
                     (0, y)
                 }
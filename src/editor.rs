@@ -1,12 +1,21 @@
+use crate::config::Config;
 use crate::error::Result;
-use crate::highlight::Highlighting;
-use crate::input::{InputSeq, KeySeq};
+use crate::fuzzy;
+use crate::grep;
+use crate::highlight::{Highlighting, MAX_BRACKET_SEARCH_LINES};
+use crate::input::{self, InputSeq, KeySeq};
+use crate::keymap::{self, Command, KeyLookup};
 use crate::language::Language;
 use crate::prompt::{self, Prompt, PromptResult};
+use crate::row::Row;
 use crate::screen::Screen;
+use crate::shell;
+use crate::signal;
 use crate::status_bar::StatusBar;
 use crate::text_buffer::{CursorDir, Lines, TextBuffer};
+use std::cmp;
 use std::io::Write;
+use std::mem;
 use std::path::Path;
 
 enum EditStep {
@@ -31,6 +40,8 @@ pub struct Editor<I: Iterator<Item = Result<InputSeq>>, W: Write> {
     bufs: Vec<TextBuffer>,
     buf_idx: usize,
     status_bar: StatusBar,
+    config: Config,
+    pending_keys: Vec<InputSeq>, // Keys input so far while typing a chord
 }
 
 impl<I, W> Editor<I, W>
@@ -43,8 +54,9 @@ where
         mut input: I,
         output: W,
         window_size: Option<(usize, usize)>,
+        config: Config,
     ) -> Result<Editor<I, W>> {
-        let screen = Screen::new(window_size, &mut input, output)?;
+        let screen = Screen::new(window_size, &mut input, output, &config)?;
         let status_bar = StatusBar::from_buffer(&buf, (1, 1));
         Ok(Editor {
             input,
@@ -54,11 +66,19 @@ where
             bufs: vec![buf],
             buf_idx: 0,
             status_bar,
+            config,
+            pending_keys: vec![],
         })
     }
 
     pub fn new(input: I, output: W, window_size: Option<(usize, usize)>) -> Result<Editor<I, W>> {
-        Self::with_buf(TextBuffer::empty(), input, output, window_size)
+        Self::with_buf(
+            TextBuffer::empty(),
+            input,
+            output,
+            window_size,
+            Config::default(),
+        )
     }
 
     pub fn with_lines<S: AsRef<str>, L: Iterator<Item = S>>(
@@ -67,7 +87,8 @@ where
         output: W,
         window_size: Option<(usize, usize)>,
     ) -> Result<Editor<I, W>> {
-        Self::with_buf(TextBuffer::with_lines(lines)?, input, output, window_size)
+        let buf = TextBuffer::with_lines(lines)?;
+        Self::with_buf(buf, input, output, window_size, Config::default())
     }
 
     pub fn open<P: AsRef<Path>>(
@@ -75,12 +96,17 @@ where
         output: W,
         window_size: Option<(usize, usize)>,
         paths: &[P],
+        config: Config,
     ) -> Result<Editor<I, W>> {
         if paths.is_empty() {
-            return Self::new(input, output, window_size);
+            let buf = TextBuffer::with_config(&config);
+            return Self::with_buf(buf, input, output, window_size, config);
         }
-        let screen = Screen::new(window_size, &mut input, output)?;
-        let bufs: Vec<_> = paths.iter().map(TextBuffer::open).collect::<Result<_>>()?;
+        let screen = Screen::new(window_size, &mut input, output, &config)?;
+        let bufs: Vec<_> = paths
+            .iter()
+            .map(|p| TextBuffer::open(p, &config))
+            .collect::<Result<_>>()?;
         let hl = Highlighting::new(bufs[0].lang(), bufs[0].rows());
         let status_bar = StatusBar::from_buffer(&bufs[0], (1, bufs.len()));
         Ok(Editor {
@@ -91,6 +117,8 @@ where
             bufs,
             buf_idx: 0,
             status_bar,
+            config,
+            pending_keys: vec![],
         })
     }
 
@@ -128,24 +156,194 @@ where
         self.status_bar.redraw = true;
     }
 
-    fn open_buffer(&mut self) -> Result<()> {
-        if let PromptResult::Input(input) = self.prompt::<prompt::NoAction>(
-            "Open: {} (Empty name for new text buffer, ^G or ESC to cancel)",
-            false,
-        )? {
+    fn open_buffer(&mut self, arg: Option<String>) -> Result<()> {
+        let result = match arg {
+            Some(path) => PromptResult::Input(path),
+            None => self.prompt::<prompt::PathComplete>(
+                "Open: {} (Empty name for new text buffer, Tab to complete, ^G or ESC to cancel)",
+                false,
+            )?,
+        };
+        if let PromptResult::Input(input) = result {
             let buf = if input.is_empty() {
-                TextBuffer::empty()
+                TextBuffer::with_config(&self.config)
             } else {
-                TextBuffer::open(input)?
+                TextBuffer::open(prompt::expand_home(&input), &self.config)?
             };
-            self.hl = Highlighting::new(buf.lang(), buf.rows());
-            self.bufs.push(buf);
-            self.buf_idx = self.bufs.len() - 1;
-            self.will_reset_scroll();
+            self.push_buffer(buf);
+        }
+        Ok(())
+    }
+
+    fn find_file(&mut self) -> Result<()> {
+        let template = "Find file: {} (UP/DOWN or ^P/^N to select, ^G or ESC to cancel)";
+        if let PromptResult::Input(path) = self.prompt::<prompt::FileFinder>(template, false)? {
+            match TextBuffer::open(&path, &self.config) {
+                Ok(buf) => self.push_buffer(buf),
+                Err(err) => self
+                    .screen
+                    .set_error_message(format!("Could not open {:?}: {}", path, err)),
+            }
+        }
+        Ok(())
+    }
+
+    fn grep(&mut self, arg: Option<String>) -> Result<()> {
+        let template = "Grep: {} (/regex/ for regular expression, ^G or ESC to cancel)";
+        let input = match arg {
+            Some(input) => input,
+            None => match self.prompt::<prompt::NoAction>(template, true)? {
+                PromptResult::Input(input) => input,
+                PromptResult::Canceled => return Ok(()),
+            },
+        };
+        let pattern = match grep::Pattern::parse(&input) {
+            Ok(pattern) => pattern,
+            Err(msg) => {
+                self.screen.set_error_message(msg);
+                return Ok(());
+            }
+        };
+
+        let matches = grep::search(".", &pattern);
+        if matches.is_empty() {
+            self.screen
+                .set_info_message(format!("No match found for {:?}", input));
+            return Ok(());
+        }
+        let name = format!("[grep: {}]", input);
+        let buf = TextBuffer::read_only(&name, matches.iter())?;
+        self.push_buffer(buf);
+        self.screen.set_info_message(format!(
+            "{} matches found (ENTER to open the location)",
+            matches.len()
+        ));
+        Ok(())
+    }
+
+    // Open the file at 'path:line:' location of the current line in grep results
+    fn open_location(&mut self) -> Result<()> {
+        let location = self
+            .buf()
+            .rows()
+            .get(self.buf().cy())
+            .and_then(|row| grep::parse_location(row.buffer()));
+        let (path, line) = match location {
+            Some((path, line)) => (path.to_string(), line),
+            None => {
+                self.screen.set_error_message("No location at the line");
+                return Ok(());
+            }
+        };
+        match self.bufs.iter().position(|b| b.filename() == path) {
+            Some(idx) => self.switch_buffer(idx),
+            None => match TextBuffer::open(&path, &self.config) {
+                Ok(buf) => self.push_buffer(buf),
+                Err(err) => {
+                    self.screen
+                        .set_error_message(format!("Could not open {:?}: {}", path, err));
+                    return Ok(());
+                }
+            },
+        }
+        let y = cmp::min(line, self.buf().rows().len()).saturating_sub(1);
+        self.buf_mut().set_cursor(0, y);
+        Ok(())
+    }
+
+    fn prompt_shell_command(
+        &mut self,
+        template: &str,
+        arg: Option<String>,
+    ) -> Result<Option<String>> {
+        match arg {
+            Some(cmd) => Ok(Some(cmd)),
+            None => match self.prompt::<prompt::NoAction>(template, true)? {
+                PromptResult::Input(cmd) => Ok(Some(cmd)),
+                PromptResult::Canceled => Ok(None),
+            },
+        }
+    }
+
+    // Run shell command and show its error in message bar. Returns its stdout on success
+    fn run_shell_command(&mut self, cmd: &str, input: Option<String>) -> Option<String> {
+        let output = match shell::run(cmd, input) {
+            Ok(output) => output,
+            Err(err) => {
+                self.screen
+                    .set_error_message(format!("Could not run {:?}: {}", cmd, err));
+                return None;
+            }
+        };
+        if let Some(msg) = output.error_message() {
+            self.screen.set_error_message(msg);
+            return None;
+        }
+        if let Some(line) = output.stderr_line() {
+            self.screen.set_error_message(line);
+        }
+        Some(output.stdout)
+    }
+
+    fn shell_command(&mut self, arg: Option<String>) -> Result<()> {
+        let template = "Shell command: {} (Output is inserted at cursor, ^G or ESC to cancel)";
+        let cmd = match self.prompt_shell_command(template, arg)? {
+            Some(cmd) => cmd,
+            None => return Ok(()),
+        };
+        let stdout = match self.run_shell_command(&cmd, None) {
+            Some(stdout) => stdout.replace("\r\n", "\n"),
+            None => return Ok(()),
+        };
+        let text = stdout.strip_suffix('\n').unwrap_or(&stdout);
+        if let Err(err) = self.buf_mut().insert_text(text) {
+            self.screen.set_error_message(err.to_string());
         }
         Ok(())
     }
 
+    fn filter_command(&mut self, arg: Option<String>) -> Result<()> {
+        let template = "Filter: {} (Whole buffer or lines by '.' or 'START,END' before command, ^G or ESC to cancel)";
+        let input = match self.prompt_shell_command(template, arg)? {
+            Some(input) => input,
+            None => return Ok(()),
+        };
+
+        let len = self.buf().rows().len();
+        let (start, end, cmd) = match shell::parse_range(&input, self.buf().cy()) {
+            (Some((start, end)), cmd) if end <= len => (start, end, cmd),
+            (Some(_), _) => {
+                self.screen
+                    .set_error_message(format!("Line range is out of buffer with {} lines", len));
+                return Ok(());
+            }
+            (None, cmd) => (0, len, cmd),
+        };
+
+        let mut text = String::new();
+        for line in self.buf().lines().skip(start).take(end - start) {
+            text.push_str(line);
+            text.push('\n');
+        }
+        if let Some(stdout) = self.run_shell_command(cmd, Some(text)) {
+            let mut lines: Vec<_> = stdout.lines().collect();
+            if lines.is_empty() && end - start == len {
+                lines.push(""); // Keep at least one line in buffer
+            }
+            if let Err(err) = self.buf_mut().replace_lines(start, end, &lines) {
+                self.screen.set_error_message(err.to_string());
+            }
+        }
+        Ok(())
+    }
+
+    fn push_buffer(&mut self, buf: TextBuffer) {
+        self.hl = Highlighting::new(buf.lang(), buf.rows());
+        self.bufs.push(buf);
+        self.buf_idx = self.bufs.len() - 1;
+        self.will_reset_scroll();
+    }
+
     fn switch_buffer(&mut self, idx: usize) {
         let len = self.bufs.len();
         if len == 1 {
@@ -194,13 +392,49 @@ where
         .run::<A, _, _>(prompt, &mut self.input)
     }
 
-    fn save(&mut self) -> Result<()> {
+    fn select_language(&mut self, arg: Option<String>) -> Result<()> {
+        let template = "Language: {} (Tab to complete, ^G or ESC to cancel)";
+        let result = match arg {
+            Some(name) => PromptResult::Input(name),
+            None => self.prompt::<prompt::LanguageSelect>(template, true)?,
+        };
+        if let PromptResult::Input(input) = result {
+            match Language::from_name(input.trim()) {
+                Some(lang) => {
+                    self.set_lang(lang);
+                    self.screen
+                        .set_info_message(format!("Language was set to {}", lang.name()));
+                }
+                None => self
+                    .screen
+                    .set_error_message(format!("Unknown language {:?}", input.trim())),
+            }
+        }
+        Ok(())
+    }
+
+    fn save(&mut self, arg: Option<String>) -> Result<()> {
+        if self.buf().is_read_only() {
+            self.screen.set_error_message("Buffer is read-only");
+            return Ok(());
+        }
+
         let mut create = false;
-        if !self.buf().has_file() {
-            let template = "Save as: {} (^G or ESC to cancel)";
-            if let PromptResult::Input(input) = self.prompt::<prompt::NoAction>(template, true)? {
+        // File name before saving as another file. It is restored when saving failed
+        let prev_file = if self.buf().has_file() {
+            Some(self.buf().filename().to_string())
+        } else {
+            None
+        };
+        if prev_file.is_none() || arg.is_some() {
+            let template = "Save as: {} (Tab to complete, ^G or ESC to cancel)";
+            let result = match arg {
+                Some(path) => PromptResult::Input(path),
+                None => self.prompt::<prompt::PathComplete>(template, true)?,
+            };
+            if let PromptResult::Input(input) = result {
                 let prev_lang = self.buf().lang();
-                self.buf_mut().set_file(input);
+                self.buf_mut().set_file(prompt::expand_home(&input));
                 self.hl.lang_changed(self.buf().lang());
                 if prev_lang != self.buf().lang() {
                     // Render entire screen since highglight updated
@@ -210,12 +444,26 @@ where
             }
         }
 
-        match self.buf_mut().save() {
-            Ok(msg) => self.screen.set_info_message(msg),
-            Err(msg) => {
+        // Buffer is not modified when formatting failed but it is still saved
+        let formatter = self.config.formatter(self.buf().lang()).map(str::to_owned);
+        let format_error = match formatter {
+            Some(cmd) if self.buf().has_file() => self.format_buffer(&cmd).err(),
+            _ => None,
+        };
+
+        let hooks = self.config.save_hooks(self.buf().lang());
+        match (self.buf_mut().save(hooks), format_error) {
+            (Ok(msg), Some(err)) => self
+                .screen
+                .set_error_message(format!("{} but could not format: {}", msg, err)),
+            (Ok(msg), None) => self.screen.set_info_message(msg),
+            (Err(msg), _) => {
                 self.screen.set_error_message(msg);
                 if create {
-                    self.buf_mut().set_unnamed();
+                    match prev_file {
+                        Some(file) => self.buf_mut().set_file(file),
+                        None => self.buf_mut().set_unnamed(),
+                    }
                 }
             }
         }
@@ -223,14 +471,95 @@ where
         Ok(())
     }
 
+    // Pipe the buffer through the formatter command and apply changes in the output
+    fn format_buffer(&mut self, cmd: &str) -> std::result::Result<(), String> {
+        let mut input = String::new();
+        for line in self.buf().lines() {
+            input.push_str(line);
+            input.push('\n');
+        }
+        let output = shell::run(cmd, Some(input)).map_err(|e| format!("{:?}: {}", cmd, e))?;
+        if let Some(msg) = output.error_message() {
+            return Err(msg);
+        }
+        let lines: Vec<_> = output.stdout.lines().collect();
+        if lines.is_empty() && self.buf().lines().any(|l| !l.is_empty()) {
+            return Err(format!("{:?} output nothing", cmd));
+        }
+        self.buf_mut()
+            .update_lines(&lines)
+            .map_err(|e| e.to_string())
+    }
+
     fn find(&mut self) -> Result<()> {
         let template = "Search: {} (^F or ^N or RIGHT to forward, ^B or ^P or LEFT to back, ^G or ESC to cancel)";
         self.prompt::<prompt::TextSearch>(template, true)?;
         Ok(())
     }
 
+    fn goto_line(&mut self, arg: Option<String>) -> Result<()> {
+        let template = "Go to line: {} (^G or ESC to cancel)";
+        let result = match arg {
+            Some(line) => PromptResult::Input(line),
+            None => self.prompt::<prompt::NoAction>(template, true)?,
+        };
+        if let PromptResult::Input(input) = result {
+            match input.trim().parse::<usize>() {
+                Ok(line) if line > 0 => {
+                    let y = cmp::min(line, self.buf().rows().len()).saturating_sub(1);
+                    self.buf_mut().set_cursor(0, y);
+                }
+                _ => self
+                    .screen
+                    .set_error_message(format!("Invalid line number {:?}", input.trim())),
+            }
+        }
+        Ok(())
+    }
+
+    // Read command name and its optional argument such as "open foo.txt" from prompt. The best
+    // fuzzy match is used when the name is not exact
+    fn prompt_command(&mut self) -> Result<Option<(Command, Option<String>)>> {
+        let template = "Command: {} (Tab to complete, ^G or ESC to cancel)";
+        let input = match self.prompt::<prompt::CommandSelect>(template, true)? {
+            PromptResult::Input(input) => input,
+            PromptResult::Canceled => return Ok(None),
+        };
+
+        let input = input.trim();
+        let (name, arg) = match input.split_once(' ') {
+            Some((name, arg)) => (name, Some(arg.trim().to_string())),
+            None => (input, None),
+        };
+        let cmd = Command::from_name(name).or_else(|| {
+            let names = Command::all()
+                .filter(|c| *c != Command::Palette)
+                .map(Command::name);
+            fuzzy::filter(name, names)
+                .first()
+                .and_then(|n| Command::from_name(n))
+        });
+
+        match cmd {
+            None => {
+                self.screen
+                    .set_error_message(format!("Unknown command {:?}", name));
+                Ok(None)
+            }
+            Some(cmd) if arg.is_some() && !cmd.takes_argument() => {
+                self.screen.set_error_message(format!(
+                    "Command '{}' does not take an argument",
+                    cmd.name()
+                ));
+                Ok(None)
+            }
+            Some(cmd) => Ok(Some((cmd, arg))),
+        }
+    }
+
     fn show_help(&mut self) -> Result<()> {
-        self.screen.render_help()?;
+        let help = self.config.keymap.help();
+        self.screen.render_help(&help)?;
 
         // This `while` loop cannot be replaced with `for seq in &mut self.input` since loop body
         // borrows self.input.
@@ -238,7 +567,7 @@ where
         while let Some(seq) = self.input.next() {
             // Consume any key
             if self.screen.maybe_resize(&mut self.input)? {
-                self.screen.render_help()?;
+                self.screen.render_help(&help)?;
                 self.status_bar.redraw = true;
             }
             if seq?.key != KeySeq::Unidentified {
@@ -251,6 +580,54 @@ where
         Ok(())
     }
 
+    // Stop the process to go back to shell. Terminal is restored while suspended and the screen is
+    // redrawn after the process is continued
+    fn suspend(&mut self) -> Result<()> {
+        if !input::raw_mode_enabled() {
+            self.screen
+                .set_error_message("Cannot suspend since terminal is not in raw mode");
+            return Ok(());
+        }
+        self.screen.suspend()?;
+        input::without_raw_mode(signal::suspend)??;
+        self.screen.resume(&mut self.input)?;
+        self.will_reset_screen();
+        Ok(())
+    }
+
+    fn toggle_whitespace(&mut self) {
+        let show = !self.screen.show_whitespace();
+        self.screen.set_show_whitespace(show);
+        let msg = if show {
+            "Whitespaces are visible"
+        } else {
+            "Whitespaces are hidden"
+        };
+        self.screen.set_info_message(msg);
+    }
+
+    fn update_bracket_highlight(&mut self) {
+        // Brackets outside the screen are not highlighted. This runs on every keypress
+        let buf = &self.bufs[self.buf_idx];
+        let lines = self.screen.visible_rows(buf.cy());
+        let pair = self.hl.find_bracket_pair(buf.rows(), buf.cursor(), lines);
+        for y in self.hl.set_bracket(pair) {
+            self.screen.set_dirty_line(y);
+        }
+    }
+
+    fn jump_to_matching_bracket(&mut self) {
+        let buf = &self.bufs[self.buf_idx];
+        let cy = buf.cy();
+        let lines = cy.saturating_sub(MAX_BRACKET_SEARCH_LINES)..cy + MAX_BRACKET_SEARCH_LINES;
+        if let Some((_, (x, y))) = self.hl.find_bracket_pair(buf.rows(), buf.cursor(), lines) {
+            let cx = buf.rows()[y].cx_from_render_idx(x);
+            self.buf_mut().set_cursor(cx, y);
+        } else {
+            self.screen.set_error_message("No matching bracket");
+        }
+    }
+
     fn handle_quit(&mut self, s: InputSeq) -> EditStep {
         let modified = self.bufs.iter().any(|b| b.modified());
         if !modified || self.quitting {
@@ -264,111 +641,215 @@ where
         }
     }
 
-    fn handle_not_mapped(&mut self, seq: &InputSeq) {
+    fn insert_char(&mut self, c: char) {
+        if self.buf().is_read_only() {
+            self.screen.set_error_message("Buffer is read-only");
+        } else {
+            self.buf_mut().insert_char(c);
+        }
+    }
+
+    fn handle_not_mapped(&mut self, keys: &[InputSeq]) {
+        let keys = keymap::format_keys(keys);
         self.screen
-            .set_error_message(format!("Key '{}' not mapped", seq));
+            .set_error_message(format!("Key '{}' not mapped", keys));
     }
 
-    fn process_keypress(&mut self, s: InputSeq) -> Result<EditStep> {
-        use KeySeq::*;
+    fn click(&mut self, x: usize, y: usize) {
+        if y >= self.screen.rows() {
+            return; // Status bar or message bar
+        }
+        let (rowoff, coloff) = (self.screen.rowoff, self.screen.coloff);
+        let buf = self.buf_mut();
+        let cy = cmp::min(rowoff + y, buf.rows().len());
+        let cx = buf
+            .rows()
+            .get(cy)
+            .map(|r| r.cx_from_rx(coloff + x))
+            .unwrap_or(0);
+        buf.set_cursor(cx, cy);
+    }
+
+    fn scroll_by_wheel(&mut self, dir: CursorDir) {
+        const WHEEL_LINES: usize = 3;
+        let prev = self.screen.rowoff;
+        let rowoff = match dir {
+            CursorDir::Up => prev.saturating_sub(WHEEL_LINES),
+            CursorDir::Down => {
+                let last = self.buf().rows().len().saturating_sub(1);
+                cmp::min(prev + WHEEL_LINES, last) // Last line remains on screen
+            }
+            _ => unreachable!(),
+        };
+        if rowoff == prev {
+            return;
+        }
+        self.screen.rowoff = rowoff;
+        self.screen.set_dirty_start(rowoff);
+
+        // Keep cursor in screen. Otherwise the screen scrolls back to the cursor on rendering
+        let bottom = rowoff + self.screen.rows() - 1;
+        let buf = self.buf_mut();
+        let (cx, cy) = buf.cursor();
+        let cy = cmp::max(cmp::min(cy, bottom), rowoff);
+        let cx = cmp::min(cx, buf.rows().get(cy).map(Row::len).unwrap_or(0));
+        buf.set_cursor(cx, cy);
+    }
+
+    // Argument is given from command palette. Commands which need input ask it with prompt when
+    // no argument is given
+    fn execute(&mut self, cmd: Command, arg: Option<String>) -> Result<()> {
+        use Command::*;
 
         let rowoff = self.screen.rowoff;
         let rows = self.screen.rows();
+
+        if self.buf().is_read_only() && cmd.edits_text() {
+            if cmd == NewLine {
+                return self.open_location();
+            }
+            self.screen.set_error_message("Buffer is read-only");
+            return Ok(());
+        }
+
+        match cmd {
+            Quit => unreachable!(), // Handled by handle_quit()
+            Save => self.save(arg)?,
+            Open => self.open_buffer(arg)?,
+            NextBuffer => self.next_buffer(),
+            PreviousBuffer => self.previous_buffer(),
+            FindFile => self.find_file()?,
+            Grep => self.grep(arg)?,
+            CursorUp => self.buf_mut().move_cursor_one(CursorDir::Up),
+            CursorDown => self.buf_mut().move_cursor_one(CursorDir::Down),
+            CursorRight => self.buf_mut().move_cursor_one(CursorDir::Right),
+            CursorLeft => self.buf_mut().move_cursor_one(CursorDir::Left),
+            HeadOfLine => self.buf_mut().move_cursor_to_buffer_edge(CursorDir::Left),
+            EndOfLine => self.buf_mut().move_cursor_to_buffer_edge(CursorDir::Right),
+            NextPage => self
+                .buf_mut()
+                .move_cursor_page(CursorDir::Down, rowoff, rows),
+            PreviousPage => self.buf_mut().move_cursor_page(CursorDir::Up, rowoff, rows),
+            NextWord => self.buf_mut().move_cursor_by_word(CursorDir::Right),
+            PreviousWord => self.buf_mut().move_cursor_by_word(CursorDir::Left),
+            NextParagraph => self.buf_mut().move_cursor_paragraph(CursorDir::Down),
+            PreviousParagraph => self.buf_mut().move_cursor_paragraph(CursorDir::Up),
+            TopOfBuffer => self.buf_mut().move_cursor_to_buffer_edge(CursorDir::Up),
+            BottomOfBuffer => self.buf_mut().move_cursor_to_buffer_edge(CursorDir::Down),
+            MatchingBracket => self.jump_to_matching_bracket(),
+            DeleteChar => self.buf_mut().delete_char(),
+            DeleteNextChar => self.buf_mut().delete_right_char(),
+            DeleteWord => self.buf_mut().delete_word(),
+            DeleteToHeadOfLine => self.buf_mut().delete_until_head_of_line(),
+            DeleteToEndOfLine => self.buf_mut().delete_until_end_of_line(),
+            Undo => {
+                if !self.buf_mut().undo() {
+                    self.screen.set_info_message("No older change");
+                }
+            }
+            Redo => {
+                if !self.buf_mut().redo() {
+                    self.screen.set_info_message("Buffer is already newest");
+                }
+            }
+            Search => self.find()?,
+            NewLine => self.buf_mut().insert_line(),
+            InsertTab => self.buf_mut().insert_tab(),
+            Shell => self.shell_command(arg)?,
+            Filter => self.filter_command(arg)?,
+            Refresh => {
+                self.screen.set_dirty_start(self.screen.rowoff); // Clear
+                self.screen.unset_message();
+                self.status_bar.redraw = true;
+            }
+            Suspend => self.suspend()?,
+            ToggleWhitespace => self.toggle_whitespace(),
+            SelectLanguage => self.select_language(arg)?,
+            GotoLine => self.goto_line(arg)?,
+            Palette => unreachable!(), // Handled by prompt_command()
+            Help => self.show_help()?,
+        }
+
+        Ok(())
+    }
+
+    fn process_keypress(&mut self, s: InputSeq) -> Result<EditStep> {
+        use KeySeq::*;
+
         let prev_cursor = self.buf().cursor();
 
         match &s {
             InputSeq {
                 key: Unidentified, ..
             } => return Ok(EditStep::Continue(s)),
-            InputSeq { key, alt: true, .. } => match key {
-                Key(b'v') => self.buf_mut().move_cursor_page(CursorDir::Up, rowoff, rows),
-                Key(b'f') => self.buf_mut().move_cursor_by_word(CursorDir::Right),
-                Key(b'b') => self.buf_mut().move_cursor_by_word(CursorDir::Left),
-                Key(b'n') => self.buf_mut().move_cursor_paragraph(CursorDir::Down),
-                Key(b'p') => self.buf_mut().move_cursor_paragraph(CursorDir::Up),
-                Key(b'x') => self.previous_buffer(),
-                Key(b'<') => self.buf_mut().move_cursor_to_buffer_edge(CursorDir::Up),
-                Key(b'>') => self.buf_mut().move_cursor_to_buffer_edge(CursorDir::Down),
-                LeftKey => self.buf_mut().move_cursor_to_buffer_edge(CursorDir::Left),
-                RightKey => self.buf_mut().move_cursor_to_buffer_edge(CursorDir::Right),
-                _ => self.handle_not_mapped(&s),
-            },
             InputSeq {
-                key, ctrl: true, ..
-            } => match key {
-                Key(b'p') => self.buf_mut().move_cursor_one(CursorDir::Up),
-                Key(b'b') => self.buf_mut().move_cursor_one(CursorDir::Left),
-                Key(b'n') => self.buf_mut().move_cursor_one(CursorDir::Down),
-                Key(b'f') => self.buf_mut().move_cursor_one(CursorDir::Right),
-                Key(b'v') => self
-                    .buf_mut()
-                    .move_cursor_page(CursorDir::Down, rowoff, rows),
-                Key(b'a') => self.buf_mut().move_cursor_to_buffer_edge(CursorDir::Left),
-                Key(b'e') => self.buf_mut().move_cursor_to_buffer_edge(CursorDir::Right),
-                Key(b'd') => self.buf_mut().delete_right_char(),
-                Key(b'g') => self.find()?,
-                Key(b'h') => self.buf_mut().delete_char(),
-                Key(b'k') => self.buf_mut().delete_until_end_of_line(),
-                Key(b'j') => self.buf_mut().delete_until_head_of_line(),
-                Key(b'w') => self.buf_mut().delete_word(),
-                Key(b'l') => {
-                    self.screen.set_dirty_start(self.screen.rowoff); // Clear
-                    self.screen.unset_message();
-                    self.status_bar.redraw = true;
-                }
-                Key(b's') => self.save()?,
-                Key(b'i') => self.buf_mut().insert_tab(),
-                Key(b'm') => self.buf_mut().insert_line(),
-                Key(b'o') => self.open_buffer()?,
-                Key(b'?') => self.show_help()?,
-                Key(b'x') => self.next_buffer(),
-                Key(b']') => self
-                    .buf_mut()
-                    .move_cursor_page(CursorDir::Down, rowoff, rows),
-                Key(b'u') => {
-                    if !self.buf_mut().undo() {
-                        self.screen.set_info_message("No older change");
+                key: MouseClick(x, y),
+                ..
+            } => self.click(*x, *y),
+            InputSeq { key: WheelUp, .. } => self.scroll_by_wheel(CursorDir::Up),
+            InputSeq { key: WheelDown, .. } => self.scroll_by_wheel(CursorDir::Down),
+            InputSeq {
+                key: Cursor(..), ..
+            }
+            | InputSeq {
+                key: BackgroundColor(..),
+                ..
+            }
+            | InputSeq {
+                key: DeviceAttributes,
+                ..
+            } => return Ok(EditStep::Continue(s)), // Responses from terminal
+            _ => {
+                self.pending_keys.push(s.clone());
+                match self.config.keymap.lookup(&self.pending_keys) {
+                    KeyLookup::Command(cmd) => {
+                        self.pending_keys.clear();
+                        let cmd = if cmd == Command::Palette {
+                            self.prompt_command()?
+                        } else {
+                            Some((cmd, None))
+                        };
+                        match cmd {
+                            Some((Command::Quit, _)) => return Ok(self.handle_quit(s)),
+                            Some((cmd, arg)) => self.execute(cmd, arg)?,
+                            None => {}
+                        }
                     }
-                }
-                Key(b'r') => {
-                    if !self.buf_mut().redo() {
-                        self.screen.set_info_message("Buffer is already newest");
+                    KeyLookup::Prefix => {
+                        // Wait for next key of chord
+                        let msg = format!("{} -", keymap::format_keys(&self.pending_keys));
+                        self.screen.set_info_message(msg);
+                        return Ok(EditStep::Continue(s));
+                    }
+                    KeyLookup::NotFound => {
+                        let keys = mem::take(&mut self.pending_keys);
+                        match &s {
+                            InputSeq {
+                                key: Key(b),
+                                ctrl: false,
+                                alt: false,
+                                ..
+                            } if keys.len() == 1 && !b.is_ascii_control() => {
+                                self.insert_char(*b as char)
+                            }
+                            InputSeq {
+                                key: Utf8Key(c),
+                                ctrl: false,
+                                alt: false,
+                                ..
+                            } if keys.len() == 1 => self.insert_char(*c),
+                            _ => self.handle_not_mapped(&keys),
+                        }
                     }
                 }
-                LeftKey => self.buf_mut().move_cursor_by_word(CursorDir::Left),
-                RightKey => self.buf_mut().move_cursor_by_word(CursorDir::Right),
-                DownKey => self.buf_mut().move_cursor_paragraph(CursorDir::Down),
-                UpKey => self.buf_mut().move_cursor_paragraph(CursorDir::Up),
-                Key(b'q') => return Ok(self.handle_quit(s)),
-                _ => self.handle_not_mapped(&s),
-            },
-            InputSeq { key, .. } => match key {
-                Key(0x1b) => self.buf_mut().move_cursor_page(CursorDir::Up, rowoff, rows), // Clash with Ctrl-[
-                Key(0x08) => self.buf_mut().delete_char(), // Backspace
-                Key(0x7f) => self.buf_mut().delete_char(), // Delete key is mapped to \x1b[3~
-                Key(b'\r') => self.buf_mut().insert_line(),
-                Key(b) if !b.is_ascii_control() => self.buf_mut().insert_char(*b as char),
-                Utf8Key(c) => self.buf_mut().insert_char(*c),
-                UpKey => self.buf_mut().move_cursor_one(CursorDir::Up),
-                LeftKey => self.buf_mut().move_cursor_one(CursorDir::Left),
-                DownKey => self.buf_mut().move_cursor_one(CursorDir::Down),
-                RightKey => self.buf_mut().move_cursor_one(CursorDir::Right),
-                PageUpKey => self.buf_mut().move_cursor_page(CursorDir::Up, rowoff, rows),
-                PageDownKey => self
-                    .buf_mut()
-                    .move_cursor_page(CursorDir::Down, rowoff, rows),
-                HomeKey => self.buf_mut().move_cursor_to_buffer_edge(CursorDir::Left),
-                EndKey => self.buf_mut().move_cursor_to_buffer_edge(CursorDir::Right),
-                DeleteKey => self.buf_mut().delete_right_char(),
-                Cursor(_, _) => unreachable!(),
-                _ => self.handle_not_mapped(&s),
-            },
+            }
         }
 
-        if let Some(line) = self.buf_mut().finish_edit() {
-            self.hl.needs_update = true;
-            self.screen.set_dirty_start(line);
+        if let Some(lines) = self.buf_mut().finish_edit() {
+            self.hl.invalidate(self.bufs[self.buf_idx].rows(), lines);
+            self.screen.set_dirty_start(lines.start);
         }
+        self.update_bracket_highlight();
         if self.buf().cursor() != prev_cursor {
             self.screen.cursor_moved = true;
         }
@@ -424,12 +905,13 @@ where
     }
 
     pub fn set_lang(&mut self, lang: Language) {
-        let buf = self.buf_mut();
-        if buf.lang() == lang {
+        if self.buf().lang() == lang {
             return;
         }
-        buf.set_lang(lang);
-        self.hl = Highlighting::new(lang, buf.rows());
+        self.buf_mut().set_lang(lang);
+        self.hl.lang_changed(lang);
+        // Render entire screen since highlight updated
+        self.screen.set_dirty_start(self.screen.rowoff);
     }
 }
 
@@ -469,11 +951,21 @@ where
 
 #[cfg(test)]
 mod tests {
+    use crate::config::Config;
     use crate::editor::Editor;
     use crate::error::Result;
+    use crate::highlight::{Highlight, Highlighting};
     use crate::input::{InputSeq, KeySeq};
-    use crate::language::Language;
-    use std::fs::File;
+    use crate::keymap::{self, Command};
+    use crate::language::{Indent, Language, SaveHooks};
+    use crate::prompt;
+    use crate::term_color::{Background, TermColor};
+    use crate::test_util::TempDir;
+    use crate::text_buffer::TextBuffer;
+    use crate::theme::{Face, Theme};
+    use crate::user_syntax;
+    use std::env;
+    use std::fs::{self, File};
     use std::io::{self, BufRead, BufReader, Write};
 
     use KeySeq::*;
@@ -603,7 +1095,14 @@ mod tests {
         let input = DummyInputs(vec![ctrl('q')]);
 
         let this_file = file!();
-        let mut editor = Editor::open(input, Discard, Some((80, 24)), &[this_file]).unwrap();
+        let mut editor = Editor::open(
+            input,
+            Discard,
+            Some((80, 24)),
+            &[this_file],
+            Config::default(),
+        )
+        .unwrap();
         editor.edit().unwrap();
 
         let f = BufReader::new(File::open(this_file).unwrap());
@@ -614,6 +1113,1181 @@ mod tests {
         assert_eq!(editor.lang(), Language::Rust);
     }
 
+    #[test]
+    fn detect_indent_on_open() {
+        let dir = TempDir::new("detect-indent");
+        let path = dir.write(
+            "test.js",
+            "function f() {\n    if (x) {\n        return 1;\n    }\n}\n",
+        );
+
+        let input = DummyInputs(vec![ctrl('i'), ctrl('q'), ctrl('q')]);
+        let mut editor =
+            Editor::open(input, Discard, Some((80, 24)), &[&path], Config::default()).unwrap();
+        editor.edit().unwrap();
+
+        assert_eq!(editor.buf().indent(), Indent::Spaces(4)); // Language default is 2 spaces
+        assert_eq!(editor.lines().next().unwrap(), "    function f() {");
+    }
+
+    #[test]
+    fn apply_editorconfig() {
+        let dir = TempDir::new("editorconfig");
+        dir.write(
+            ".editorconfig",
+            "root = true
+
+[*]
+indent_style = tab
+
+[*.txt]
+indent_style = space
+indent_size = 3
+end_of_line = crlf
+trim_trailing_whitespace = true
+insert_final_newline = false
+",
+        );
+        let path = dir.write("test.txt", "foo  \r\nbar\r\n");
+
+        let input = DummyInputs(vec![ctrl('i'), ctrl('s'), ctrl('q')]);
+        let mut editor =
+            Editor::open(input, Discard, Some((80, 24)), &[&path], Config::default()).unwrap();
+        editor.edit().unwrap();
+
+        assert_eq!(editor.buf().indent(), Indent::Spaces(3));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "   foo\r\nbar");
+
+        // Properties for the previous path are reset on saving as another file
+        editor.buf_mut().set_cursor(6, 0);
+        editor.buf_mut().insert_char(' ');
+        let path = dir.join("test.md");
+        let path_str = path.to_str().unwrap().to_string();
+        editor.execute(Command::Save, Some(path_str)).unwrap();
+        assert_eq!(editor.buf().indent(), Indent::Tab);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "   foo \nbar\n");
+    }
+
+    #[test]
+    fn open_with_broken_editorconfig() {
+        let dir = TempDir::new("broken-editorconfig");
+        // .editorconfig which is not UTF-8 cannot be read
+        dir.write(".editorconfig", b"\xff\xfe");
+        let path = dir.write("test.txt", "foo\n");
+
+        let input = DummyInputs(vec![ctrl('q')]);
+        let mut editor =
+            Editor::open(input, Discard, Some((80, 24)), &[&path], Config::default()).unwrap();
+        editor.edit().unwrap();
+        assert_eq!(editor.lines().collect::<Vec<_>>(), vec!["foo"]);
+    }
+
+    #[test]
+    fn user_config() {
+        let dir = TempDir::new("user-config");
+        let config_path = dir.write(
+            "config.toml",
+            r#"
+indent_style = "space"
+indent_size = 3
+trim_trailing_whitespace = false
+show_whitespace = true
+
+[keymap]
+"Ctrl-T" = "insert-tab"
+"#,
+        );
+        let config = Config::parse(&config_path).unwrap();
+
+        let path = dir.join("new.go");
+        let tabbed = dir.write("tabbed.go", "func f() {\n\treturn\n}\n");
+
+        let input = DummyInputs(vec![ctrl('t'), key('a'), key(' '), ctrl('s'), ctrl('q')]);
+        let mut editor =
+            Editor::open(input, Discard, Some((80, 24)), &[&path, &tabbed], config).unwrap();
+        editor.edit().unwrap();
+
+        // Language default of Go is tab
+        assert_eq!(editor.buf().indent(), Indent::Spaces(3));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "   a \n");
+        assert!(editor.screen().show_whitespace());
+        // Detected indentation is prioritized
+        assert_eq!(editor.bufs[1].indent(), Indent::Tab);
+
+        for (broken, expected) in &[
+            ("tabwidth = 4", "unknown field `tabwidth`"),
+            ("indent_style = \"tabs\"", "unknown variant `tabs`"),
+            ("tab_width = 0", "'tab_width' must be greater than 0"),
+            ("background = \"gray\"", "Unknown background \"gray\""),
+            (
+                "[theme.keywords]\nfg = \"red\"",
+                "Unknown element 'keywords' in [theme]",
+            ),
+            ("theme = \"nothing\"", "Theme 'nothing' not found"),
+            ("theme = \"../config\"", "Theme '../config' not found"),
+            (
+                "[keymap]\n\"Ctrl-A\" = \"foo\"",
+                "Unknown command 'foo' in [keymap]",
+            ),
+            (
+                "[formatter]\nrusty = \"rustfmt\"",
+                "Unknown language 'rusty' in [formatter]",
+            ),
+            (
+                "[save_hooks.rusty]\ntrim_trailing_blank_lines = false",
+                "Unknown language 'rusty' in [save_hooks]",
+            ),
+            (
+                "[save_hooks.rust]\ntrim_blank_lines = false",
+                "unknown field `trim_blank_lines`",
+            ),
+        ] {
+            fs::write(&config_path, broken).unwrap();
+            let msg = Config::parse(&config_path).err().unwrap().to_string();
+            assert!(msg.starts_with("Invalid config file"), "{:?}", msg);
+            assert!(
+                msg.contains(expected),
+                "{:?} does not contain {:?}",
+                msg,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn theme_file() {
+        let dir = TempDir::new("theme-file");
+        dir.write(
+            "themes/mytheme.toml",
+            "[keyword]\nfg = \"red\"\n\n[status_bar]\nbold = true\n",
+        );
+        let config_path = dir.write("config.toml", "theme = \"mytheme\"");
+        let config = Config::parse(&config_path).unwrap();
+        let theme = Theme::new(TermColor::Colors16, Background::Dark, config.theme.as_ref());
+        let keyword = theme.sequence(Face::Text(Highlight::Keyword));
+        assert!(keyword.ends_with(b"\x1b[31m"), "{:?}", keyword);
+
+        for (broken, expected) in &[
+            ("[keywords]\nfg = \"red\"", "Unknown element 'keywords'"),
+            ("[keyword]\nfg = \"reddish\"", "Invalid color 'reddish'"),
+            ("[keyword", "expected"),
+        ] {
+            dir.write("themes/mytheme.toml", broken);
+            let msg = Config::parse(&config_path).err().unwrap().to_string();
+            assert!(msg.starts_with("Invalid theme file"), "{:?}", msg);
+            assert!(msg.contains("mytheme.toml"), "{:?}", msg);
+            assert!(
+                msg.contains(expected),
+                "{:?} does not contain {:?}",
+                msg,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn save_hooks_are_undoable() {
+        let dir = TempDir::new("save-hooks");
+        let path = dir.write("test.rs", "fn f() {  \n}\t\n\n\n");
+
+        let config = Config {
+            trim_trailing_whitespace: Some(true),
+            trim_trailing_blank_lines: Some(true),
+            ..Config::default()
+        };
+        let input = DummyInputs(vec![ctrl('s'), ctrl('u'), ctrl('q'), ctrl('q')]);
+        let mut editor = Editor::open(input, Discard, Some((80, 24)), &[&path], config).unwrap();
+        editor.edit().unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "fn f() {\n}\n");
+        let lines: Vec<_> = editor.lines().collect();
+        assert_eq!(lines, vec!["fn f() {  ", "}\t", "", ""]);
+    }
+
+    #[test]
+    fn save_hooks_per_language() {
+        let dir = TempDir::new("save-hooks-per-lang");
+        let config_path = dir.write(
+            "config.toml",
+            r#"
+trim_trailing_blank_lines = true
+
+[save_hooks.markdown]
+trim_trailing_whitespace = true
+trim_trailing_blank_lines = false
+
+[save_hooks.plain]
+trim_trailing_whitespace = true
+"#,
+        );
+        let config = Config::parse(&config_path).unwrap();
+
+        let content = "foo  \nbar\t\n\n\n";
+        let mut paths = vec![];
+        for name in &["test.md", "test.txt", "test.rs"] {
+            let path = dir.write(name, content);
+            paths.push(path);
+        }
+
+        let keys = vec![
+            ctrl('s'),
+            ctrl('x'),
+            ctrl('s'),
+            ctrl('x'),
+            ctrl('s'),
+            ctrl('q'),
+        ];
+        let input = DummyInputs(keys);
+        let mut editor = Editor::open(input, Discard, Some((80, 24)), &paths, config).unwrap();
+        editor.edit().unwrap();
+
+        let read = |i: usize| fs::read_to_string(&paths[i]).unwrap();
+        assert_eq!(read(0), "foo\nbar\n\n\n");
+        assert_eq!(read(1), "foo\nbar\n");
+        // Global config is applied to languages without per-language config
+        assert_eq!(read(2), "foo  \nbar\t\n");
+
+        // Nothing is trimmed by default
+        let config = Config::default();
+        for lang in &[Language::Rust, Language::Markdown, Language::Plain] {
+            assert_eq!(config.save_hooks(*lang), SaveHooks::default(), "{:?}", lang);
+        }
+    }
+
+    #[test]
+    fn config_for_user_language() {
+        let dir = TempDir::new("config-user-lang");
+        dir.write(
+            "syntax/kirotest.toml",
+            "name = \"kirotest\"\nfile_extensions = [\"kirotest\"]\nindent_style = \"tab\"\n",
+        );
+        // Only this test registers user-defined languages since they can be registered only once
+        let langs = user_syntax::load_dir(&dir.join("syntax")).unwrap();
+        Language::register_user_languages(langs).unwrap();
+        let langs = user_syntax::load_dir(&dir.join("syntax")).unwrap();
+        assert!(Language::register_user_languages(langs).is_err());
+        let lang = Language::detect("test.kirotest");
+        assert_eq!(lang.name(), "kirotest");
+        assert_eq!(lang.indent(), Indent::Tab);
+
+        let config_path = dir.write(
+            "config.toml",
+            r#"
+[formatter]
+kirotest = "cat"
+
+[save_hooks.kirotest]
+trim_trailing_whitespace = true
+trim_trailing_blank_lines = false
+"#,
+        );
+        let config = Config::parse(&config_path).unwrap();
+        assert_eq!(config.formatter(lang), Some("cat"));
+        let hooks = config.save_hooks(lang);
+        assert!(hooks.trim_trailing_whitespace);
+        assert!(!hooks.trim_trailing_blank_lines);
+    }
+
+    #[test]
+    fn toggle_visible_whitespace() {
+        let input = DummyInputs(vec![
+            InputSeq::alt(Key(b'w')),
+            InputSeq::alt(Key(b'w')),
+            InputSeq::alt(Key(b'w')),
+            ctrl('q'),
+        ]);
+        let lines = ["\tfoo \u{a0}bar  "];
+        let mut buf = Vec::new();
+        let mut editor = Editor::with_lines(lines.iter(), input, &mut buf, Some((80, 24))).unwrap();
+        editor.edit().unwrap();
+
+        assert!(editor.screen().show_whitespace());
+        assert_eq!(editor.screen().message_text(), "Whitespaces are visible");
+        // Buffer is not modified
+        assert_eq!(editor.lines().collect::<Vec<_>>(), lines);
+        drop(editor);
+
+        // Remove escape sequences from output
+        let mut out = String::new();
+        let mut in_seq = false;
+        for c in String::from_utf8(buf).unwrap().chars() {
+            match c {
+                '\x1b' => in_seq = true,
+                c if in_seq => in_seq = !c.is_ascii_alphabetic(),
+                c => out.push(c),
+            }
+        }
+        assert!(out.contains(">       foo +bar--"), "{:?}", out);
+    }
+
+    #[test]
+    fn whitespace_glyphs_keep_highlight() {
+        let input = DummyInputs(vec![InputSeq::alt(Key(b'w')), ctrl('q')]);
+        let lines = ["let s = \"\t\";"];
+        let mut buf = Vec::new();
+        let mut editor = Editor::with_lines(lines.iter(), input, &mut buf, Some((80, 24))).unwrap();
+        editor.set_lang(Language::Rust);
+        editor.edit().unwrap();
+        drop(editor);
+
+        let out = String::from_utf8(buf).unwrap();
+        let theme = Theme::from_env(None);
+        let seq = |face| String::from_utf8(theme.sequence(face).to_vec()).unwrap();
+        // Tab in string literal is rendered with the highlight of string
+        let string = format!("{}\">", seq(Face::Text(Highlight::String)));
+        assert!(out.contains(&string), "{:?}", out);
+        let non_text = format!("{}>", seq(Face::NonText));
+        assert!(!out.contains(&non_text), "{:?}", out);
+    }
+
+    #[test]
+    fn jump_to_matching_bracket() {
+        let lines = ["fn f() {", "    let s = \"}\"; // }", "    if x { y }", "}"];
+        let input = DummyInputs(vec![ctrl('e'), InputSeq::alt(Key(b'm')), ctrl('q')]);
+        let mut editor = Editor::with_lines(lines.iter(), input, Discard, Some((80, 24))).unwrap();
+        editor.set_lang(Language::Rust);
+        editor.edit().unwrap();
+
+        // Brackets in string literal and comment are skipped
+        assert_eq!(editor.buf().cursor(), (0, 3));
+        assert_eq!(editor.hl.lines[0][7], Highlight::Bracket);
+        assert_eq!(editor.hl.lines[3][0], Highlight::Bracket);
+
+        let input = DummyInputs(vec![ctrl('f'), InputSeq::alt(Key(b'm')), ctrl('q')]);
+        let lines = ["\t(a)"];
+        let mut editor = Editor::with_lines(lines.iter(), input, Discard, Some((80, 24))).unwrap();
+        editor.edit().unwrap();
+        assert_eq!(editor.buf().cursor(), (3, 0));
+        // Highlights are put on render text where tab is expanded
+        assert_eq!(editor.hl.lines[0][8], Highlight::Bracket);
+        assert_eq!(editor.hl.lines[0][10], Highlight::Bracket);
+
+        let input = DummyInputs(vec![InputSeq::alt(Key(b'm')), ctrl('q')]);
+        let mut editor = Editor::with_lines(["(a"].iter(), input, Discard, Some((80, 24))).unwrap();
+        editor.edit().unwrap();
+        assert_eq!(editor.buf().cursor(), (0, 0));
+        assert_eq!(editor.screen().message_text(), "No matching bracket");
+    }
+
+    #[test]
+    fn highlight_bracket_pair_on_screen() {
+        let mut keys = vec![ctrl('f'); 5];
+        keys.push(ctrl('q'));
+        let input = DummyInputs(keys);
+        let lines = ["f(x);"];
+        let mut editor = Editor::with_lines(lines.iter(), input, Discard, Some((80, 24))).unwrap();
+        editor.set_lang(Language::Rust);
+        editor.edit().unwrap();
+        // Highlights are restored after the cursor leaves the bracket
+        assert_eq!(editor.buf().cursor(), (5, 0));
+        assert_eq!(editor.hl.lines[0][1], Highlight::Normal);
+        assert_eq!(editor.hl.lines[0][3], Highlight::Normal);
+
+        // Partner bracket below the bottom of screen is not searched
+        let mut lines = vec!["fn f() {".to_string()];
+        lines.extend((0..30).map(|i| format!("    x{};", i)));
+        lines.push("}".to_string());
+        let input = DummyInputs(vec![ctrl('e'), ctrl('q')]);
+        let mut editor = Editor::with_lines(lines.iter(), input, Discard, Some((80, 10))).unwrap();
+        editor.set_lang(Language::Rust);
+        editor.edit().unwrap();
+        assert_eq!(editor.hl.lines[0][7], Highlight::Normal);
+        assert!(editor
+            .hl
+            .lines
+            .iter()
+            .flatten()
+            .all(|h| *h != Highlight::Bracket));
+    }
+
+    #[test]
+    fn mouse_click_and_wheel() {
+        let dir = TempDir::new("mouse");
+        let path = dir.join("test.txt");
+        let mut text = String::from("\tabc\nあいう\n");
+        for _ in 0..100 {
+            text.push_str("x\n");
+        }
+        fs::write(&path, &text).unwrap();
+
+        for (inputs, cursor, rowoff) in &[
+            (vec![MouseClick(9, 0)], (2, 0), 0), // Right side of tab
+            (vec![MouseClick(3, 0)], (0, 0), 0), // Middle of tab
+            (vec![MouseClick(3, 1)], (1, 1), 0), // Right half of wide char
+            (vec![MouseClick(50, 1)], (3, 1), 0),
+            (vec![MouseClick(0, 22)], (0, 0), 0), // Click on status bar
+            (vec![WheelDown, WheelDown], (0, 6), 6),
+            (vec![MouseClick(1, 21), WheelUp], (1, 21), 0),
+            (
+                vec![MouseClick(1, 21), WheelDown, WheelUp, WheelUp],
+                (1, 21),
+                0,
+            ),
+            (vec![MouseClick(1, 1), WheelDown], (0, 3), 3), // Cursor is clamped to line length
+            (vec![WheelUp], (0, 0), 0),
+            ((0..50).map(|_| WheelDown).collect(), (0, 101), 101),
+        ] {
+            let mut keys: Vec<_> = inputs.iter().cloned().map(InputSeq::new).collect();
+            keys.push(ctrl('q'));
+            let input = DummyInputs(keys);
+            let mut editor =
+                Editor::open(input, Discard, Some((80, 24)), &[&path], Config::default()).unwrap();
+            editor.edit().unwrap();
+            assert_eq!(editor.buf().cursor(), *cursor, "{:?}", inputs);
+            assert_eq!(editor.screen().rowoff, *rowoff, "{:?}", inputs);
+        }
+    }
+
+    #[test]
+    fn disable_mouse() {
+        for mouse in &[true, false] {
+            let config = Config {
+                mouse: *mouse,
+                ..Config::default()
+            };
+            let mut buf = Vec::new();
+            {
+                let input = DummyInputs(vec![ctrl('q')]);
+                let text = TextBuffer::empty();
+                let mut editor =
+                    Editor::with_buf(text, input, &mut buf, Some((80, 24)), config).unwrap();
+                editor.edit().unwrap();
+            }
+            let out = String::from_utf8(buf).unwrap();
+            assert_eq!(out.contains("\x1b[?1000h"), *mouse, "{:?}", out);
+            assert_eq!(out.contains("\x1b[?1000l"), *mouse, "{:?}", out);
+        }
+    }
+
+    #[test]
+    fn user_keymap() {
+        let dir = TempDir::new("user-keymap");
+        let path = dir.write(
+            "config.toml",
+            r#"
+[keymap]
+"Ctrl-X Ctrl-U" = "undo"
+"Ctrl-Shift-Z" = "redo"
+"Ctrl-U" = "none"
+"F2" = "cursor-left"
+"#,
+        );
+        let config = Config::parse(&path).unwrap();
+
+        let mut redo = ctrl('z');
+        redo.shift = true;
+        let input = DummyInputs(vec![
+            key('a'),
+            key('b'),
+            ctrl('u'), // Not mapped
+            ctrl('x'),
+            ctrl('u'), // Undo
+            redo,
+            sp(FunctionKey(2)),
+            key('c'),
+            ctrl('x'),
+            key('q'), // Not mapped
+        ]);
+        let mut editor = Editor::new(input, Discard, Some((80, 24))).unwrap();
+        editor.config = config;
+        editor.edit().unwrap();
+
+        assert_eq!(editor.buf().rows()[0].buffer(), "acb");
+        assert_eq!(editor.screen().message_text(), "Key 'Ctrl-X q' not mapped");
+
+        let help = editor.config.keymap.help();
+        let desc_of = |keys: &str| {
+            help.lines()
+                .map(|l| l.trim().splitn(2, " : ").collect::<Vec<_>>())
+                .find(|cols| cols[0].trim_end() == keys)
+                .map(|cols| cols[1])
+        };
+        assert_eq!(desc_of("Ctrl-X Ctrl-U"), Some("Undo last change"));
+        assert_eq!(
+            desc_of("Ctrl-R or Ctrl-Shift-Z"),
+            Some("Redo last undo change")
+        );
+        assert_eq!(desc_of("Ctrl-B or Left or F2"), Some("Move cursor left"));
+        assert!(!help.contains("Next text buffer"), "{}", help); // Ctrl-X conflicts with chord
+
+        for notation in &[
+            "Ctrl-Q",
+            "Alt-Shift-Left",
+            "Ctrl-[",
+            "Alt-<",
+            "F12",
+            "Backspace",
+            "é",
+        ] {
+            let key = keymap::parse_key(notation).unwrap();
+            assert_eq!(keymap::format_key(&key), *notation);
+        }
+        assert_eq!(keymap::parse_key("enter"), Some(ctrl('m')));
+        assert_eq!(keymap::parse_key("Alt-V"), keymap::parse_key("alt-v"));
+
+        for broken in &[
+            "\"Hyper-A\" = \"quit\"",
+            "\"Ctrl-A\" = \"unknown-command\"",
+            "\"Ctrl-A\" = \"quit\"\n\"Ctrl-A Ctrl-B\" = \"save\"",
+        ] {
+            fs::write(&path, format!("[keymap]\n{}", broken)).unwrap();
+            assert!(Config::parse(&path).is_err(), "{:?}", broken);
+        }
+    }
+
+    #[test]
+    fn command_palette() {
+        let mut keys = vec![];
+        for cmd in &["goto-line 3\r", "eol\r", "dwo\t\r", "undo 1\r"] {
+            keys.push(ctrl('\\'));
+            keys.extend(cmd.chars().map(|c| match c {
+                '\t' => ctrl('i'),
+                c => key(c),
+            }));
+        }
+
+        let input = DummyInputs(keys);
+        let lines = ["abc", "def", "ghi"];
+        let mut editor = Editor::with_lines(lines.iter(), input, Discard, Some((80, 24))).unwrap();
+        editor.edit().unwrap();
+
+        // "eol" is fuzzy matched to end-of-line and "dwo" is completed to delete-word
+        assert_eq!(editor.lines().collect::<Vec<_>>(), vec!["abc", "def", ""]);
+        assert_eq!(
+            editor.screen().message_text(),
+            "Command 'undo' does not take an argument",
+        );
+
+        let keys = vec![ctrl('\\'), key('z'), key('z'), key('\r')];
+        let mut editor = Editor::new(DummyInputs(keys), Discard, Some((80, 24))).unwrap();
+        editor.edit().unwrap();
+        assert_eq!(editor.screen().message_text(), "Unknown command \"zz\"");
+    }
+
+    #[test]
+    fn goto_line() {
+        let lines = ["abc", "def", "ghi"];
+        for (arg, cursor) in &[("2", (0, 1)), ("3", (0, 2)), ("100", (0, 2))] {
+            let input = DummyInputs(vec![]);
+            let mut editor =
+                Editor::with_lines(lines.iter(), input, Discard, Some((80, 24))).unwrap();
+            editor
+                .execute(Command::GotoLine, Some(arg.to_string()))
+                .unwrap();
+            assert_eq!(editor.buf().cursor(), *cursor, "{:?}", arg);
+        }
+
+        // Buffer without any line
+        let empty: [&str; 0] = [];
+        let input = DummyInputs(vec![]);
+        let mut editor = Editor::with_lines(empty.iter(), input, Discard, Some((80, 24))).unwrap();
+        assert!(editor.buf().rows().is_empty());
+        editor
+            .execute(Command::GotoLine, Some("3".to_string()))
+            .unwrap();
+        assert_eq!(editor.buf().cursor(), (0, 0));
+    }
+
+    #[test]
+    fn complete_path_in_prompt() {
+        let dir = TempDir::new("complete-path");
+        dir.write("fizz/a.txt", "a");
+        dir.write("foo.txt", "foo");
+        dir.write("foobar.rs", "");
+        dir.write(".foo", "");
+
+        let dir_str = dir.to_str().unwrap();
+        let mut keys = vec![ctrl('o')];
+        keys.extend(format!("{}/f", dir_str).chars().map(key));
+        // Candidates are cycled: fizz/ -> foo.txt
+        keys.extend(vec![ctrl('i'), ctrl('i'), key('\r')]);
+        keys.push(ctrl('o'));
+        keys.extend(format!("{}/fi", dir_str).chars().map(key));
+        // Complete directory and then enter it
+        keys.extend(vec![ctrl('i'), ctrl('i'), key('\r')]);
+
+        let mut editor = Editor::new(DummyInputs(keys), Discard, Some((80, 24))).unwrap();
+        editor.edit().unwrap();
+
+        let files: Vec<_> = editor.bufs.iter().map(|b| b.filename()).collect();
+        let expected = vec![
+            "[No Name]".to_string(),
+            format!("{}/foo.txt", dir_str),
+            format!("{}/fizz/a.txt", dir_str),
+        ];
+        assert_eq!(files, expected);
+
+        let home = env::var("HOME").unwrap();
+        assert_eq!(prompt::expand_home("~"), home);
+        assert_eq!(prompt::expand_home("~/foo"), format!("{}/foo", home));
+        assert_eq!(prompt::expand_home("~foo"), "~foo");
+        assert_eq!(prompt::expand_home("foo/~"), "foo/~");
+    }
+
+    #[test]
+    fn find_file_in_project() {
+        let alt_o = InputSeq::alt(Key(b'o'));
+        let mut keys = vec![alt_o.clone()];
+        keys.extend("src/editor.rs".chars().map(key));
+        keys.push(key('\r'));
+        keys.push(alt_o.clone());
+        keys.extend("Cargo".chars().map(key));
+        keys.extend(vec![sp(DownKey), sp(DownKey), sp(UpKey), key('\r')]);
+        keys.push(alt_o.clone());
+        keys.extend("no such file!".chars().map(key));
+        keys.push(key('\r'));
+
+        let mut editor = Editor::new(DummyInputs(keys), Discard, Some((80, 24))).unwrap();
+        editor.edit().unwrap();
+
+        let files: Vec<_> = editor.bufs.iter().map(|b| b.filename()).collect();
+        // Cargo.lock, Cargo.toml, fuzz/Cargo.lock, ... are listed in this order
+        assert_eq!(files, vec!["[No Name]", "src/editor.rs", "Cargo.toml"]);
+        assert_eq!(editor.lang(), Language::Toml);
+        assert_eq!(editor.screen().message_text(), "No file matched");
+
+        // Error on opening the file is shown and the current buffer is kept
+        let mut keys = vec![alt_o];
+        keys.extend("undo-redo-support-diagram.png".chars().map(key));
+        keys.push(key('\r'));
+        let mut editor = Editor::new(DummyInputs(keys), Discard, Some((80, 24))).unwrap();
+        editor.edit().unwrap();
+        assert_eq!(editor.bufs.len(), 1);
+        let msg = editor.screen().message_text();
+        assert!(
+            msg.starts_with("Could not open \"assets/undo-redo-support-diagram.png\": "),
+            "{:?}",
+            msg,
+        );
+    }
+
+    #[test]
+    fn grep_in_project() {
+        let alt_g = InputSeq::alt(Key(b'g'));
+        let mut keys = vec![alt_g.clone()];
+        // Regular expression not to match this line itself
+        keys.extend("/^    fn open_locatio[n]/".chars().map(key));
+        keys.push(key('\r'));
+        keys.push(key('x')); // Read-only
+        keys.push(ctrl('s'));
+        keys.push(key('\r'));
+        keys.push(alt_g.clone());
+        keys.extend("/no such tex[t]/".chars().map(key));
+        keys.push(key('\r'));
+
+        let mut editor = Editor::new(DummyInputs(keys), Discard, Some((80, 24))).unwrap();
+        editor.edit().unwrap();
+
+        let files: Vec<_> = editor.bufs.iter().map(|b| b.filename()).collect();
+        assert_eq!(
+            files,
+            vec![
+                "[No Name]",
+                "[grep: /^    fn open_locatio[n]/]",
+                "src/editor.rs"
+            ],
+        );
+        let results = &editor.bufs[1];
+        assert!(results.is_read_only());
+        assert!(!results.modified());
+        let lines: Vec<_> = results.lines().collect();
+        assert_eq!(lines.len(), 1);
+        assert!(lines[0].starts_with("src/editor.rs:"), "{:?}", lines);
+        assert!(lines[0].ends_with(":     fn open_location(&mut self) -> Result<()> {"));
+
+        let (_, y) = editor.buf().cursor();
+        assert_eq!(
+            editor.buf().rows()[y].buffer(),
+            "    fn open_location(&mut self) -> Result<()> {",
+        );
+        assert_eq!(
+            editor.screen().message_text(),
+            "No match found for \"/no such tex[t]/\"",
+        );
+    }
+
+    #[test]
+    fn open_invalid_location() {
+        let dir = TempDir::new("open-location");
+        let empty = dir.write("empty.txt", "");
+        fs::create_dir(dir.join("subdir")).unwrap();
+        let lines = [
+            format!("{}:1: foo", dir.join("subdir").to_str().unwrap()),
+            format!("{}:3: foo", empty.to_str().unwrap()),
+        ];
+        let buf = TextBuffer::read_only("[grep: foo]", lines.iter()).unwrap();
+        let input = DummyInputs(vec![]);
+        let mut editor =
+            Editor::with_buf(buf, input, Discard, Some((80, 24)), Config::default()).unwrap();
+
+        // Directory cannot be opened
+        editor.execute(Command::NewLine, None).unwrap();
+        assert_eq!(editor.bufs.len(), 1);
+        let msg = editor.screen().message_text();
+        assert!(msg.starts_with("Could not open \""), "{:?}", msg);
+
+        // Cursor is at the end of buffer
+        editor.buf_mut().set_cursor(0, 2);
+        editor.execute(Command::NewLine, None).unwrap();
+        assert_eq!(editor.bufs.len(), 1);
+        assert_eq!(editor.screen().message_text(), "No location at the line");
+
+        // Line number is clamped to empty file
+        editor.buf_mut().set_cursor(0, 1);
+        editor.execute(Command::NewLine, None).unwrap();
+        assert_eq!(editor.bufs.len(), 2);
+        assert_eq!(editor.buf().filename(), empty.to_str().unwrap());
+        assert_eq!(editor.buf().cursor(), (0, 0));
+    }
+
+    #[test]
+    fn shell_command_and_filter() {
+        let alt = |c| InputSeq::alt(Key(c));
+        let mut keys = vec![alt(b'|')];
+        keys.extend("sort -r".chars().map(key));
+        keys.push(key('\r'));
+        let mut editor = Editor::with_lines(
+            ["b", "a", "c"].iter(),
+            DummyInputs(keys),
+            Discard,
+            Some((80, 24)),
+        )
+        .unwrap();
+        editor.edit().unwrap();
+        assert_eq!(editor.lines().collect::<Vec<_>>(), vec!["c", "b", "a"]);
+
+        // Filter lines and undo it at once. Cursor is at the second line
+        let mut keys = vec![ctrl('n'), alt(b'|')];
+        keys.extend("2,3 tr a-z A-Z".chars().map(key));
+        keys.push(key('\r'));
+        let mut editor = Editor::with_lines(
+            ["b", "a", "c"].iter(),
+            DummyInputs(keys.clone()),
+            Discard,
+            Some((80, 24)),
+        )
+        .unwrap();
+        editor.edit().unwrap();
+        assert_eq!(editor.lines().collect::<Vec<_>>(), vec!["b", "A", "C"]);
+        keys.push(ctrl('u'));
+        let mut editor = Editor::with_lines(
+            ["b", "a", "c"].iter(),
+            DummyInputs(keys),
+            Discard,
+            Some((80, 24)),
+        )
+        .unwrap();
+        editor.edit().unwrap();
+        assert_eq!(editor.lines().collect::<Vec<_>>(), vec!["b", "a", "c"]);
+
+        // Buffer is not changed when command failed
+        let mut keys = vec![alt(b'|')];
+        keys.extend("echo error >&2; exit 1".chars().map(key));
+        keys.push(key('\r'));
+        let mut editor = Editor::with_lines(
+            ["b", "a", "c"].iter(),
+            DummyInputs(keys),
+            Discard,
+            Some((80, 24)),
+        )
+        .unwrap();
+        editor.edit().unwrap();
+        assert_eq!(editor.lines().collect::<Vec<_>>(), vec!["b", "a", "c"]);
+        assert!(!editor.buf().modified());
+        assert_eq!(
+            editor.screen().message_text(),
+            "Command exited with status 1: error"
+        );
+
+        // Insert output at cursor
+        let mut keys = vec![ctrl('f'), alt(b'!')];
+        keys.extend("printf 'foo\\nbar\\n'".chars().map(key));
+        keys.push(key('\r'));
+        keys.push(key('!'));
+        let mut editor =
+            Editor::with_lines(["()"].iter(), DummyInputs(keys), Discard, Some((80, 24))).unwrap();
+        editor.edit().unwrap();
+        assert_eq!(editor.lines().collect::<Vec<_>>(), vec!["(foo", "bar!)"]);
+    }
+
+    #[test]
+    fn format_on_save() {
+        let dir = TempDir::new("format-on-save");
+        let config_path = dir.join("config.toml");
+        // Indent lines and insert blank line after each block
+        fs::write(
+            &config_path,
+            r#"
+[formatter]
+plain = "sed -e 's/^x/    x/' -e 's/^}$/}\\n/'"
+"#,
+        )
+        .unwrap();
+        let config = Config::parse(&config_path).unwrap();
+        let path = dir.write("test.txt", "{\nx\n}\n{\nx\n}\n");
+
+        // Cursor is at 'x' in the second block
+        let keys = vec![ctrl('n'), ctrl('n'), ctrl('n'), ctrl('n'), ctrl('s')];
+        let mut editor =
+            Editor::open(DummyInputs(keys), Discard, Some((80, 24)), &[&path], config).unwrap();
+        editor.edit().unwrap();
+
+        let expected = "{\n    x\n}\n\n{\n    x\n}\n\n";
+        assert_eq!(fs::read_to_string(&path).unwrap(), expected);
+        assert_eq!(editor.buf().cursor(), (0, 5));
+        assert!(!editor.buf().modified());
+
+        // Formatting is one undoable change
+        let keys = vec![ctrl('s'), ctrl('u')];
+        let config = Config::parse(&config_path).unwrap();
+        fs::write(&path, "{\nx\n}\n").unwrap();
+        let mut editor =
+            Editor::open(DummyInputs(keys), Discard, Some((80, 24)), &[&path], config).unwrap();
+        editor.edit().unwrap();
+        assert_eq!(editor.lines().collect::<Vec<_>>(), vec!["{", "x", "}"]);
+
+        // Buffer is saved without change when formatter failed
+        fs::write(
+            &config_path,
+            "[formatter]\nplain = \"echo oops >&2; exit 2\"",
+        )
+        .unwrap();
+        let config = Config::parse(&config_path).unwrap();
+        fs::write(&path, "{\nx\n}\n").unwrap();
+        let keys = vec![key('y'), ctrl('s')];
+        let mut editor =
+            Editor::open(DummyInputs(keys), Discard, Some((80, 24)), &[&path], config).unwrap();
+        editor.edit().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "y{\nx\n}\n");
+        let msg = editor.screen().message_text();
+        assert!(
+            msg.ends_with("but could not format: Command exited with status 2: oops"),
+            "{:?}",
+            msg
+        );
+    }
+
+    #[test]
+    fn suspend_without_terminal() {
+        // Process is not stopped when stdin is not in raw mode
+        let input = DummyInputs(vec![key('a'), ctrl('z')]);
+        let mut editor = Editor::new(input, Discard, Some((80, 24))).unwrap();
+        editor.edit().unwrap();
+        assert_eq!(editor.lines().collect::<Vec<_>>(), vec!["a"]);
+        assert_eq!(
+            editor.screen().message_text(),
+            "Cannot suspend since terminal is not in raw mode"
+        );
+    }
+
+    #[test]
+    fn detect_language_on_save() {
+        let dir = TempDir::new("detect-on-save");
+        let path = dir.join("test");
+        let mut keys = vec![ctrl('s')];
+        keys.extend(path.to_str().unwrap().chars().map(key));
+        keys.extend(vec![key('\r'), ctrl('q')]);
+
+        let input = DummyInputs(keys);
+        let lines = ["#!/usr/bin/env python3", "print('hello')"];
+        let mut editor = Editor::with_lines(lines.iter(), input, Discard, Some((80, 24))).unwrap();
+        assert_eq!(editor.lang(), Language::Plain);
+        editor.edit().unwrap();
+
+        assert_eq!(editor.lang(), Language::Python);
+        assert_eq!(editor.buf().filename(), path.to_str().unwrap());
+
+        // Language selected by user is kept on saving as another file
+        editor.set_lang(Language::Rust);
+        let path = dir.join("test.py");
+        let path = path.to_str().unwrap().to_string();
+        editor.execute(Command::Save, Some(path.clone())).unwrap();
+        assert_eq!(editor.lang(), Language::Rust);
+        assert_eq!(editor.buf().filename(), path);
+    }
+
+    #[test]
+    fn highlight_more_languages() {
+        use Highlight::*;
+
+        // Expected highlights at (y, x) for each language
+        type Case = (
+            Language,
+            &'static [&'static str],
+            &'static [(usize, usize, Highlight)],
+        );
+        let cases: &[Case] = &[
+            (
+                Language::Shell,
+                &["echo \"$HOME/${DIR}\" $@ # $NOT"],
+                &[
+                    (0, 5, String),
+                    (0, 6, SpecialVar),
+                    (0, 13, SpecialVar),
+                    (0, 18, String),
+                    (0, 20, SpecialVar),
+                    (0, 27, Comment),
+                ],
+            ),
+            (
+                Language::Toml,
+                &["[package] # c", "name = \"kiro\"", "[[bin]]"],
+                &[
+                    (0, 0, Definition),
+                    (0, 8, Definition),
+                    (0, 10, Comment),
+                    (1, 0, Keyword),
+                    (1, 3, Keyword),
+                    (1, 4, Normal),
+                    (1, 7, String),
+                    (2, 6, Definition),
+                ],
+            ),
+            (
+                Language::Yaml,
+                &[
+                    "on:",
+                    "  - name: x",
+                    "- http://example.com",
+                    "name: don't",
+                    "- 'a', [x,'b']",
+                    "- it's",
+                ],
+                &[
+                    (0, 0, Keyword),
+                    (0, 1, Keyword),
+                    (0, 2, Normal),
+                    (1, 4, Keyword),
+                    (1, 10, Normal),
+                    (2, 2, Normal),
+                    (3, 9, Normal),
+                    (3, 10, Normal),
+                    (4, 2, String),
+                    (4, 4, String),
+                    (4, 5, Normal),
+                    (4, 10, String),
+                    (4, 12, String),
+                    (5, 4, Normal),
+                    (5, 5, Normal),
+                ],
+            ),
+            (
+                Language::Markdown,
+                &["## Title", "#hash", "```", "# not heading", "```", "`code`"],
+                &[
+                    (0, 0, Definition),
+                    (0, 7, Definition),
+                    (1, 0, Normal),
+                    (2, 0, String),
+                    (3, 0, String),
+                    (4, 2, String),
+                    (5, 1, String),
+                ],
+            ),
+            (
+                Language::Json,
+                &["{", "  \"key\": [\"value\", 1, true]"],
+                &[
+                    (1, 2, Keyword),
+                    (1, 6, Keyword),
+                    (1, 7, Normal),
+                    (1, 10, String),
+                    (1, 19, Number),
+                    (1, 22, Boolean),
+                ],
+            ),
+        ];
+
+        for (lang, lines, expected) in cases {
+            let input = DummyInputs(vec![ctrl('q')]);
+            let mut editor =
+                Editor::with_lines(lines.iter(), input, Discard, Some((80, 24))).unwrap();
+            editor.set_lang(*lang);
+            editor.edit().unwrap();
+            for (y, x, hl) in expected.iter() {
+                assert_eq!(editor.hl.lines[*y][*x], *hl, "{:?} at ({}, {})", lang, x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn highlight_multiline_strings() {
+        use Highlight::*;
+
+        type Case = (
+            Language,
+            &'static [&'static str],
+            &'static [(usize, usize, Highlight)],
+        );
+        let cases: &[Case] = &[
+            (
+                Language::Python,
+                &["x = '''a", "# not comment ' \\'''", "'''", "y"],
+                &[
+                    (0, 4, String),
+                    (0, 7, String),
+                    (1, 0, String),
+                    (1, 18, String),
+                    (2, 0, String),
+                    (2, 2, String),
+                    (3, 0, Normal),
+                ],
+            ),
+            (
+                Language::Rust,
+                &["let s = r#\"a\"", "// \"# x", "bar\"x\""],
+                &[
+                    (0, 8, String),
+                    (0, 12, String),
+                    (1, 0, String),
+                    (1, 4, String),
+                    (1, 6, Normal),
+                    (2, 0, Normal),
+                    (2, 3, String),
+                ],
+            ),
+            (
+                Language::JavaScript,
+                &["const s = `a", "\\` b`;", "x"],
+                &[
+                    (0, 10, String),
+                    (1, 0, String),
+                    (1, 1, String),
+                    (1, 4, String),
+                    (1, 5, Normal),
+                    (2, 0, Normal),
+                ],
+            ),
+            (
+                Language::Go,
+                &["s := `a", "b\\` + 1"],
+                &[
+                    (0, 5, String),
+                    (1, 0, String),
+                    (1, 2, String), // Backslash does not escape in raw string
+                    (1, 4, Normal),
+                    (1, 6, Number),
+                ],
+            ),
+        ];
+
+        for (lang, lines, expected) in cases {
+            let input = DummyInputs(vec![ctrl('q')]);
+            let mut editor =
+                Editor::with_lines(lines.iter(), input, Discard, Some((80, 24))).unwrap();
+            editor.set_lang(*lang);
+            editor.edit().unwrap();
+            for (y, x, hl) in expected.iter() {
+                assert_eq!(editor.hl.lines[*y][*x], *hl, "{:?} at ({}, {})", lang, x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn incremental_highlighting() {
+        let lines = [
+            "fn main() {",
+            "    let s = \"a\";",
+            "    // comment",
+            "    let n = 1;",
+            "}",
+        ];
+        let inputs = vec![
+            // Open block comment affecting all following lines
+            vec![key('/'), key('*')],
+            // Open and close block comment, then insert lines
+            vec![key('/'), key('*'), ctrl('n'), ctrl('m'), key('*'), key('/')],
+            // Delete lines
+            vec![
+                ctrl('n'),
+                ctrl('k'),
+                ctrl('k'),
+                ctrl('k'),
+                ctrl('p'),
+                key('"'),
+            ],
+            // Undo changes at separate lines at once
+            vec![
+                key('1'),
+                ctrl('n'),
+                ctrl('n'),
+                ctrl('n'),
+                key('2'),
+                ctrl('u'),
+            ],
+            // Undo and redo multiple lines change
+            vec![
+                key('/'),
+                key('*'),
+                ctrl('n'),
+                ctrl('m'),
+                ctrl('u'),
+                ctrl('r'),
+                ctrl('u'),
+            ],
+        ];
+
+        for mut keys in inputs {
+            keys.push(ctrl('q'));
+            let mut editor = Editor::with_lines(
+                lines.iter(),
+                DummyInputs(keys.clone()),
+                Discard,
+                Some((80, 24)),
+            )
+            .unwrap();
+            editor.set_lang(Language::Rust);
+            editor.edit().unwrap();
+
+            // Highlights must be the same as highlighting all lines from scratch
+            let rows = editor.buf().rows();
+            let mut hl = Highlighting::new(Language::Rust, rows);
+            hl.update(rows, rows.len());
+            assert_eq!(editor.hl.lines, hl.lines, "keys: {:?}", keys);
+        }
+    }
+
+    #[test]
+    fn select_language() {
+        let alt_l = InputSeq::alt(Key(b'l'));
+        let tab = ctrl('i');
+        let input = DummyInputs(vec![
+            alt_l.clone(),
+            key('p'),
+            key('y'),
+            tab.clone(),
+            key('\r'),
+            alt_l.clone(),
+            key('j'),
+            tab.clone(), // Candidates are javascript, julia and json
+            tab.clone(),
+            tab.clone(),
+            tab,
+            key('\r'),
+            ctrl('q'),
+        ]);
+        let mut editor = Editor::new(input, Discard, Some((80, 24))).unwrap();
+        editor.edit().unwrap();
+        assert_eq!(editor.lang(), Language::JavaScript);
+        assert_eq!(
+            editor.screen().message_text(),
+            "Language was set to javascript"
+        );
+
+        let input = DummyInputs(vec![
+            InputSeq::alt(Key(b'l')),
+            key('f'),
+            key('o'),
+            key('o'),
+            key('\r'),
+            ctrl('q'),
+        ]);
+        let mut editor = Editor::new(input, Discard, Some((80, 24))).unwrap();
+        editor.edit().unwrap();
+        assert_eq!(editor.lang(), Language::Plain);
+        assert_eq!(editor.screen().message_text(), "Unknown language \"foo\"");
+    }
+
     #[test]
     fn message_bar_squashed() {
         let input = DummyInputs(vec![ctrl('l'), sp(Unidentified), ctrl('q')]);
@@ -0,0 +1,398 @@
+// EditorConfig support: https://editorconfig.org/
+//
+// Spec: https://editorconfig-specification.readthedocs.io/
+use crate::error::Result;
+use crate::glob;
+use crate::language::Indent;
+use serde::Deserialize;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const CONFIG_FILE_NAME: &str = ".editorconfig";
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IndentStyle {
+    Tab,
+    Space,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IndentSize {
+    Width(usize),
+    Tab, // Same as tab_width
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EndOfLine {
+    Lf,
+    CrLf,
+    Cr,
+}
+
+impl EndOfLine {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            EndOfLine::Lf => "\n",
+            EndOfLine::CrLf => "\r\n",
+            EndOfLine::Cr => "\r",
+        }
+    }
+
+    // Split text into lines. Note that last newline is not considered as start of a new line.
+    // For LF and CRLF, both \n and \r\n are accepted as newline.
+    pub fn lines(self, mut text: &str) -> Vec<&str> {
+        if text.is_empty() {
+            return vec![];
+        }
+        let sep = if self == EndOfLine::Cr { '\r' } else { '\n' };
+        if text.ends_with(sep) {
+            text = &text[..text.len() - 1];
+        }
+        text.split(sep)
+            .map(|l| {
+                if sep == '\n' && l.ends_with('\r') {
+                    &l[..l.len() - 1]
+                } else {
+                    l
+                }
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Charset {
+    Latin1,
+    Utf8,
+    Utf8Bom,
+    Utf16Be,
+    Utf16Le,
+}
+
+const UTF8_BOM: &[u8] = b"\xef\xbb\xbf";
+
+fn invalid_data<E: Into<Box<dyn std::error::Error + Send + Sync>>>(err: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+impl Charset {
+    pub fn decode(self, bytes: Vec<u8>) -> Result<String> {
+        fn utf16<F: Fn([u8; 2]) -> u16>(bytes: &[u8], to_u16: F) -> Result<String> {
+            if !bytes.len().is_multiple_of(2) {
+                return Err(invalid_data("odd length of UTF-16 byte sequence").into());
+            }
+            let units: Vec<_> = bytes
+                .chunks(2)
+                .map(|c| to_u16([c[0], c[1]]))
+                .skip_while(|u| *u == 0xfeff) // Skip BOM
+                .collect();
+            Ok(String::from_utf16(&units).map_err(invalid_data)?)
+        }
+
+        match self {
+            Charset::Latin1 => Ok(bytes.iter().map(|b| *b as char).collect()),
+            Charset::Utf8 => Ok(String::from_utf8(bytes).map_err(invalid_data)?),
+            Charset::Utf8Bom => {
+                let bytes = if bytes.starts_with(UTF8_BOM) {
+                    bytes[UTF8_BOM.len()..].to_vec()
+                } else {
+                    bytes
+                };
+                Ok(String::from_utf8(bytes).map_err(invalid_data)?)
+            }
+            Charset::Utf16Be => utf16(&bytes, u16::from_be_bytes),
+            Charset::Utf16Le => utf16(&bytes, u16::from_le_bytes),
+        }
+    }
+
+    pub fn bom(self) -> &'static [u8] {
+        match self {
+            Charset::Utf8Bom => UTF8_BOM,
+            Charset::Utf16Be => b"\xfe\xff",
+            Charset::Utf16Le => b"\xff\xfe",
+            Charset::Latin1 | Charset::Utf8 => b"",
+        }
+    }
+
+    pub fn encode(self, s: &str, out: &mut Vec<u8>) -> std::result::Result<(), String> {
+        match self {
+            Charset::Latin1 => {
+                for c in s.chars() {
+                    if c as u32 > 0xff {
+                        return Err(format!("Character {:?} cannot be encoded in latin1", c));
+                    }
+                    out.push(c as u8);
+                }
+            }
+            Charset::Utf8 | Charset::Utf8Bom => out.extend_from_slice(s.as_bytes()),
+            Charset::Utf16Be => s
+                .encode_utf16()
+                .for_each(|u| out.extend_from_slice(&u.to_be_bytes())),
+            Charset::Utf16Le => s
+                .encode_utf16()
+                .for_each(|u| out.extend_from_slice(&u.to_le_bytes())),
+        }
+        Ok(())
+    }
+}
+
+// Properties which are supported by Kiro. None means the property is not set
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct EditorConfig {
+    pub indent_style: Option<IndentStyle>,
+    pub indent_size: Option<IndentSize>,
+    pub tab_width: Option<usize>,
+    pub end_of_line: Option<EndOfLine>,
+    pub charset: Option<Charset>,
+    pub trim_trailing_whitespace: Option<bool>,
+    pub insert_final_newline: Option<bool>,
+}
+
+impl EditorConfig {
+    // Collect properties for the file from .editorconfig files in its ancestor directories
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<EditorConfig> {
+        let path = path.as_ref();
+        let path = if path.is_absolute() {
+            path.to_path_buf()
+        } else {
+            env::current_dir()?.join(path)
+        };
+
+        // Nearer .editorconfig has higher priority. Collect them from nearest and apply them in
+        // reverse order.
+        let mut files = vec![];
+        for dir in path.ancestors().skip(1) {
+            let file = dir.join(CONFIG_FILE_NAME);
+            if !file.is_file() {
+                continue;
+            }
+            let content = fs::read_to_string(&file)?;
+            let root = is_root(&content);
+            files.push((dir.to_path_buf(), content));
+            if root {
+                break;
+            }
+        }
+
+        let mut config = EditorConfig::default();
+        for (dir, content) in files.iter().rev() {
+            config.apply_file(content, &relative_path(dir, &path));
+        }
+        Ok(config)
+    }
+
+    fn apply_file(&mut self, content: &str, path: &str) {
+        let name = path.rsplit('/').next().unwrap_or(path);
+        let mut matched = false;
+        for line in content.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+
+            if line.starts_with('[') && line.ends_with(']') {
+                let section = &line[1..line.len() - 1];
+                matched = if section.contains('/') {
+                    glob::matches(section.trim_start_matches('/'), path)
+                } else {
+                    glob::matches(section, name)
+                };
+                continue;
+            }
+
+            if matched {
+                if let Some((key, value)) = line.split_once('=') {
+                    self.set(&key.trim().to_ascii_lowercase(), value.trim());
+                }
+            }
+        }
+    }
+
+    fn set(&mut self, key: &str, value: &str) {
+        fn parse_bool(v: &str) -> Option<bool> {
+            match v {
+                "true" => Some(true),
+                "false" => Some(false),
+                _ => None,
+            }
+        }
+
+        fn parse_width(v: &str) -> Option<usize> {
+            v.parse().ok().filter(|w| *w > 0)
+        }
+
+        // 'unset' and invalid values remove the property
+        let value = value.to_ascii_lowercase();
+        let v = value.as_str();
+        match key {
+            "indent_style" => {
+                self.indent_style = match v {
+                    "tab" => Some(IndentStyle::Tab),
+                    "space" => Some(IndentStyle::Space),
+                    _ => None,
+                }
+            }
+            "indent_size" => {
+                self.indent_size = match v {
+                    "tab" => Some(IndentSize::Tab),
+                    _ => parse_width(v).map(IndentSize::Width),
+                }
+            }
+            "tab_width" => self.tab_width = parse_width(v),
+            "end_of_line" => {
+                self.end_of_line = match v {
+                    "lf" => Some(EndOfLine::Lf),
+                    "crlf" => Some(EndOfLine::CrLf),
+                    "cr" => Some(EndOfLine::Cr),
+                    _ => None,
+                }
+            }
+            "charset" => {
+                self.charset = match v {
+                    "latin1" => Some(Charset::Latin1),
+                    "utf-8" => Some(Charset::Utf8),
+                    "utf-8-bom" => Some(Charset::Utf8Bom),
+                    "utf-16be" => Some(Charset::Utf16Be),
+                    "utf-16le" => Some(Charset::Utf16Le),
+                    _ => None,
+                }
+            }
+            "trim_trailing_whitespace" => self.trim_trailing_whitespace = parse_bool(v),
+            "insert_final_newline" => self.insert_final_newline = parse_bool(v),
+            _ => {}
+        }
+    }
+
+    // Width of tab character. When it is not set, indent_size is used as spec says
+    pub fn tab_width(&self) -> Option<usize> {
+        match (self.tab_width, self.indent_size) {
+            (Some(w), _) | (None, Some(IndentSize::Width(w))) => Some(w),
+            _ => None,
+        }
+    }
+
+    // Indentation configured for the file. `current` is indentation used when the config does not
+    // tell enough information.
+    pub fn indent(&self, current: Indent) -> Option<Indent> {
+        let width = match (self.indent_size, self.indent_style) {
+            (Some(IndentSize::Width(w)), _) => Some(w),
+            (Some(IndentSize::Tab), _) => self.tab_width,
+            // When indent_size is not set, indent_size is tab_width for indent_style=space
+            (None, Some(IndentStyle::Space)) => self.tab_width,
+            (None, _) => None,
+        };
+        match (self.indent_style, width, current) {
+            (Some(IndentStyle::Tab), ..) => Some(Indent::Tab),
+            (Some(IndentStyle::Space), Some(w), _) => Some(Indent::Spaces(w)),
+            (None, Some(w), Indent::Spaces(_)) => Some(Indent::Spaces(w)),
+            // Width is unknown or style is not set. Keep the current indentation
+            (Some(IndentStyle::Space), None, _) | (None, ..) => None,
+        }
+    }
+}
+
+fn is_root(content: &str) -> bool {
+    // 'root' must be put in preamble (before any section)
+    for line in content.lines() {
+        let line = line.trim();
+        if line.starts_with('[') {
+            break;
+        }
+        if let Some((key, value)) = line.split_once('=') {
+            if key.trim().eq_ignore_ascii_case("root") && value.trim().eq_ignore_ascii_case("true")
+            {
+                return true;
+            }
+        }
+    }
+    false
+}
+
+// Path relative to the directory of .editorconfig separated with '/'
+fn relative_path(dir: &Path, path: &Path) -> String {
+    let rel = path
+        .strip_prefix(dir)
+        .map(PathBuf::from)
+        .unwrap_or_default();
+    let parts: Vec<_> = rel.iter().map(|c| c.to_string_lossy()).collect();
+    parts.join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn load_from_ancestors() {
+        let dir = TempDir::new("load-editorconfig");
+        dir.write(".editorconfig", "[*]\ncharset = latin1\n");
+        dir.write(
+            "proj/.editorconfig",
+            "root = true\n\n[*]\nindent_style = tab\ntab_width = 8\n\n[*.md]\ntrim_trailing_whitespace = false\n",
+        );
+        dir.write(
+            "proj/src/.editorconfig",
+            "[*.rs]\nindent_style = space\nindent_size = 4\n\n[/lib.rs]\nend_of_line = crlf\n",
+        );
+
+        // Nearer file takes precedence and files above the root are not read
+        let config = EditorConfig::load(dir.join("proj/src/lib.rs")).unwrap();
+        assert_eq!(
+            config,
+            EditorConfig {
+                indent_style: Some(IndentStyle::Space),
+                indent_size: Some(IndentSize::Width(4)),
+                tab_width: Some(8),
+                end_of_line: Some(EndOfLine::CrLf),
+                ..EditorConfig::default()
+            }
+        );
+
+        let config = EditorConfig::load(dir.join("proj/src/README.md")).unwrap();
+        assert_eq!(config.indent_style, Some(IndentStyle::Tab));
+        assert_eq!(config.trim_trailing_whitespace, Some(false));
+        assert_eq!(config.end_of_line, None);
+
+        let config = EditorConfig::load(dir.join("test.txt")).unwrap();
+        assert_eq!(config.charset, Some(Charset::Latin1));
+        assert_eq!(config.indent_style, None);
+
+        // .editorconfig which is not UTF-8 cannot be read
+        dir.write("proj/src/.editorconfig", b"\xff");
+        assert!(EditorConfig::load(dir.join("proj/src/lib.rs")).is_err());
+    }
+
+    #[test]
+    fn indent_from_config() {
+        for (content, current, expected) in &[
+            ("indent_style = tab", Indent::Spaces(2), Some(Indent::Tab)),
+            (
+                "indent_style = space\nindent_size = 3",
+                Indent::Tab,
+                Some(Indent::Spaces(3)),
+            ),
+            (
+                "indent_style = space\ntab_width = 8",
+                Indent::Tab,
+                Some(Indent::Spaces(8)),
+            ),
+            ("indent_style = space", Indent::Spaces(2), None),
+            ("indent_style = space", Indent::Tab, None),
+            (
+                "indent_size = 3",
+                Indent::Spaces(2),
+                Some(Indent::Spaces(3)),
+            ),
+            ("indent_size = 3", Indent::Tab, None),
+            ("tab_width = 8", Indent::Spaces(2), None),
+        ] {
+            let mut config = EditorConfig::default();
+            config.apply_file(&format!("[*]\n{}", content), "test.txt");
+            assert_eq!(config.indent(*current), *expected, "{:?}", content);
+        }
+    }
+}
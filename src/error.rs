@@ -1,5 +1,6 @@
 use std::fmt;
 use std::io;
+use std::path::PathBuf;
 use std::time::SystemTimeError;
 
 // Deriving Debug is necessary to use .expect() method
@@ -11,6 +12,11 @@ pub enum Error {
     UnknownWindowSize,
     NotUtf8Input(Vec<u8>),
     ControlCharInText(char),
+    InvalidSyntaxFile(PathBuf, String),
+    InvalidConfigFile(PathBuf, String),
+    InvalidThemeFile(PathBuf, String),
+    UnknownBackground(String),
+    UserLanguagesRegisteredTwice,
 }
 
 impl fmt::Display for Error {
@@ -33,6 +39,21 @@ impl fmt::Display for Error {
                 Ok(())
             }
             ControlCharInText(c) => write!(f, "Invalid character for text is included: {:?}", c),
+            InvalidSyntaxFile(path, msg) => {
+                write!(f, "Invalid syntax definition file {:?}: {}", path, msg)
+            }
+            InvalidConfigFile(path, msg) => write!(f, "Invalid config file {:?}: {}", path, msg),
+            InvalidThemeFile(path, msg) => write!(f, "Invalid theme file {:?}: {}", path, msg),
+            UnknownBackground(bg) => {
+                write!(
+                    f,
+                    "Unknown background {:?}. It must be 'dark' or 'light'",
+                    bg
+                )
+            }
+            UserLanguagesRegisteredTwice => {
+                write!(f, "User-defined languages cannot be registered twice")
+            }
         }
     }
 }
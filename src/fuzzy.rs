@@ -0,0 +1,98 @@
+// Fuzzy matching for completing names. Characters of a pattern must appear in a candidate in the
+// same order (e.g. "nb" matches "next-buffer"). Case is ignored.
+
+fn is_separator(c: char) -> bool {
+    !c.is_alphanumeric()
+}
+
+// Score of the text matched to the pattern. Higher is better. Matches at start of words and
+// consecutive matches are preferred. None means the text does not match
+pub fn score(pattern: &str, text: &str) -> Option<usize> {
+    let mut score = 0;
+    let mut chars = text.chars();
+    let mut prev = None; // Previous character in the text
+    let mut adjacent = false; // Previous character in the text was matched
+    for p in pattern.chars() {
+        loop {
+            let c = chars.next()?;
+            if c.to_lowercase().eq(p.to_lowercase()) {
+                score += 1;
+                if prev.is_none_or(is_separator) {
+                    score += 3;
+                }
+                if adjacent {
+                    score += 2;
+                }
+                prev = Some(c);
+                adjacent = true;
+                break;
+            }
+            prev = Some(c);
+            adjacent = false;
+        }
+    }
+    Some(score)
+}
+
+// Candidates matched to the pattern sorted by score. Shorter candidates come first on the same
+// score. Otherwise the original order is kept
+pub fn filter<S, I>(pattern: &str, candidates: I) -> Vec<S>
+where
+    S: AsRef<str>,
+    I: IntoIterator<Item = S>,
+{
+    let mut matched: Vec<_> = candidates
+        .into_iter()
+        .filter_map(|c| score(pattern, c.as_ref()).map(|s| (s, c)))
+        .collect();
+    matched.sort_by_key(|(s, c)| (usize::MAX - s, c.as_ref().len()));
+    matched.into_iter().map(|(_, c)| c).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{filter, score};
+
+    #[test]
+    fn fuzzy_score() {
+        for (pattern, text, matched) in &[
+            ("", "foo", true),
+            ("nb", "next-buffer", true),
+            ("NB", "next-buffer", true),
+            ("bn", "next-buffer", false),
+            ("next-buffer", "next-buffer", true),
+            ("next-buffers", "next-buffer", false),
+            ("ü", "Über", true),
+        ] {
+            assert_eq!(
+                score(pattern, text).is_some(),
+                *matched,
+                "{} {}",
+                pattern,
+                text
+            );
+        }
+
+        // Word starts and consecutive characters are preferred
+        assert!(score("nb", "next-buffer") > score("nb", "number"));
+        assert!(score("buf", "buffer") > score("buf", "bounds-of-file"));
+    }
+
+    #[test]
+    fn fuzzy_filter() {
+        let candidates = &["previous-buffer", "next-buffer", "undo", "bottom-of-buffer"];
+        assert_eq!(
+            filter("buf", candidates.iter().copied()),
+            vec!["next-buffer", "previous-buffer", "bottom-of-buffer"],
+        );
+        assert_eq!(
+            filter("pb", candidates.iter().copied()),
+            vec!["previous-buffer"]
+        );
+        assert_eq!(
+            filter("", candidates.iter().copied()).len(),
+            candidates.len()
+        );
+        assert!(filter("xyz", candidates.iter().copied()).is_empty());
+    }
+}
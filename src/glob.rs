@@ -0,0 +1,198 @@
+// Glob pattern matching for path names. Supported syntax follows EditorConfig:
+//   https://editorconfig-specification.readthedocs.io/#glob-expressions
+//
+//   *          any string except for '/'
+//   **         any string
+//   ?          any single character except for '/'
+//   [abc]      any single character in the set. Ranges such as [a-z] are also available
+//   [!abc]     any single character not in the set
+//   {a,b,c}    any of the comma-separated strings
+//   {n1..n2}   any integer number between n1 and n2
+//   \c         character c literally
+
+pub fn matches(pattern: &str, path: &str) -> bool {
+    let pattern: Vec<_> = pattern.chars().collect();
+    let path: Vec<_> = path.chars().collect();
+    match_chars(&pattern, &path)
+}
+
+fn match_chars(pat: &[char], s: &[char]) -> bool {
+    match pat.first() {
+        None => s.is_empty(),
+        Some('*') if pat.get(1) == Some(&'*') => {
+            let rest = &pat[2..];
+            (0..=s.len()).any(|i| match_chars(rest, &s[i..]))
+        }
+        Some('*') => {
+            let rest = &pat[1..];
+            for i in 0..=s.len() {
+                if match_chars(rest, &s[i..]) {
+                    return true;
+                }
+                if i < s.len() && s[i] == '/' {
+                    break; // '*' does not match to path separator
+                }
+            }
+            false
+        }
+        Some('?') => !s.is_empty() && s[0] != '/' && match_chars(&pat[1..], &s[1..]),
+        Some('[') => {
+            if let Some((matched, len)) = s.first().and_then(|c| match_class(pat, *c)) {
+                matched && match_chars(&pat[len..], &s[1..])
+            } else {
+                match_literal('[', pat, s)
+            }
+        }
+        Some('{') => {
+            if let Some(end) = find_close_brace(pat) {
+                match_braces(&pat[1..end], &pat[end + 1..], s)
+            } else {
+                match_literal('{', pat, s)
+            }
+        }
+        Some('\\') if pat.len() > 1 => match_literal(pat[1], &pat[1..], s),
+        Some(c) => match_literal(*c, pat, s),
+    }
+}
+
+fn match_literal(c: char, pat: &[char], s: &[char]) -> bool {
+    s.first() == Some(&c) && match_chars(&pat[1..], &s[1..])
+}
+
+// Returns whether the character is matched to the class and length of the class pattern.
+// None means the pattern is not a valid character class.
+fn match_class(pat: &[char], c: char) -> Option<(bool, usize)> {
+    let mut i = 1;
+    let negated = pat.get(i) == Some(&'!');
+    if negated {
+        i += 1;
+    }
+
+    let mut matched = false;
+    let start = i;
+    while let Some(&p) = pat.get(i) {
+        if p == ']' && i > start {
+            return Some((matched != negated, i + 1));
+        }
+        if pat.get(i + 1) == Some(&'-') && pat.get(i + 2).map(|e| *e != ']') == Some(true) {
+            matched |= p <= c && c <= pat[i + 2];
+            i += 3;
+        } else {
+            matched |= p == c;
+            i += 1;
+        }
+    }
+
+    None
+}
+
+fn find_close_brace(pat: &[char]) -> Option<usize> {
+    let mut depth = 0;
+    let mut escaped = false;
+    for (i, c) in pat.iter().enumerate() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+fn split_alternatives(inner: &[char]) -> Vec<&[char]> {
+    let mut alts = vec![];
+    let mut depth = 0;
+    let mut escaped = false;
+    let mut start = 0;
+    for (i, c) in inner.iter().enumerate() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            ',' if depth == 0 => {
+                alts.push(&inner[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    alts.push(&inner[start..]);
+    alts
+}
+
+fn parse_num_range(inner: &[char]) -> Option<(i64, i64)> {
+    let inner: String = inner.iter().collect();
+    let (lhs, rhs) = inner.split_once("..")?;
+    let (lhs, rhs) = (lhs.parse::<i64>().ok()?, rhs.parse::<i64>().ok()?);
+    Some((lhs.min(rhs), lhs.max(rhs)))
+}
+
+fn match_braces(inner: &[char], rest: &[char], s: &[char]) -> bool {
+    if let Some((min, max)) = parse_num_range(inner) {
+        let sign = if s.first() == Some(&'-') { 1 } else { 0 };
+        let digits = s[sign..].iter().take_while(|c| c.is_ascii_digit()).count();
+        return (sign + 1..=sign + digits).any(|end| {
+            let num: String = s[..end].iter().collect();
+            match num.parse::<i64>() {
+                Ok(n) => min <= n && n <= max && match_chars(rest, &s[end..]),
+                Err(_) => false,
+            }
+        });
+    }
+
+    let alts = split_alternatives(inner);
+    if alts.len() == 1 {
+        // '{single}' is not a brace expansion. Match it literally
+        let mut pat = vec!['\\', '{'];
+        pat.extend_from_slice(inner);
+        pat.extend_from_slice(&['\\', '}']);
+        pat.extend_from_slice(rest);
+        return match_chars(&pat, s);
+    }
+
+    alts.iter().any(|alt| {
+        let mut pat = alt.to_vec();
+        pat.extend_from_slice(rest);
+        match_chars(&pat, s)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::matches;
+
+    #[test]
+    fn glob_patterns() {
+        for (pat, path, expected) in &[
+            ("*", "foo.rs", true),
+            ("*.rs", "foo.rs", true),
+            ("*.rs", "foo.rs.bak", false),
+            ("*.rs", "src/foo.rs", false),
+            ("**.rs", "src/foo.rs", true),
+            ("src/**/*.rs", "src/a/b/foo.rs", true),
+            ("?.c", "a.c", true),
+            ("?.c", "ab.c", false),
+            ("[abc].c", "b.c", true),
+            ("[!abc].c", "b.c", false),
+            ("[a-z].c", "x.c", true),
+            ("*.{js,ts}", "foo.ts", true),
+            ("*.{js,ts}", "foo.rs", false),
+            ("{Makefile,*.mk}", "Makefile", true),
+            ("file{1..3}.txt", "file2.txt", true),
+            ("file{1..3}.txt", "file4.txt", false),
+            ("{single}", "{single}", true),
+            ("\\*.c", "*.c", true),
+            ("\\*.c", "a.c", false),
+        ] {
+            assert_eq!(matches(pat, path), *expected, "{:?} {:?}", pat, path);
+        }
+    }
+}
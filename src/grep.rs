@@ -0,0 +1,132 @@
+// Search text in files of a project without external commands. Each match is formatted as
+// 'path:line: text' so that the location can be parsed back from a line of the results buffer.
+//
+// Pattern surrounded with slashes such as '/fo+/' is a regular expression. Otherwise the pattern
+// is searched literally.
+use crate::project;
+use regex::Regex;
+use std::fs;
+use std::path::Path;
+
+// Stop searching when too many lines matched
+const MAX_MATCHES: usize = 10_000;
+
+pub enum Pattern {
+    Literal(String),
+    Regex(Regex),
+}
+
+impl Pattern {
+    pub fn parse(input: &str) -> Result<Pattern, String> {
+        match input.strip_prefix('/').and_then(|s| s.strip_suffix('/')) {
+            Some(re) if !re.is_empty() => Regex::new(re)
+                .map(Pattern::Regex)
+                .map_err(|e| format!("Invalid regular expression {:?}: {}", re, e)),
+            _ if input.is_empty() => Err("Empty pattern".to_string()),
+            _ => Ok(Pattern::Literal(input.to_string())),
+        }
+    }
+
+    fn is_match(&self, line: &str) -> bool {
+        match self {
+            Pattern::Literal(s) => line.contains(s.as_str()),
+            Pattern::Regex(re) => re.is_match(line),
+        }
+    }
+}
+
+// Control characters cannot be put in text buffer
+fn sanitize(line: &str) -> String {
+    line.chars()
+        .map(|c| if c.is_control() && c != '\t' { '?' } else { c })
+        .collect()
+}
+
+// Lines matched to the pattern in files under the root directory. Files which are not UTF-8 text
+// are skipped
+pub fn search<P: AsRef<Path>>(root: P, pattern: &Pattern) -> Vec<String> {
+    let root = root.as_ref();
+    let mut matches = vec![];
+    for file in project::files(root) {
+        let content = match fs::read(root.join(&file)).map(String::from_utf8) {
+            Ok(Ok(content)) if !content.contains('\0') => content,
+            _ => continue,
+        };
+        for (idx, line) in content.lines().enumerate() {
+            if pattern.is_match(line) {
+                matches.push(format!("{}:{}: {}", file, idx + 1, sanitize(line)));
+                if matches.len() >= MAX_MATCHES {
+                    return matches;
+                }
+            }
+        }
+    }
+    matches
+}
+
+// Parse file path and line number (1-based) from a line of search results
+pub fn parse_location(line: &str) -> Option<(&str, usize)> {
+    // File path may contain ':'. Find the first ':{digits}: ' part
+    line.match_indices(':').find_map(|(i, _)| {
+        let (path, rest) = (&line[..i], &line[i + 1..]);
+        let (num, _) = rest.split_once(": ")?;
+        match num.parse() {
+            Ok(n) if n > 0 && !path.is_empty() => Some((path, n)),
+            _ => None,
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn parse_pattern() {
+        assert!(matches!(Pattern::parse("foo"), Ok(Pattern::Literal(s)) if s == "foo"));
+        assert!(matches!(Pattern::parse("/fo+/"), Ok(Pattern::Regex(_))));
+        assert!(matches!(Pattern::parse("/"), Ok(Pattern::Literal(s)) if s == "/"));
+        assert!(matches!(Pattern::parse("//"), Ok(Pattern::Literal(s)) if s == "//"));
+        assert!(Pattern::parse("").is_err());
+        assert!(Pattern::parse("/(/").is_err());
+    }
+
+    #[test]
+    fn search_files() {
+        let root = TempDir::new("grep-search");
+        root.write("README.md", "foo\nbar\nfoooo\n");
+        root.write("src/lib.rs", "// foo\r\nfn f() {}\r\n");
+        root.write("binary", b"foo\0");
+        root.write("latin1", b"foo\xff");
+        root.write(".gitignore", "*.log\n");
+        root.write("a.log", "foo\n");
+
+        let pat = Pattern::parse("foo").unwrap();
+        assert_eq!(
+            search(&root, &pat),
+            vec![
+                "README.md:1: foo",
+                "README.md:3: foooo",
+                "src/lib.rs:1: // foo"
+            ],
+        );
+        let pat = Pattern::parse("/^fo{3,}$/").unwrap();
+        assert_eq!(search(&root, &pat), vec!["README.md:3: foooo"]);
+        let pat = Pattern::parse("nothing").unwrap();
+        assert!(search(&root, &pat).is_empty());
+    }
+
+    #[test]
+    fn parse_result_location() {
+        assert_eq!(
+            parse_location("src/lib.rs:12: foo"),
+            Some(("src/lib.rs", 12))
+        );
+        assert_eq!(parse_location("a:b.txt:3: x: 4: y"), Some(("a:b.txt", 3)));
+        assert_eq!(parse_location("foo.txt:3: "), Some(("foo.txt", 3)));
+        assert_eq!(parse_location("foo.txt:0: x"), None);
+        assert_eq!(parse_location("foo.txt: x"), None);
+        assert_eq!(parse_location("Found 3 matches"), None);
+    }
+}
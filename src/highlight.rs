@@ -1,10 +1,14 @@
+use std::cmp;
 use std::iter;
+use std::ops::Range;
 
+use crate::edit_diff::DirtyLines;
 use crate::language::Language;
 use crate::row::Row;
 use crate::term_color::Color;
+use crate::user_syntax::{leak_str, leak_strs, SyntaxDefinition};
 
-#[derive(Clone, Copy, PartialEq, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
 pub enum Highlight {
     Normal,
     Number,
@@ -19,6 +23,7 @@ pub enum Highlight {
     SpecialVar,
     Search,
     Match,
+    Bracket,
 }
 
 impl Highlight {
@@ -39,13 +44,29 @@ impl Highlight {
             SpecialVar => Cyan,
             Search => OrangeBg,
             Match => YellowBg,
+            Bracket => GrayBg,
         }
     }
 }
 
-struct SyntaxHighlight {
+// Delimiters of string literal which may span multiple lines such as """...""" in Python
+struct StringDelim {
+    start: &'static str,
+    end: &'static str,
+    // Whether a backslash escapes the next character
+    escape: bool,
+}
+
+impl StringDelim {
+    const fn new(start: &'static str, end: &'static str, escape: bool) -> Self {
+        Self { start, end, escape }
+    }
+}
+
+pub struct SyntaxHighlight {
     lang: Language,
     string_quotes: &'static [char],
+    multiline_strings: &'static [StringDelim],
     number: bool,
     hex_number: bool,
     bin_number: bool,
@@ -59,6 +80,16 @@ struct SyntaxHighlight {
     boolean_constants: &'static [&'static str],
     special_vars: &'static [&'static str],
     definition_keywords: &'static [&'static str],
+    // Variable expansions such as $FOO or ${FOO}
+    variables: bool,
+    // Separator of a key at head of line such as ':' in YAML or '=' in TOML
+    key_separator: Option<char>,
+    // Table headers such as [table] in TOML
+    table_header: bool,
+    // Headings such as '# Title' in Markdown
+    heading: bool,
+    // Delimiter of fenced code blocks such as ``` in Markdown
+    code_fence: Option<&'static str>,
 }
 
 const PLAIN_SYNTAX: SyntaxHighlight = SyntaxHighlight {
@@ -68,6 +99,7 @@ const PLAIN_SYNTAX: SyntaxHighlight = SyntaxHighlight {
     bin_number: false,
     number_delim: None,
     string_quotes: &[],
+    multiline_strings: &[],
     character: false,
     line_comment: None,
     block_comment: None,
@@ -77,6 +109,11 @@ const PLAIN_SYNTAX: SyntaxHighlight = SyntaxHighlight {
     boolean_constants: &[],
     special_vars: &[],
     definition_keywords: &[],
+    variables: false,
+    key_separator: None,
+    table_header: false,
+    heading: false,
+    code_fence: None,
 };
 
 const C_SYNTAX: SyntaxHighlight = SyntaxHighlight {
@@ -86,6 +123,7 @@ const C_SYNTAX: SyntaxHighlight = SyntaxHighlight {
     bin_number: false,
     number_delim: None,
     string_quotes: &['"'],
+    multiline_strings: &[],
     character: true,
     line_comment: Some("//"),
     block_comment: Some(("/*", "*/")),
@@ -103,6 +141,11 @@ const C_SYNTAX: SyntaxHighlight = SyntaxHighlight {
     boolean_constants: &[],
     special_vars: &[],
     definition_keywords: &["enum", "struct", "union"],
+    variables: false,
+    key_separator: None,
+    table_header: false,
+    heading: false,
+    code_fence: None,
 };
 
 const RUST_SYNTAX: SyntaxHighlight = SyntaxHighlight {
@@ -112,6 +155,16 @@ const RUST_SYNTAX: SyntaxHighlight = SyntaxHighlight {
     bin_number: true,
     number_delim: Some('_'),
     string_quotes: &['"'],
+    multiline_strings: &[
+        StringDelim::new("r\"", "\"", false),
+        StringDelim::new("r#\"", "\"#", false),
+        StringDelim::new("r##\"", "\"##", false),
+        StringDelim::new("r###\"", "\"###", false),
+        StringDelim::new("br\"", "\"", false),
+        StringDelim::new("br#\"", "\"#", false),
+        StringDelim::new("br##\"", "\"##", false),
+        StringDelim::new("br###\"", "\"###", false),
+    ],
     character: true,
     line_comment: Some("//"),
     block_comment: Some(("/*", "*/")),
@@ -133,6 +186,11 @@ const RUST_SYNTAX: SyntaxHighlight = SyntaxHighlight {
     definition_keywords: &[
         "fn", "let", "const", "mod", "struct", "enum", "trait", "union",
     ],
+    variables: false,
+    key_separator: None,
+    table_header: false,
+    heading: false,
+    code_fence: None,
 };
 
 const JAVASCRIPT_SYNTAX: SyntaxHighlight = SyntaxHighlight {
@@ -142,6 +200,7 @@ const JAVASCRIPT_SYNTAX: SyntaxHighlight = SyntaxHighlight {
     bin_number: false,
     number_delim: None,
     string_quotes: &['"', '\''],
+    multiline_strings: &[StringDelim::new("`", "`", true)],
     character: false,
     line_comment: Some("//"),
     block_comment: Some(("/*", "*/")),
@@ -209,6 +268,11 @@ const JAVASCRIPT_SYNTAX: SyntaxHighlight = SyntaxHighlight {
     boolean_constants: &["true", "false"],
     special_vars: &["null", "this", "undefined"],
     definition_keywords: &["class", "const", "function", "var", "let"],
+    variables: false,
+    key_separator: None,
+    table_header: false,
+    heading: false,
+    code_fence: None,
 };
 
 const GO_SYNTAX: SyntaxHighlight = SyntaxHighlight {
@@ -217,7 +281,8 @@ const GO_SYNTAX: SyntaxHighlight = SyntaxHighlight {
     hex_number: true,
     bin_number: true,
     number_delim: Some('_'),
-    string_quotes: &['"', '`'],
+    string_quotes: &['"'],
+    multiline_strings: &[StringDelim::new("`", "`", false)],
     character: true,
     line_comment: Some("//"),
     block_comment: Some(("/*", "*/")),
@@ -283,6 +348,11 @@ const GO_SYNTAX: SyntaxHighlight = SyntaxHighlight {
         "type",
         "var",
     ],
+    variables: false,
+    key_separator: None,
+    table_header: false,
+    heading: false,
+    code_fence: None,
 };
 
 const CPP_SYNTAX: SyntaxHighlight = SyntaxHighlight {
@@ -292,6 +362,7 @@ const CPP_SYNTAX: SyntaxHighlight = SyntaxHighlight {
     bin_number: true,
     number_delim: Some('\''),
     string_quotes: &['"'],
+    multiline_strings: &[StringDelim::new("R\"(", ")\"", false)],
     character: true,
     line_comment: Some("//"),
     block_comment: Some(("/*", "*/")),
@@ -388,6 +459,11 @@ const CPP_SYNTAX: SyntaxHighlight = SyntaxHighlight {
         "union",
         "module",
     ],
+    variables: false,
+    key_separator: None,
+    table_header: false,
+    heading: false,
+    code_fence: None,
 };
 
 const PYTHON_SYNTAX: SyntaxHighlight = SyntaxHighlight {
@@ -396,7 +472,11 @@ const PYTHON_SYNTAX: SyntaxHighlight = SyntaxHighlight {
     hex_number: true,
     bin_number: true,
     number_delim: Some('_'),
-    string_quotes: &['"', '\''], // TODO: Multi-line strings '''...'''
+    string_quotes: &['"', '\''],
+    multiline_strings: &[
+        StringDelim::new("\"\"\"", "\"\"\"", true),
+        StringDelim::new("'''", "'''", true),
+    ],
     character: false,
     line_comment: Some("#"),
     block_comment: None,
@@ -426,6 +506,11 @@ const PYTHON_SYNTAX: SyntaxHighlight = SyntaxHighlight {
     boolean_constants: &["True", "False"],
     special_vars: &["self", "None"],
     definition_keywords: &["def", "class", "global", "nonlocal"],
+    variables: false,
+    key_separator: None,
+    table_header: false,
+    heading: false,
+    code_fence: None,
 };
 
 const JULIA_SYNTAX: SyntaxHighlight = SyntaxHighlight {
@@ -434,7 +519,8 @@ const JULIA_SYNTAX: SyntaxHighlight = SyntaxHighlight {
     hex_number: true,
     bin_number: true,
     number_delim: Some('_'),
-    string_quotes: &['"'], // TODO: Multi-line strings """..."""
+    string_quotes: &['"'],
+    multiline_strings: &[StringDelim::new("\"\"\"", "\"\"\"", true)],
     character: false,
     line_comment: Some("#"),
     block_comment: Some(("#=", "=#")),
@@ -504,9 +590,246 @@ const JULIA_SYNTAX: SyntaxHighlight = SyntaxHighlight {
     boolean_constants: &["true", "false"],
     special_vars: &["in", "isa", "where", "nothing", "missing"],
     definition_keywords: &["def", "class", "global", "nonlocal"],
+    variables: false,
+    key_separator: None,
+    table_header: false,
+    heading: false,
+    code_fence: None,
+};
+
+const SHELL_SYNTAX: SyntaxHighlight = SyntaxHighlight {
+    lang: Language::Shell,
+    number: true,
+    hex_number: false,
+    bin_number: false,
+    number_delim: None,
+    string_quotes: &['"', '\''],
+    multiline_strings: &[],
+    character: false,
+    line_comment: Some("#"),
+    block_comment: None,
+    keywords: &[
+        "alias", "declare", "eval", "exec", "export", "function", "local", "readonly", "set",
+        "shift", "source", "trap", "typeset", "unset",
+    ],
+    control_statements: &[
+        "break", "case", "continue", "do", "done", "elif", "else", "esac", "exit", "fi", "for",
+        "if", "in", "return", "select", "then", "until", "while",
+    ],
+    builtin_types: &[],
+    boolean_constants: &["true", "false"],
+    special_vars: &[],
+    definition_keywords: &["function"],
+    variables: true,
+    key_separator: None,
+    table_header: false,
+    heading: false,
+    code_fence: None,
+};
+
+const MAKE_SYNTAX: SyntaxHighlight = SyntaxHighlight {
+    lang: Language::Make,
+    number: false,
+    hex_number: false,
+    bin_number: false,
+    number_delim: None,
+    string_quotes: &['"', '\''],
+    multiline_strings: &[],
+    character: false,
+    line_comment: Some("#"),
+    block_comment: None,
+    keywords: &[
+        "define", "endef", "export", "include", "override", "private", "sinclude", "unexport",
+        "vpath",
+    ],
+    control_statements: &["else", "endif", "ifdef", "ifeq", "ifndef", "ifneq"],
+    builtin_types: &[],
+    boolean_constants: &[],
+    special_vars: &[],
+    definition_keywords: &["define"],
+    variables: false,
+    key_separator: None,
+    table_header: false,
+    heading: false,
+    code_fence: None,
+};
+
+const DOCKERFILE_SYNTAX: SyntaxHighlight = SyntaxHighlight {
+    lang: Language::Dockerfile,
+    number: true,
+    hex_number: false,
+    bin_number: false,
+    number_delim: None,
+    string_quotes: &['"', '\''],
+    multiline_strings: &[],
+    character: false,
+    line_comment: Some("#"),
+    block_comment: None,
+    keywords: &[
+        "ADD",
+        "ARG",
+        "AS",
+        "CMD",
+        "COPY",
+        "ENTRYPOINT",
+        "ENV",
+        "EXPOSE",
+        "FROM",
+        "HEALTHCHECK",
+        "LABEL",
+        "MAINTAINER",
+        "ONBUILD",
+        "RUN",
+        "SHELL",
+        "STOPSIGNAL",
+        "USER",
+        "VOLUME",
+        "WORKDIR",
+    ],
+    control_statements: &[],
+    builtin_types: &[],
+    boolean_constants: &[],
+    special_vars: &[],
+    definition_keywords: &[],
+    variables: true,
+    key_separator: None,
+    table_header: false,
+    heading: false,
+    code_fence: None,
+};
+
+const TOML_SYNTAX: SyntaxHighlight = SyntaxHighlight {
+    lang: Language::Toml,
+    number: true,
+    hex_number: true,
+    bin_number: true,
+    number_delim: Some('_'),
+    string_quotes: &['"', '\''],
+    multiline_strings: &[
+        StringDelim::new("\"\"\"", "\"\"\"", true),
+        StringDelim::new("'''", "'''", false),
+    ],
+    character: false,
+    line_comment: Some("#"),
+    block_comment: None,
+    keywords: &[],
+    control_statements: &[],
+    builtin_types: &[],
+    boolean_constants: &["true", "false"],
+    special_vars: &["inf", "nan"],
+    definition_keywords: &[],
+    variables: false,
+    key_separator: Some('='),
+    table_header: true,
+    heading: false,
+    code_fence: None,
+};
+
+const YAML_SYNTAX: SyntaxHighlight = SyntaxHighlight {
+    lang: Language::Yaml,
+    number: true,
+    hex_number: true,
+    bin_number: false,
+    number_delim: None,
+    string_quotes: &['"', '\''],
+    multiline_strings: &[],
+    character: false,
+    line_comment: Some("#"),
+    block_comment: None,
+    keywords: &[],
+    control_statements: &[],
+    builtin_types: &[],
+    boolean_constants: &["true", "false", "True", "False", "TRUE", "FALSE"],
+    special_vars: &["null", "Null", "NULL", "~"],
+    definition_keywords: &[],
+    variables: false,
+    key_separator: Some(':'),
+    table_header: false,
+    heading: false,
+    code_fence: None,
+};
+
+const MARKDOWN_SYNTAX: SyntaxHighlight = SyntaxHighlight {
+    lang: Language::Markdown,
+    number: false,
+    hex_number: false,
+    bin_number: false,
+    number_delim: None,
+    string_quotes: &['`'],
+    multiline_strings: &[],
+    character: false,
+    line_comment: None,
+    block_comment: Some(("<!--", "-->")),
+    keywords: &[],
+    control_statements: &[],
+    builtin_types: &[],
+    boolean_constants: &[],
+    special_vars: &[],
+    definition_keywords: &[],
+    variables: false,
+    key_separator: None,
+    table_header: false,
+    heading: true,
+    code_fence: Some("```"),
+};
+
+const JSON_SYNTAX: SyntaxHighlight = SyntaxHighlight {
+    lang: Language::Json,
+    number: true,
+    hex_number: false,
+    bin_number: false,
+    number_delim: None,
+    string_quotes: &['"'],
+    multiline_strings: &[],
+    character: false,
+    line_comment: None,
+    block_comment: None,
+    keywords: &[],
+    control_statements: &[],
+    builtin_types: &[],
+    boolean_constants: &["true", "false"],
+    special_vars: &["null"],
+    definition_keywords: &[],
+    variables: false,
+    key_separator: Some(':'),
+    table_header: false,
+    heading: false,
+    code_fence: None,
 };
 
 impl SyntaxHighlight {
+    pub fn from_definition(lang: Language, def: SyntaxDefinition) -> SyntaxHighlight {
+        SyntaxHighlight {
+            lang,
+            string_quotes: Box::leak(def.string_quotes.into_boxed_slice()),
+            multiline_strings: Box::leak(
+                def.multiline_strings
+                    .into_iter()
+                    .map(|d| StringDelim::new(leak_str(d.start), leak_str(d.end), d.escape))
+                    .collect::<Vec<_>>()
+                    .into_boxed_slice(),
+            ),
+            number: def.number,
+            hex_number: def.hex_number,
+            bin_number: def.bin_number,
+            number_delim: def.number_delim,
+            character: def.character,
+            line_comment: def.line_comment.map(leak_str),
+            block_comment: def.block_comment.map(|(s, e)| (leak_str(s), leak_str(e))),
+            keywords: leak_strs(def.keywords),
+            control_statements: leak_strs(def.control_statements),
+            builtin_types: leak_strs(def.builtin_types),
+            boolean_constants: leak_strs(def.boolean_constants),
+            special_vars: leak_strs(def.special_vars),
+            definition_keywords: leak_strs(def.definition_keywords),
+            variables: def.variables,
+            key_separator: def.key_separator,
+            table_header: def.table_header,
+            heading: def.heading,
+            code_fence: def.code_fence.map(leak_str),
+        }
+    }
+
     fn for_lang(lang: Language) -> &'static SyntaxHighlight {
         use Language::*;
         match lang {
@@ -518,6 +841,14 @@ impl SyntaxHighlight {
             Cpp => &CPP_SYNTAX,
             Python => &PYTHON_SYNTAX,
             Julia => &JULIA_SYNTAX,
+            Shell => &SHELL_SYNTAX,
+            Make => &MAKE_SYNTAX,
+            Dockerfile => &DOCKERFILE_SYNTAX,
+            Toml => &TOML_SYNTAX,
+            Yaml => &YAML_SYNTAX,
+            Markdown => &MARKDOWN_SYNTAX,
+            Json => &JSON_SYNTAX,
+            Custom(idx) => &Language::user_language(idx).syntax,
         }
     }
 }
@@ -538,12 +869,58 @@ fn is_sep(c: char) -> bool {
     c.is_ascii_whitespace() || (c.is_ascii_punctuation() && c != '_') || c == '\0'
 }
 
+// Byte length of a key at head of the input followed by the separator such as 'key: value'
+fn key_len(input: &str, sep: char) -> Option<usize> {
+    let mut chars = input.char_indices();
+    let len = match chars.next()? {
+        (_, q) if q == '"' || q == '\'' => {
+            let mut escaped = false;
+            let (end, _) = chars.find(|&(_, c)| {
+                let found = !escaped && c == q;
+                escaped = !escaped && c == '\\';
+                found
+            })?;
+            end + 1
+        }
+        _ => {
+            let end = input.find(sep)?;
+            let key = input[..end].trim_end();
+            let is_key_char = |c: char| c.is_alphanumeric() || "_-. ".contains(c);
+            if key.is_empty() || !key.chars().all(is_key_char) {
+                return None;
+            }
+            key.len()
+        }
+    };
+
+    let rest = input[len..].trim_start().strip_prefix(sep)?;
+    // Colon after unquoted key must be followed by whitespace in YAML (e.g. 'http://...' is not a key)
+    let quoted = input.starts_with(['"', '\'']);
+    if sep == ':' && !quoted && !rest.is_empty() && !rest.starts_with(char::is_whitespace) {
+        return None;
+    }
+    Some(len)
+}
+
+// States carried from end of line to the next line
+#[derive(Clone, Copy, PartialEq, Default)]
+struct LineState {
+    prev_quote: Option<char>,
+    in_string: Option<usize>,
+    in_block_comment: bool,
+    in_code_fence: bool,
+}
+
 struct Highlighter<'a> {
     syntax: &'a SyntaxHighlight,
     prev_quote: Option<char>,
+    in_string: Option<usize>, // Index of multiline_strings
     in_block_comment: bool,
+    in_code_fence: bool,
     prev_hl: Highlight,
     prev_char: char,
+    // Last non-whitespace character in the line
+    prev_non_space: char,
     num: NumLit,
     after_def_keyword: bool,
 }
@@ -553,14 +930,35 @@ impl<'a> Highlighter<'a> {
         Self {
             syntax,
             prev_quote: None,
+            in_string: None,
             in_block_comment: false,
+            in_code_fence: false,
             prev_hl: Highlight::Normal,
             prev_char: '\0',
+            prev_non_space: '\0',
             num: NumLit::Digit,
             after_def_keyword: false,
         }
     }
 
+    fn with_state<'b: 'a>(syntax: &'b SyntaxHighlight, state: LineState) -> Self {
+        let mut highlighter = Self::new(syntax);
+        highlighter.prev_quote = state.prev_quote;
+        highlighter.in_string = state.in_string;
+        highlighter.in_block_comment = state.in_block_comment;
+        highlighter.in_code_fence = state.in_code_fence;
+        highlighter
+    }
+
+    fn state(&self) -> LineState {
+        LineState {
+            prev_quote: self.prev_quote,
+            in_string: self.in_string,
+            in_block_comment: self.in_block_comment,
+            in_code_fence: self.in_code_fence,
+        }
+    }
+
     fn eat_n(
         &mut self,
         out: &mut [Highlight],
@@ -577,6 +975,14 @@ impl<'a> Highlighter<'a> {
         }
         self.prev_hl = hl;
         self.prev_char = input.chars().nth(len - 1).unwrap();
+        if let Some(c) = input
+            .chars()
+            .take(len)
+            .filter(|c| !c.is_whitespace())
+            .last()
+        {
+            self.prev_non_space = c;
+        }
         ParseStep::Ahead(len)
     }
 
@@ -584,6 +990,9 @@ impl<'a> Highlighter<'a> {
         out[0] = hl;
         self.prev_hl = hl;
         self.prev_char = c;
+        if !c.is_whitespace() {
+            self.prev_non_space = c;
+        }
         ParseStep::Ahead(1)
     }
 
@@ -641,7 +1050,7 @@ impl<'a> Highlighter<'a> {
                 self.prev_quote = None;
             }
             Some(self.eat_one(out, c, Highlight::String))
-        } else if self.syntax.string_quotes.contains(&c) {
+        } else if self.syntax.string_quotes.contains(&c) && self.can_start_string() {
             self.prev_quote = Some(c);
             Some(self.eat_one(out, c, Highlight::String))
         } else {
@@ -649,6 +1058,85 @@ impl<'a> Highlighter<'a> {
         }
     }
 
+    fn can_start_string(&self) -> bool {
+        if self.syntax.lang != Language::Yaml {
+            return true;
+        }
+        // Quotes in YAML start a string only at start of value. Otherwise they are part of plain
+        // scalar such as "name: don't"
+        match self.prev_char {
+            '\0' | '[' | '{' | ',' => true,
+            c if c.is_whitespace() => matches!(
+                self.prev_non_space,
+                '\0' | ':' | '-' | '?' | '[' | '{' | ','
+            ),
+            _ => false,
+        }
+    }
+
+    fn highlight_variable(&mut self, out: &mut [Highlight], input: &str) -> Option<ParseStep> {
+        // Variables are expanded in double-quoted strings
+        if !matches!(self.prev_quote, None | Some('"')) || self.prev_char == '\\' {
+            return None;
+        }
+        let rest = input.strip_prefix('$')?;
+        let len = if rest.starts_with('{') {
+            rest.find('}')? + 1
+        } else {
+            match rest.find(|c: char| !c.is_ascii_alphanumeric() && c != '_') {
+                Some(0) if rest.starts_with(|c: char| "@*#?$!-".contains(c)) => 1, // e.g. $@
+                Some(0) => return None,
+                Some(idx) => idx,
+                None if rest.is_empty() => return None,
+                None => rest.len(),
+            }
+        };
+        // '$' and variable name consist of ASCII characters so byte length equals to char count
+        Some(self.eat_n(out, input, Highlight::SpecialVar, len + 1))
+    }
+
+    fn highlight_in_multiline_string(
+        &mut self,
+        delim: &StringDelim,
+        c: char,
+        out: &mut [Highlight],
+        input: &str,
+    ) -> ParseStep {
+        if delim.escape && c == '\\' {
+            // Eat escaped character together not to treat it as end of string
+            let len = input.chars().take(2).count();
+            return self.eat_n(out, input, Highlight::String, len);
+        }
+        if input.starts_with(delim.end) {
+            self.in_string = None;
+            return self.eat_n(out, input, Highlight::String, delim.end.chars().count());
+        }
+        self.eat_one(out, c, Highlight::String)
+    }
+
+    fn highlight_multiline_string_start(
+        &mut self,
+        out: &mut [Highlight],
+        input: &str,
+    ) -> Option<ParseStep> {
+        if self.prev_quote.is_some() {
+            return None;
+        }
+        // Prefix such as r in r"..." must be at start of word
+        let is_bound = is_sep(self.prev_char);
+        let (idx, delim) = self
+            .syntax
+            .multiline_strings
+            .iter()
+            .enumerate()
+            .find(|(_, d)| {
+                input.starts_with(d.start)
+                    && (is_bound || !d.start.starts_with(char::is_alphanumeric))
+            })?;
+        self.in_string = Some(idx);
+        Some(self.eat_n(out, input, Highlight::String, delim.start.chars().count()))
+    }
+
     fn highlight_ident(&mut self, out: &mut [Highlight], input: &str) -> Option<ParseStep> {
         fn lex_ident(mut input: &str) -> Option<&str> {
             for (i, c) in input.char_indices() {
@@ -668,7 +1156,7 @@ impl<'a> Highlighter<'a> {
         fn iter_words<'a>(
             words: &'a [&'a str],
             hl: Highlight,
-        ) -> impl Iterator<Item = (&&'a str, Highlight)> {
+        ) -> impl Iterator<Item = (&'a &'a str, Highlight)> {
             words.iter().zip(iter::repeat(hl))
         }
 
@@ -682,12 +1170,10 @@ impl<'a> Highlighter<'a> {
                 .chain(iter_words(self.syntax.special_vars, SpecialVar))
                 .find(|(k, _)| *k == ident);
 
-            let definition = keyword.or_else(|| {
-                if self.after_def_keyword {
-                    Some((ident, Highlight::Definition))
-                } else {
-                    None
-                }
+            let definition = keyword.or(if self.after_def_keyword {
+                Some((ident, Highlight::Definition))
+            } else {
+                None
             });
 
             if keyword.is_some() && self.syntax.definition_keywords.contains(ident) {
@@ -788,6 +1274,16 @@ impl<'a> Highlighter<'a> {
             };
         }
 
+        if let Some(idx) = self.in_string {
+            let syntax = self.syntax;
+            return self.highlight_in_multiline_string(
+                &syntax.multiline_strings[idx],
+                c,
+                out,
+                input,
+            );
+        }
+
         if let Some((comment_start, comment_end)) = self.syntax.block_comment {
             try_highlight!(self.highlight_block_comment(comment_start, comment_end, c, out, input));
         }
@@ -796,10 +1292,18 @@ impl<'a> Highlighter<'a> {
             try_highlight!(self.highlight_line_comment(comment_leader, out, input));
         }
 
+        if !self.syntax.multiline_strings.is_empty() {
+            try_highlight!(self.highlight_multiline_string_start(out, input));
+        }
+
         if self.syntax.character {
             try_highlight!(self.highlight_char(out, input));
         }
 
+        if self.syntax.variables && c == '$' {
+            try_highlight!(self.highlight_variable(out, input));
+        }
+
         if !self.syntax.string_quotes.is_empty() {
             try_highlight!(self.highlight_string(c, out));
         }
@@ -826,6 +1330,78 @@ impl<'a> Highlighter<'a> {
         self.eat_one(out, c, Highlight::Normal)
     }
 
+    // Highlight constructs determined by head of line such as headings or keys. Returns the number
+    // of highlighted characters, or None when entire line was highlighted.
+    fn highlight_line_head(&mut self, out: &mut [Highlight], row: &str) -> Option<usize> {
+        fn fill(out: &mut [Highlight], hl: Highlight) -> Option<usize> {
+            for out in out.iter_mut() {
+                *out = hl;
+            }
+            None
+        }
+
+        if self.in_block_comment || self.prev_quote.is_some() || self.in_string.is_some() {
+            return Some(0);
+        }
+
+        let trimmed = row.trim_start();
+        let indent = &row[..row.len() - trimmed.len()];
+        let indent_chars = indent.chars().count();
+
+        if let Some(fence) = self.syntax.code_fence {
+            if trimmed.starts_with(fence) {
+                self.in_code_fence = !self.in_code_fence;
+                return fill(out, Highlight::String);
+            }
+            if self.in_code_fence {
+                return fill(out, Highlight::String);
+            }
+        }
+
+        if self.syntax.heading && indent_chars < 4 {
+            // e.g. '## Title'. At most 6 levels
+            let level = trimmed.chars().take_while(|c| *c == '#').count();
+            let rest = &trimmed[level..];
+            if (1..=6).contains(&level) && (rest.is_empty() || rest.starts_with(' ')) {
+                return fill(out, Highlight::Definition);
+            }
+        }
+
+        if self.syntax.table_header && trimmed.starts_with('[') {
+            // e.g. '[table]' or '[[array]]' followed by optional comment
+            if let Some(end) = trimmed.rfind(']') {
+                let rest = trimmed[end + 1..].trim_start();
+                let comment = self.syntax.line_comment.map(|c| rest.starts_with(c));
+                if rest.is_empty() || comment == Some(true) {
+                    let len = trimmed[..=end].chars().count();
+                    self.eat_n(
+                        &mut out[indent_chars..],
+                        trimmed,
+                        Highlight::Definition,
+                        len,
+                    );
+                    return Some(indent_chars + len);
+                }
+            }
+        }
+
+        if let Some(sep) = self.syntax.key_separator {
+            // Skip list item markers in YAML such as '- key: value'
+            let mut key = trimmed;
+            while let Some(rest) = key.strip_prefix("- ") {
+                key = rest.trim_start();
+            }
+            if let Some(len) = key_len(key, sep) {
+                let start = row[..row.len() - key.len()].chars().count();
+                let len = key[..len].chars().count();
+                self.eat_n(&mut out[start..], key, Highlight::Keyword, len);
+                return Some(start + len);
+            }
+        }
+
+        Some(0)
+    }
+
     fn highlight_line(&mut self, out: &mut [Highlight], row: &str) {
         if self.syntax.lang == Language::Plain {
             // On 'plain' syntax, skip highlighting since nothing is highlighted.
@@ -835,10 +1411,16 @@ impl<'a> Highlighter<'a> {
         // Initialize states for line highlighting
         self.prev_hl = Highlight::Normal;
         self.prev_char = '\0';
+        self.prev_non_space = '\0';
         self.num = NumLit::Digit;
         self.after_def_keyword = false;
 
-        let mut iter = row.char_indices().enumerate();
+        let skip = match self.highlight_line_head(out, row) {
+            Some(skip) => skip,
+            None => return, // Entire line was highlighted
+        };
+
+        let mut iter = row.char_indices().enumerate().skip(skip);
         while let Some((x, (idx, c))) = iter.next() {
             let input = &row[idx..];
             let out = &mut out[x..];
@@ -847,7 +1429,7 @@ impl<'a> Highlighter<'a> {
                     // while statement always consume one character at top. Eat input chars considering that.
                     iter.nth(len.saturating_sub(2));
                 }
-                ParseStep::Ahead(len) if len == 1 => { /* Go next */ }
+                ParseStep::Ahead(1) => { /* Go next */ }
                 ParseStep::Ahead(_) => unreachable!(),
                 ParseStep::Break => break,
             }
@@ -855,6 +1437,13 @@ impl<'a> Highlighter<'a> {
     }
 }
 
+const BRACKET_PAIRS: &[(char, char)] = &[('(', ')'), ('[', ']'), ('{', '}')];
+// Max number of lines scanned to jump to a matching bracket
+pub const MAX_BRACKET_SEARCH_LINES: usize = 1000;
+
+// Position of a pair of brackets. Each position is (x, y) in render text coordinates
+pub type BracketPair = ((usize, usize), (usize, usize));
+
 pub struct RegionHighlight {
     pub hl: Highlight,
     pub start: (usize, usize),
@@ -878,8 +1467,14 @@ pub struct Highlighting {
     pub needs_update: bool,
     // One item per render text byte
     pub lines: Vec<Vec<Highlight>>, // TODO: One item per one character
-    previous_bottom_of_screen: usize,
+    // States at end of highlighted lines. Its length is the number of lines highlighted so far
+    states: Vec<LineState>,
+    // Lines which need to be highlighted again
+    dirty: Option<DirtyLines>,
     matched: Vec<RegionHighlight>,
+    bracket: Option<BracketPair>,
+    // Highlights of the cells overwritten by highlights of the bracket pair
+    bracket_cells: [Highlight; 2],
     syntax: &'static SyntaxHighlight,
 }
 
@@ -888,8 +1483,11 @@ impl Default for Highlighting {
         Highlighting {
             needs_update: false,
             lines: vec![],
-            previous_bottom_of_screen: 0,
+            states: vec![],
+            dirty: None,
             matched: vec![],
+            bracket: None,
+            bracket_cells: [Highlight::Normal; 2],
             syntax: &PLAIN_SYNTAX,
         }
     }
@@ -897,19 +1495,25 @@ impl Default for Highlighting {
 
 impl Highlighting {
     pub fn new(lang: Language, rows: &[Row]) -> Highlighting {
+        Self::with_syntax(SyntaxHighlight::for_lang(lang), rows)
+    }
+
+    pub fn with_syntax(syntax: &'static SyntaxHighlight, rows: &[Row]) -> Highlighting {
         Highlighting {
             needs_update: true,
             lines: rows
                 .iter()
                 .map(|r| {
-                    iter::repeat(Highlight::Normal)
-                        .take(r.render_text().chars().count()) // TODO: One item per one character
-                        .collect()
+                    // TODO: One item per one character
+                    iter::repeat_n(Highlight::Normal, r.render_text().chars().count()).collect()
                 })
                 .collect(),
-            previous_bottom_of_screen: 0,
+            states: vec![],
+            dirty: None,
             matched: vec![],
-            syntax: SyntaxHighlight::for_lang(lang),
+            bracket: None,
+            bracket_cells: [Highlight::Normal; 2],
+            syntax,
         }
     }
 
@@ -918,9 +1522,64 @@ impl Highlighting {
             return;
         }
         self.syntax = SyntaxHighlight::for_lang(new_lang);
+        self.states.clear();
+        self.dirty = None;
         self.needs_update = true;
     }
 
+    fn add_dirty(&mut self, lines: DirtyLines) {
+        self.dirty = Some(match self.dirty {
+            Some(d) => DirtyLines::new(cmp::min(d.start, lines.start), cmp::max(d.end, lines.end)),
+            None => lines,
+        });
+        self.needs_update = true;
+    }
+
+    // Shift highlights after the line when lines were inserted (delta > 0) or deleted (delta < 0)
+    fn shift_lines(&mut self, at: usize, delta: isize) {
+        let at = cmp::min(at, self.lines.len());
+        if delta > 0 {
+            let n = delta as usize;
+            self.lines.splice(at..at, iter::repeat_n(vec![], n));
+            if at <= self.states.len() {
+                self.states
+                    .splice(at..at, iter::repeat_n(LineState::default(), n));
+            }
+        } else {
+            let n = delta.unsigned_abs();
+            self.lines.drain(at..cmp::min(at + n, self.lines.len()));
+            if at < self.states.len() {
+                self.states.drain(at..cmp::min(at + n, self.states.len()));
+            }
+        }
+
+        let shift = |y: &mut usize| {
+            if *y > at {
+                *y = cmp::max(at, y.saturating_add_signed(delta));
+            }
+        };
+        if let Some(d) = &mut self.dirty {
+            shift(&mut d.end);
+        }
+        if let Some(((_, y1), (_, y2))) = &mut self.bracket {
+            shift(y1);
+            shift(y2);
+        }
+        for region in self.matched.iter_mut() {
+            shift(&mut region.start.1);
+            shift(&mut region.end.1);
+        }
+    }
+
+    // Lines were modified. Highlights of the lines are calculated again at next update
+    pub fn invalidate(&mut self, rows: &[Row], lines: DirtyLines) {
+        let delta = rows.len() as isize - self.lines.len() as isize;
+        if delta != 0 {
+            self.shift_lines(lines.start, delta);
+        }
+        self.add_dirty(lines);
+    }
+
     fn highlight_match(&mut self, overwrite: Option<Highlight>) {
         for region in self.matched.iter() {
             let highlight = overwrite.unwrap_or(region.hl);
@@ -934,19 +1593,53 @@ impl Highlighting {
         }
     }
 
+    // Highlight lines from the first dirty line until bottom of screen. Highlighting stops early when
+    // the state at end of line after the dirty lines is the same as the previous highlighting since
+    // the following lines are not affected.
     pub fn update(&mut self, rows: &[Row], bottom_of_screen: usize) {
-        if !self.needs_update && bottom_of_screen <= self.previous_bottom_of_screen {
+        let bottom = cmp::min(bottom_of_screen, rows.len());
+        if !self.needs_update && bottom <= self.states.len() {
             return;
         }
 
-        let mut highlighter = Highlighter::new(self.syntax);
+        if self.lines.len() != rows.len() {
+            // Number of lines was changed without invalidation. Highlight all lines again
+            self.lines.resize_with(rows.len(), Default::default);
+            self.states.clear();
+            self.dirty = None;
+        }
 
-        self.lines.resize_with(rows.len(), Default::default);
-        for (y, row) in rows.iter().enumerate().take(bottom_of_screen) {
-            let row = row.render_text();
-            self.lines[y].resize(row.chars().count(), Highlight::Normal); // TODO: One item per one character
+        let (start, end) = match self.dirty.take() {
+            Some(d) => (cmp::min(d.start, self.states.len()), d.end),
+            None => (self.states.len(), 0),
+        };
+        let prev_len = self.states.len();
 
+        let mut highlighter = match start.checked_sub(1) {
+            Some(y) => Highlighter::with_state(self.syntax, self.states[y]),
+            None => Highlighter::new(self.syntax),
+        };
+
+        let mut y = start;
+        let mut unchanged = false;
+        while y < bottom && !unchanged {
+            let row = rows[y].render_text();
+            self.lines[y].resize(row.chars().count(), Highlight::Normal); // TODO: One item per one character
             highlighter.highlight_line(&mut self.lines[y], row);
+
+            let state = highlighter.state();
+            if y < prev_len {
+                unchanged = y >= end && self.states[y] == state;
+                self.states[y] = state;
+            } else {
+                self.states.push(state);
+            }
+            y += 1;
+        }
+
+        if !unchanged {
+            // Highlights of lines after here may be affected
+            self.states.truncate(y);
         }
 
         // Overwrite matched region
@@ -954,9 +1647,160 @@ impl Highlighting {
         // TODO: Move logic to highlighter rather than overwriting highlights after.
         // Give self.matched to Highlighter::new() and it checks each cell should be highlighted as match
         self.highlight_match(None);
+        self.apply_bracket();
 
         self.needs_update = false;
-        self.previous_bottom_of_screen = bottom_of_screen;
+    }
+
+    // Overwrite highlights of the bracket pair. Original highlights are saved to restore them
+    fn apply_bracket(&mut self) {
+        if let Some(((x1, y1), (x2, y2))) = self.bracket {
+            for (i, &(x, y)) in [(x1, y1), (x2, y2)].iter().enumerate() {
+                // The cell is still highlighted as bracket when the line was not highlighted again
+                match self.lines.get_mut(y).and_then(|l| l.get_mut(x)) {
+                    Some(cell) if *cell != Highlight::Bracket => {
+                        self.bracket_cells[i] = *cell;
+                        *cell = Highlight::Bracket;
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    fn restore_bracket(&mut self) {
+        if let Some(((x1, y1), (x2, y2))) = self.bracket {
+            for (i, &(x, y)) in [(x1, y1), (x2, y2)].iter().enumerate() {
+                match self.lines.get_mut(y).and_then(|l| l.get_mut(x)) {
+                    Some(cell) if *cell == Highlight::Bracket => *cell = self.bracket_cells[i],
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    // Brackets in string literals, character literals and comments are not considered
+    fn is_code(&self, x: usize, y: usize) -> bool {
+        use Highlight::*;
+        !matches!(
+            self.lines.get(y).and_then(|l| l.get(x)),
+            Some(String) | Some(Char) | Some(Comment)
+        )
+    }
+
+    fn scan_partner_bracket(
+        &self,
+        rows: &[Row],
+        (cx, cy): (usize, usize),
+        (bracket, partner): (char, char),
+        forward: bool,
+        lines: &Range<usize>,
+    ) -> Option<(usize, usize)> {
+        let mut depth = 0;
+        let mut is_partner = |x: usize, y: usize, c: char| {
+            if !self.is_code(x, y) {
+                return false;
+            }
+            if c == bracket {
+                depth += 1;
+            } else if c == partner {
+                if depth == 0 {
+                    return true;
+                }
+                depth -= 1;
+            }
+            false
+        };
+
+        if forward {
+            let end = cmp::min(lines.end, rows.len());
+            for (y, row) in rows.iter().enumerate().take(end).skip(cy) {
+                let skip = if y == cy { cx + 1 } else { 0 };
+                for (x, c) in row.render_text().chars().enumerate().skip(skip) {
+                    if is_partner(x, y, c) {
+                        return Some((x, y));
+                    }
+                }
+            }
+        } else {
+            for (y, row) in rows.iter().enumerate().take(cy + 1).skip(lines.start).rev() {
+                let text = row.render_text();
+                let len = text.chars().count();
+                let skip = if y == cy { len - cx } else { 0 };
+                for (x, c) in (0..len).rev().zip(text.chars().rev()).skip(skip) {
+                    if is_partner(x, y, c) {
+                        return Some((x, y));
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    // Find the bracket under the cursor (or just before the cursor) and its partner within the
+    // lines. The first position of returned pair is the bracket at cursor.
+    pub fn find_bracket_pair(
+        &mut self,
+        rows: &[Row],
+        (cx, cy): (usize, usize),
+        lines: Range<usize>,
+    ) -> Option<BracketPair> {
+        if !lines.contains(&cy) {
+            return None;
+        }
+        let row = rows.get(cy)?;
+        let rx = row.render_idx_from_cx(cx);
+        let char_at = |x: usize| row.render_text().chars().nth(x);
+
+        let is_bracket = |x: usize| {
+            char_at(x)
+                .map(|c| BRACKET_PAIRS.iter().any(|&(o, e)| c == o || c == e))
+                .unwrap_or(false)
+        };
+        if !is_bracket(rx) && !rx.checked_sub(1).map(is_bracket).unwrap_or(false) {
+            return None;
+        }
+
+        // Highlights of the lines may not be calculated yet
+        self.update(rows, lines.end);
+
+        // Bracket under cursor is prioritized
+        for x in iter::once(rx).chain(rx.checked_sub(1)) {
+            let c = match char_at(x) {
+                Some(c) if self.is_code(x, cy) => c,
+                _ => continue,
+            };
+            for &(open, close) in BRACKET_PAIRS {
+                let partner = if c == open {
+                    self.scan_partner_bracket(rows, (x, cy), (open, close), true, &lines)
+                } else if c == close {
+                    self.scan_partner_bracket(rows, (x, cy), (close, open), false, &lines)
+                } else {
+                    continue;
+                };
+                return partner.map(|p| ((x, cy), p));
+            }
+        }
+
+        None
+    }
+
+    // Returns the lines where rendering should be updated. Highlights of the previous brackets are
+    // restored and the new brackets are highlighted without highlighting the lines again
+    pub fn set_bracket(&mut self, bracket: Option<BracketPair>) -> Vec<usize> {
+        if self.bracket == bracket {
+            return vec![];
+        }
+        let lines = self
+            .bracket
+            .iter()
+            .chain(bracket.iter())
+            .flat_map(|&((_, y1), (_, y2))| [y1, y2])
+            .collect();
+        self.restore_bracket();
+        self.bracket = bracket;
+        self.apply_bracket();
+        lines
     }
 
     pub fn set_matches(&mut self, matches: Vec<RegionHighlight>) {
@@ -966,12 +1810,14 @@ impl Highlighting {
 
     pub fn clear_previous_match(&mut self) -> Option<usize> {
         let dirty_start = self.matched.iter().map(|r| r.start.1).min();
-        if dirty_start.is_some() {
+        if let Some(start) = dirty_start {
             if self.syntax.lang == Language::Plain {
                 // Back to normal color. It is necessary on plain file type since it skips highlighting.
-                // Otherwise, this process is unnecessary because next highlighting will overwrite match
-                // highlights.
                 self.highlight_match(Some(Highlight::Normal));
+            } else {
+                // Highlight matched lines again to overwrite match highlights
+                let end = self.matched.iter().map(|r| r.end.1).max().unwrap_or(start);
+                self.add_dirty(DirtyLines::new(start, end));
             }
             self.matched.clear();
         }
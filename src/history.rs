@@ -1,6 +1,5 @@
-use crate::edit_diff::{EditDiff, UndoRedo};
+use crate::edit_diff::{DirtyLines, EditDiff, UndoRedo};
 use crate::row::Row;
-use std::cmp;
 use std::collections::VecDeque;
 use std::mem;
 
@@ -47,32 +46,43 @@ impl History {
         diffs: I,
         which: UndoRedo,
         rows: &mut Vec<Row>,
-    ) -> (usize, usize, usize) {
-        diffs.fold((0, 0, usize::max_value()), |(_, _, dirty_start), diff| {
-            let (x, y) = diff.apply(rows, which);
-            (x, y, cmp::min(dirty_start, y))
+        tab_stop: usize,
+    ) -> (usize, usize, Option<DirtyLines>) {
+        diffs.fold((0, 0, None), |(_, _, dirty), diff| {
+            let len = rows.len();
+            let (x, y) = diff.apply(rows, which, tab_stop);
+            let delta = rows.len() as isize - len as isize;
+            (x, y, Some(DirtyLines::extend(dirty, y, delta)))
         })
     }
 
-    pub fn undo(&mut self, rows: &mut Vec<Row>) -> Option<(usize, usize, usize, bool)> {
+    pub fn undo(
+        &mut self,
+        rows: &mut Vec<Row>,
+        tab_stop: usize,
+    ) -> Option<(usize, usize, Option<DirtyLines>, bool)> {
         let edited = self.finish_ongoing_edit();
         if self.index == 0 {
             return None;
         }
         self.index -= 1;
         let i = self.entries[self.index].iter().rev();
-        let (x, y, dirty_start) = Self::apply_diffs(i, UndoRedo::Undo, rows);
-        Some((x, y, dirty_start, edited))
+        let (x, y, dirty) = Self::apply_diffs(i, UndoRedo::Undo, rows, tab_stop);
+        Some((x, y, dirty, edited))
     }
 
-    pub fn redo(&mut self, rows: &mut Vec<Row>) -> Option<(usize, usize, usize, bool)> {
+    pub fn redo(
+        &mut self,
+        rows: &mut Vec<Row>,
+        tab_stop: usize,
+    ) -> Option<(usize, usize, Option<DirtyLines>, bool)> {
         let edited = self.finish_ongoing_edit();
         if self.index == self.entries.len() {
             return None;
         }
         self.index += 1;
         let i = self.entries[self.index - 1].iter();
-        let (x, y, dirty_start) = Self::apply_diffs(i, UndoRedo::Redo, rows);
-        Some((x, y, dirty_start, edited))
+        let (x, y, dirty) = Self::apply_diffs(i, UndoRedo::Redo, rows, tab_stop);
+        Some((x, y, dirty, edited))
     }
 }
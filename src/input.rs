@@ -4,6 +4,11 @@ use std::io::{self, Read};
 use std::ops::{Deref, DerefMut};
 use std::os::unix::io::AsRawFd;
 use std::str;
+use std::sync::Mutex;
+
+// Original and raw terminal modes while raw mode is enabled. They are used to leave raw mode
+// temporarily on suspending the editor
+static TERMINAL_MODES: Mutex<Option<(termios::Termios, termios::Termios)>> = Mutex::new(None);
 
 pub struct StdinRawMode {
     stdin: io::Stdin,
@@ -36,6 +41,7 @@ impl StdinRawMode {
         termios.c_cc[VTIME] = 0;
         // Apply terminal configurations
         tcsetattr(fd, TCSAFLUSH, &termios)?;
+        *TERMINAL_MODES.lock().unwrap() = Some((orig, termios));
 
         Ok(StdinRawMode { stdin, orig })
     }
@@ -45,10 +51,53 @@ impl StdinRawMode {
     }
 }
 
+pub fn raw_mode_enabled() -> bool {
+    TERMINAL_MODES.lock().unwrap().is_some()
+}
+
+// Run the function with the original terminal mode and enable raw mode again after that
+pub fn without_raw_mode<T, F: FnOnce() -> T>(f: F) -> Result<T> {
+    let (orig, raw) = match *TERMINAL_MODES.lock().unwrap() {
+        Some(modes) => modes,
+        None => return Ok(f()),
+    };
+    let fd = io::stdin().as_raw_fd();
+    termios::tcsetattr(fd, termios::TCSAFLUSH, &orig)?;
+    let ret = f();
+    termios::tcsetattr(fd, termios::TCSAFLUSH, &raw)?;
+    Ok(ret)
+}
+
+// Run the function with reading input which times out in 100ms. Input iterator returns Unidentified
+// on timeout
+pub fn with_read_timeout<T, F: FnOnce() -> T>(f: F) -> Result<T> {
+    use termios::*;
+
+    let raw = match *TERMINAL_MODES.lock().unwrap() {
+        Some((_, raw)) => raw,
+        None => return Ok(f()),
+    };
+    let mut timeout = raw;
+    timeout.c_cc[VMIN] = 0;
+    timeout.c_cc[VTIME] = 1;
+    let fd = io::stdin().as_raw_fd();
+    tcsetattr(fd, TCSANOW, &timeout)?;
+    let ret = f();
+    tcsetattr(fd, TCSANOW, &raw)?;
+    Ok(ret)
+}
+
+impl Read for StdinRawMode {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.stdin.read(buf)
+    }
+}
+
 impl Drop for StdinRawMode {
     fn drop(&mut self) {
         // Restore original terminal mode
         termios::tcsetattr(self.stdin.as_raw_fd(), termios::TCSAFLUSH, &self.orig).unwrap();
+        *TERMINAL_MODES.lock().unwrap() = None;
     }
 }
 
@@ -79,8 +128,15 @@ pub enum KeySeq {
     PageDownKey,
     HomeKey,
     EndKey,
+    InsertKey,
     DeleteKey,
-    Cursor(usize, usize), // Pseudo key (x, y)
+    FunctionKey(u8),             // F1~F12
+    Cursor(usize, usize),        // Pseudo key (x, y)
+    BackgroundColor(u8, u8, u8), // Pseudo key (r, g, b)
+    DeviceAttributes,            // Pseudo key
+    MouseClick(usize, usize),    // Left button press at (x, y) on screen (0-origin)
+    WheelUp,
+    WheelDown,
 }
 
 impl fmt::Display for KeySeq {
@@ -100,8 +156,15 @@ impl fmt::Display for KeySeq {
             PageDownKey => write!(f, "PAGEDOWN"),
             HomeKey => write!(f, "HOME"),
             EndKey => write!(f, "END"),
+            InsertKey => write!(f, "INSERT"),
             DeleteKey => write!(f, "DELETE"),
+            FunctionKey(n) => write!(f, "F{}", n),
             Cursor(r, c) => write!(f, "CURSOR({},{})", r, c),
+            BackgroundColor(r, g, b) => write!(f, "BACKGROUND({},{},{})", r, g, b),
+            DeviceAttributes => write!(f, "DEVICEATTRIBUTES"),
+            MouseClick(x, y) => write!(f, "CLICK({},{})", x, y),
+            WheelUp => write!(f, "WHEELUP"),
+            WheelDown => write!(f, "WHEELDOWN"),
         }
     }
 }
@@ -111,6 +174,7 @@ pub struct InputSeq {
     pub key: KeySeq,
     pub ctrl: bool,
     pub alt: bool,
+    pub shift: bool,
 }
 
 impl InputSeq {
@@ -119,6 +183,7 @@ impl InputSeq {
             key,
             ctrl: false,
             alt: false,
+            shift: false,
         }
     }
 
@@ -127,6 +192,7 @@ impl InputSeq {
             key,
             ctrl: true,
             alt: false,
+            shift: false,
         }
     }
 
@@ -135,8 +201,59 @@ impl InputSeq {
             key,
             ctrl: false,
             alt: true,
+            shift: false,
         }
     }
+
+    pub fn shift(key: KeySeq) -> Self {
+        Self {
+            key,
+            ctrl: false,
+            alt: false,
+            shift: true,
+        }
+    }
+
+    // xterm encodes modifiers as 1 + bits of shift (1), alt (2), ctrl (4) and meta (8). e.g.
+    // Ctrl-Shift-UP => \x1b[1;6A
+    // https://invisible-island.net/xterm/ctlseqs/ctlseqs.html#h2-PC-Style-Function-Keys
+    fn with_modifiers(key: KeySeq, param: Option<usize>) -> Self {
+        let bits = param.unwrap_or(1).saturating_sub(1);
+        Self {
+            key,
+            ctrl: bits & 4 != 0,
+            alt: bits & (2 | 8) != 0,
+            shift: bits & 1 != 0,
+        }
+    }
+
+    // Key from code point reported by modifyOtherKeys of xterm or kitty keyboard protocol
+    fn from_code_point(code: usize, param: Option<usize>) -> Self {
+        use KeySeq::*;
+        let mut seq = Self::with_modifiers(Unidentified, param);
+        seq.key = match code {
+            0x1b | 0x7f => Key(code as u8),
+            // Control characters are mapped to Ctrl + key as well as decode(). e.g. Tab => Ctrl-I
+            0x00..=0x1f => {
+                seq.ctrl = true;
+                Key(code as u8 | 0b0110_0000)
+            }
+            // Shifted alphabet with modifiers such as Ctrl-Shift-Z is reported as 'z' with shift
+            0x41..=0x5a if seq.ctrl || seq.alt => {
+                seq.shift = true;
+                Key(code as u8 | 0b0010_0000)
+            }
+            0x61..=0x7a if seq.shift && !seq.ctrl && !seq.alt => {
+                seq.shift = false;
+                Key(code as u8 & !0b0010_0000)
+            }
+            0x20..=0x7e => Key(code as u8),
+            // Private use area is used for functional keys by kitty
+            0xe000..=0xf8ff => Unidentified,
+            _ => std::char::from_u32(code as u32).map_or(Unidentified, Utf8Key),
+        };
+        seq
+    }
 }
 
 impl fmt::Display for InputSeq {
@@ -147,15 +264,18 @@ impl fmt::Display for InputSeq {
         if self.alt {
             write!(f, "M-")?;
         }
+        if self.shift {
+            write!(f, "S-")?;
+        }
         write!(f, "{}", self.key)
     }
 }
 
-pub struct InputSequences {
-    stdin: StdinRawMode,
+pub struct InputSequences<R: Read = StdinRawMode> {
+    stdin: R,
 }
 
-impl InputSequences {
+impl<R: Read> InputSequences<R> {
     fn read_byte(&mut self) -> Result<Option<u8>> {
         let mut one_byte: [u8; 1] = [0];
         Ok(if self.stdin.read(&mut one_byte)? == 0 {
@@ -170,9 +290,10 @@ impl InputSequences {
 
         // Try to read expecting '[' as escape sequence header. Note that, if next input does
         // not arrive within next tick, it means that it is not an escape sequence.
-        // TODO?: Should we consider sequences not starting with '['?
         match self.read_byte()? {
             Some(b'[') => { /* fall through */ }
+            Some(b']') => return self.decode_os_command(),
+            Some(b'O') => return self.decode_ss3(),
             Some(b) if b.is_ascii_control() => return Ok(InputSeq::new(Key(0x1b))), // Ignore control characters after ESC
             Some(b) => {
                 // Alt key is sent as ESC prefix (e.g. Alt-A => \x1b\x61
@@ -190,9 +311,9 @@ impl InputSequences {
         let cmd = loop {
             if let Some(b) = self.read_byte()? {
                 match b {
-                    // Control command chars from http://ascii-table.com/ansi-escape-sequences-vt-100.php
-                    b'A' | b'B' | b'C' | b'D' | b'F' | b'H' | b'K' | b'J' | b'R' | b'c' | b'f'
-                    | b'g' | b'h' | b'l' | b'm' | b'n' | b'q' | b't' | b'y' | b'~' => break b,
+                    // Final bytes of control sequences are in range of 0x40~0x7e
+                    // https://vt100.net/emu/dec_ansi_parser
+                    0x40..=0x7e => break b,
                     _ => buf.push(b),
                 }
             } else {
@@ -201,58 +322,178 @@ impl InputSequences {
             }
         };
 
+        // Sub-parameters separated by ':' such as event type of kitty keyboard protocol are ignored
         fn parse_bytes_as_usize(b: &[u8]) -> Option<usize> {
+            let b = b.split(|b| *b == b':').next()?;
             str::from_utf8(b).ok().and_then(|s| s.parse().ok())
         }
 
-        let mut args = buf.split(|b| *b == b';');
+        let mut args = buf.split(|b| *b == b';').map(parse_bytes_as_usize);
         match cmd {
+            // F3 with modifiers such as \x1b[1;5R is the same format as cursor position report.
+            // Row 1 in the report is not possible since the report is requested at bottom-right
+            // corner of window
+            b'R' if buf.starts_with(b"1;") => {
+                args.next();
+                Ok(InputSeq::with_modifiers(
+                    FunctionKey(3),
+                    args.next().flatten(),
+                ))
+            }
             b'R' => {
                 // https://vt100.net/docs/vt100-ug/chapter3.html#CPR e.g. \x1b[24;80R
-                let mut i = args.filter_map(parse_bytes_as_usize);
-                match (i.next(), i.next()) {
-                    (Some(r), Some(c)) => Ok(InputSeq::new(Cursor(r, c))),
+                match (args.next(), args.next()) {
+                    (Some(Some(r)), Some(Some(c))) => Ok(InputSeq::new(Cursor(r, c))),
                     _ => Ok(InputSeq::new(Unidentified)),
                 }
             }
             // e.g. <LEFT> => \x1b[C
             // e.g. C-<LEFT> => \x1b[1;5C
-            b'A' | b'B' | b'C' | b'D' => {
+            // e.g. C-<HOME> => \x1b[1;5H
+            // e.g. S-<F1> => \x1b[1;2P
+            b'A' | b'B' | b'C' | b'D' | b'H' | b'F' | b'P' | b'Q' | b'S' => {
                 let key = match cmd {
                     b'A' => UpKey,
                     b'B' => DownKey,
                     b'C' => RightKey,
                     b'D' => LeftKey,
+                    b'H' => HomeKey,
+                    b'F' => EndKey,
+                    b'P' => FunctionKey(1),
+                    b'Q' => FunctionKey(2),
+                    b'S' => FunctionKey(4),
                     _ => unreachable!(),
                 };
-                let ctrl = args.next() == Some(b"1") && args.next() == Some(b"5");
-                let alt = false;
-                Ok(InputSeq { key, ctrl, alt })
+                args.next();
+                Ok(InputSeq::with_modifiers(key, args.next().flatten()))
             }
+            // Shift-Tab => \x1b[Z. Tab is Ctrl-I
+            b'Z' => Ok(InputSeq {
+                key: Key(b'i'),
+                ctrl: true,
+                alt: false,
+                shift: true,
+            }),
             b'~' => {
-                // e.g. \x1b[5~
-                match args.next() {
-                    Some(b"5") => Ok(InputSeq::new(PageUpKey)),
-                    Some(b"6") => Ok(InputSeq::new(PageDownKey)),
-                    Some(b"1") | Some(b"7") => Ok(InputSeq::new(HomeKey)),
-                    Some(b"4") | Some(b"8") => Ok(InputSeq::new(EndKey)),
-                    Some(b"3") => Ok(InputSeq::new(DeleteKey)),
-                    _ => Ok(InputSeq::new(Unidentified)),
-                }
+                // e.g. \x1b[5~ or \x1b[5;5~
+                let num = args.next().flatten();
+                let param = args.next().flatten();
+                let key = match num {
+                    Some(1) | Some(7) => HomeKey,
+                    Some(2) => InsertKey,
+                    Some(3) => DeleteKey,
+                    Some(4) | Some(8) => EndKey,
+                    Some(5) => PageUpKey,
+                    Some(6) => PageDownKey,
+                    Some(n @ 11..=15) => FunctionKey(n as u8 - 10),
+                    Some(n @ 17..=21) => FunctionKey(n as u8 - 11),
+                    Some(n @ 23..=24) => FunctionKey(n as u8 - 12),
+                    // modifyOtherKeys of xterm e.g. C-S-z => \x1b[27;6;90~
+                    // https://invisible-island.net/xterm/modified-keys.html
+                    Some(27) => {
+                        return Ok(match args.next().flatten() {
+                            Some(code) => InputSeq::from_code_point(code, param),
+                            None => InputSeq::new(Unidentified),
+                        })
+                    }
+                    _ => return Ok(InputSeq::new(Unidentified)),
+                };
+                Ok(InputSeq::with_modifiers(key, param))
             }
-            b'H' | b'F' => {
-                // C-HOME => \x1b[1;5H
-                let key = match cmd {
-                    b'H' => HomeKey,
-                    b'F' => EndKey,
-                    _ => unreachable!(),
+            // kitty keyboard protocol and modifyOtherKeys with formatOtherKeys=1 e.g. C-S-z => \x1b[122;6u
+            // https://sw.kovidgoyal.net/kitty/keyboard-protocol/
+            b'u' => match args.next().flatten() {
+                Some(code) => Ok(InputSeq::from_code_point(code, args.next().flatten())),
+                None => Ok(InputSeq::new(Unidentified)),
+            },
+            // SGR mouse report e.g. \x1b[<0;12;5M (press) or \x1b[<0;12;5m (release)
+            // https://invisible-island.net/xterm/ctlseqs/ctlseqs.html#h2-Mouse-Tracking
+            b'M' | b'm' if buf.first() == Some(&b'<') => {
+                let mut i = buf[1..].split(|b| *b == b';').map(parse_bytes_as_usize);
+                let (button, x, y) = match (i.next(), i.next(), i.next()) {
+                    (Some(Some(b)), Some(Some(x)), Some(Some(y))) if x > 0 && y > 0 => (b, x, y),
+                    _ => return Ok(InputSeq::new(Unidentified)),
                 };
-                let ctrl = args.next() == Some(b"1") && args.next() == Some(b"5");
-                let alt = false;
-                Ok(InputSeq { key, ctrl, alt })
+                let key = match (button & !0b11100, cmd) {
+                    (0, b'M') => MouseClick(x - 1, y - 1),
+                    (64, b'M') => WheelUp,
+                    (65, b'M') => WheelDown,
+                    _ => Unidentified, // Release, other buttons and motion are not handled
+                };
+                // Modifiers are 4 (shift), 8 (meta) and 16 (ctrl)
+                Ok(InputSeq {
+                    key,
+                    ctrl: button & 16 != 0,
+                    alt: button & 8 != 0,
+                    shift: button & 4 != 0,
+                })
+            }
+            // Response to primary device attributes query e.g. \x1b[?62;22c
+            b'c' if buf.first() == Some(&b'?') => Ok(InputSeq::new(DeviceAttributes)),
+            _ => Ok(InputSeq::new(Unidentified)),
+        }
+    }
+
+    // SS3 sequences are sent for F1~F4 and for cursor keys in application mode. e.g. F1 => \x1bOP
+    fn decode_ss3(&mut self) -> Result<InputSeq> {
+        use KeySeq::*;
+        let key = match self.read_byte()? {
+            Some(b'A') => UpKey,
+            Some(b'B') => DownKey,
+            Some(b'C') => RightKey,
+            Some(b'D') => LeftKey,
+            Some(b'H') => HomeKey,
+            Some(b'F') => EndKey,
+            Some(b'P') => FunctionKey(1),
+            Some(b'Q') => FunctionKey(2),
+            Some(b'R') => FunctionKey(3),
+            Some(b'S') => FunctionKey(4),
+            _ => Unidentified,
+        };
+        Ok(InputSeq::new(key))
+    }
+
+    // Operating system command is a response from terminal such as \x1b]11;rgb:ffff/ffff/ffff\x1b\\
+    // which is terminated with ST (\x1b\\) or BEL (\x07)
+    fn decode_os_command(&mut self) -> Result<InputSeq> {
+        let mut buf = vec![];
+        loop {
+            match self.read_byte()? {
+                Some(0x07) => break,
+                Some(0x1b) => {
+                    self.read_byte()?; // Eat '\\' of ST
+                    break;
+                }
+                Some(b) if buf.len() < 64 => buf.push(b),
+                _ => return Ok(InputSeq::new(KeySeq::Unidentified)),
+            }
+        }
+
+        // Each color component is 1~4 hex digits. Take upper 8 bits of the component
+        fn parse_component(b: &[u8]) -> Option<u8> {
+            let s = str::from_utf8(b).ok()?;
+            let v = u32::from_str_radix(s, 16).ok()?;
+            match s.len() {
+                1 => Some((v * 0x11) as u8),
+                2 => Some(v as u8),
+                3 => Some((v >> 4) as u8),
+                4 => Some((v >> 8) as u8),
+                _ => None,
             }
-            _ => unreachable!(),
         }
+
+        let color = buf.strip_prefix(b"11;rgb:").and_then(|rgb| {
+            let mut c = rgb.split(|b| *b == b'/').map(parse_component);
+            match (c.next(), c.next(), c.next(), c.next()) {
+                (Some(Some(r)), Some(Some(g)), Some(Some(b)), None) => Some((r, g, b)),
+                _ => None,
+            }
+        });
+
+        Ok(InputSeq::new(match color {
+            Some((r, g, b)) => KeySeq::BackgroundColor(r, g, b),
+            None => KeySeq::Unidentified,
+        }))
     }
 
     fn decode_utf8(&mut self, b: u8) -> Result<InputSeq> {
@@ -315,7 +556,7 @@ impl InputSequences {
     }
 }
 
-impl Iterator for InputSequences {
+impl<R: Read> Iterator for InputSequences<R> {
     type Item = Result<InputSeq>;
 
     // Read next byte from stdin, if nothing was read, it returns InputSeq::Unidentified.
@@ -324,3 +565,114 @@ impl Iterator for InputSequences {
         Some(self.read_seq())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use KeySeq::*;
+
+    fn decode_all(input: &[u8]) -> Vec<InputSeq> {
+        let mut seqs = InputSequences { stdin: input };
+        let mut decoded = vec![];
+        loop {
+            let seq = seqs.next().unwrap().unwrap();
+            if seq.key == Unidentified && seqs.stdin.is_empty() {
+                return decoded;
+            }
+            decoded.push(seq);
+        }
+    }
+
+    fn seq(key: KeySeq, ctrl: bool, alt: bool, shift: bool) -> InputSeq {
+        InputSeq {
+            key,
+            ctrl,
+            alt,
+            shift,
+        }
+    }
+
+    #[test]
+    fn decode_modifiers() {
+        for (input, expected) in &[
+            (&b"\x1b[A"[..], InputSeq::new(UpKey)),
+            (b"\x1b[1;2A", InputSeq::shift(UpKey)),
+            (b"\x1b[1;3B", InputSeq::alt(DownKey)),
+            (b"\x1b[1;4C", seq(RightKey, false, true, true)),
+            (b"\x1b[1;5D", InputSeq::ctrl(LeftKey)),
+            (b"\x1b[1;6H", seq(HomeKey, true, false, true)),
+            (b"\x1b[1;7F", seq(EndKey, true, true, false)),
+            (b"\x1b[1;8A", seq(UpKey, true, true, true)),
+            (b"\x1b[1;10A", seq(UpKey, false, true, true)), // Meta
+            (b"\x1b[5;5~", InputSeq::ctrl(PageUpKey)),
+            (b"\x1b[3;2~", InputSeq::shift(DeleteKey)),
+            (b"\x1b[2~", InputSeq::new(InsertKey)),
+            (b"\x1b[Z", seq(Key(b'i'), true, false, true)),
+            (b"\x1bOA", InputSeq::new(UpKey)),
+            (b"\x1bOH", InputSeq::new(HomeKey)),
+            (b"\x1bf", InputSeq::alt(Key(b'f'))),
+            (b"\x1b[24;80R", InputSeq::new(Cursor(24, 80))),
+        ] {
+            assert_eq!(decode_all(input), vec![expected.clone()], "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn decode_function_keys() {
+        for (input, expected) in &[
+            (&b"\x1bOP"[..], InputSeq::new(FunctionKey(1))),
+            (b"\x1bOS", InputSeq::new(FunctionKey(4))),
+            (b"\x1b[1;2P", InputSeq::shift(FunctionKey(1))),
+            (b"\x1b[1;5R", InputSeq::ctrl(FunctionKey(3))),
+            (b"\x1b[15~", InputSeq::new(FunctionKey(5))),
+            (b"\x1b[17~", InputSeq::new(FunctionKey(6))),
+            (b"\x1b[21;3~", InputSeq::alt(FunctionKey(10))),
+            (b"\x1b[24~", InputSeq::new(FunctionKey(12))),
+            (b"\x1b[99~", InputSeq::new(Unidentified)),
+        ] {
+            let mut seqs = InputSequences { stdin: *input };
+            assert_eq!(seqs.next().unwrap().unwrap(), *expected, "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn decode_other_keys() {
+        for (input, expected) in &[
+            // modifyOtherKeys of xterm
+            (&b"\x1b[27;6;90~"[..], seq(Key(b'z'), true, false, true)),
+            (b"\x1b[27;5;105~", InputSeq::ctrl(Key(b'i'))),
+            (b"\x1b[27;5;9~", InputSeq::ctrl(Key(b'i'))),
+            (b"\x1b[27;2;13~", seq(Key(b'm'), true, false, true)),
+            // kitty keyboard protocol
+            (b"\x1b[122;6u", seq(Key(b'z'), true, false, true)),
+            (b"\x1b[97;5:1u", InputSeq::ctrl(Key(b'a'))),
+            (b"\x1b[97;2u", InputSeq::new(Key(b'A'))),
+            (b"\x1b[27u", InputSeq::new(Key(0x1b))),
+            (b"\x1b[12354;3u", InputSeq::alt(Utf8Key('\u{3042}'))),
+            (b"\x1b[57399u", InputSeq::new(Unidentified)),
+        ] {
+            let mut seqs = InputSequences { stdin: *input };
+            assert_eq!(seqs.next().unwrap().unwrap(), *expected, "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn decode_mouse_and_responses() {
+        assert_eq!(
+            decode_all(b"\x1b[<0;3;5M\x1b[<0;3;5m\x1b[<65;1;1M\x1b[<20;2;2M"),
+            vec![
+                InputSeq::new(MouseClick(2, 4)),
+                InputSeq::new(Unidentified),
+                InputSeq::new(WheelDown),
+                seq(MouseClick(1, 1), true, false, true),
+            ],
+        );
+        assert_eq!(
+            decode_all(b"\x1b]11;rgb:ffff/8080/0000\x1b\\\x1b[?62;22c"),
+            vec![
+                InputSeq::new(BackgroundColor(0xff, 0x80, 0x00)),
+                InputSeq::new(DeviceAttributes),
+            ],
+        );
+    }
+}
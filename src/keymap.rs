@@ -0,0 +1,499 @@
+// Key bindings of editor commands. Bindings can be changed by [keymap] table of 'config.toml'.
+// Each entry maps a key sequence to a command name. Key sequence may be a chord of multiple keys
+// separated by spaces. "none" removes the binding.
+//
+// Example of TOML file:
+//
+//   [keymap]
+//   "Ctrl-X Ctrl-S" = "save"
+//   "Ctrl-X Ctrl-C" = "quit"
+//   "Ctrl-Shift-Z" = "redo"
+//   "Ctrl-Q" = "none"
+use crate::input::{InputSeq, KeySeq};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Command {
+    Quit,
+    Save,
+    Open,
+    NextBuffer,
+    PreviousBuffer,
+    FindFile,
+    Grep,
+    CursorUp,
+    CursorDown,
+    CursorRight,
+    CursorLeft,
+    HeadOfLine,
+    EndOfLine,
+    NextPage,
+    PreviousPage,
+    NextWord,
+    PreviousWord,
+    NextParagraph,
+    PreviousParagraph,
+    TopOfBuffer,
+    BottomOfBuffer,
+    MatchingBracket,
+    DeleteChar,
+    DeleteNextChar,
+    DeleteWord,
+    DeleteToHeadOfLine,
+    DeleteToEndOfLine,
+    Undo,
+    Redo,
+    Search,
+    NewLine,
+    InsertTab,
+    Shell,
+    Filter,
+    Refresh,
+    Suspend,
+    ToggleWhitespace,
+    SelectLanguage,
+    GotoLine,
+    Palette,
+    Help,
+}
+
+// Names and descriptions of commands. Help shows commands in this order
+const COMMANDS: &[(Command, &str, &str)] = {
+    use Command::*;
+    &[
+        (Quit, "quit", "Quit"),
+        (Save, "save", "Save to file"),
+        (Open, "open", "Open text buffer"),
+        (NextBuffer, "next-buffer", "Next text buffer"),
+        (PreviousBuffer, "previous-buffer", "Previous text buffer"),
+        (FindFile, "find-file", "Find file in project"),
+        (Grep, "grep", "Search text in project files"),
+        (CursorUp, "cursor-up", "Move cursor up"),
+        (CursorDown, "cursor-down", "Move cursor down"),
+        (CursorRight, "cursor-right", "Move cursor right"),
+        (CursorLeft, "cursor-left", "Move cursor left"),
+        (HeadOfLine, "head-of-line", "Move cursor to head of line"),
+        (EndOfLine, "end-of-line", "Move cursor to end of line"),
+        (NextPage, "next-page", "Next page"),
+        (PreviousPage, "previous-page", "Previous page"),
+        (NextWord, "next-word", "Move cursor to next word"),
+        (
+            PreviousWord,
+            "previous-word",
+            "Move cursor to previous word",
+        ),
+        (
+            NextParagraph,
+            "next-paragraph",
+            "Move cursor to next paragraph",
+        ),
+        (
+            PreviousParagraph,
+            "previous-paragraph",
+            "Move cursor to previous paragraph",
+        ),
+        (TopOfBuffer, "top-of-buffer", "Move cursor to top of file"),
+        (
+            BottomOfBuffer,
+            "bottom-of-buffer",
+            "Move cursor to bottom of file",
+        ),
+        (
+            MatchingBracket,
+            "matching-bracket",
+            "Jump to matching bracket",
+        ),
+        (DeleteChar, "delete-char", "Delete character"),
+        (DeleteNextChar, "delete-next-char", "Delete next character"),
+        (DeleteWord, "delete-word", "Delete a word"),
+        (
+            DeleteToHeadOfLine,
+            "delete-to-head-of-line",
+            "Delete until head of line",
+        ),
+        (
+            DeleteToEndOfLine,
+            "delete-to-end-of-line",
+            "Delete until end of line",
+        ),
+        (Undo, "undo", "Undo last change"),
+        (Redo, "redo", "Redo last undo change"),
+        (Search, "search", "Search text"),
+        (NewLine, "new-line", "New line"),
+        (InsertTab, "insert-tab", "Insert tab"),
+        (Shell, "shell-command", "Insert output of shell command"),
+        (
+            Filter,
+            "filter-command",
+            "Filter lines through shell command",
+        ),
+        (Refresh, "refresh", "Refresh screen"),
+        (Suspend, "suspend", "Suspend to shell"),
+        (
+            ToggleWhitespace,
+            "toggle-whitespace",
+            "Toggle visible whitespaces",
+        ),
+        (
+            SelectLanguage,
+            "select-language",
+            "Select language of buffer",
+        ),
+        (GotoLine, "goto-line", "Go to line"),
+        (Palette, "command-palette", "Execute command by name"),
+        (Help, "help", "Show this help"),
+    ]
+};
+
+const DEFAULT_BINDINGS: &[(&str, Command)] = {
+    use Command::*;
+    &[
+        ("Ctrl-Q", Quit),
+        ("Ctrl-S", Save),
+        ("Ctrl-O", Open),
+        ("Ctrl-X", NextBuffer),
+        ("Alt-X", PreviousBuffer),
+        ("Alt-O", FindFile),
+        ("Alt-G", Grep),
+        ("Ctrl-P", CursorUp),
+        ("Up", CursorUp),
+        ("Ctrl-N", CursorDown),
+        ("Down", CursorDown),
+        ("Ctrl-F", CursorRight),
+        ("Right", CursorRight),
+        ("Ctrl-B", CursorLeft),
+        ("Left", CursorLeft),
+        ("Ctrl-A", HeadOfLine),
+        ("Alt-Left", HeadOfLine),
+        ("Home", HeadOfLine),
+        ("Ctrl-E", EndOfLine),
+        ("Alt-Right", EndOfLine),
+        ("End", EndOfLine),
+        ("Ctrl-V", NextPage),
+        ("Ctrl-]", NextPage),
+        ("PageDown", NextPage),
+        ("Alt-V", PreviousPage),
+        ("Ctrl-[", PreviousPage),
+        ("PageUp", PreviousPage),
+        ("Alt-F", NextWord),
+        ("Ctrl-Right", NextWord),
+        ("Alt-B", PreviousWord),
+        ("Ctrl-Left", PreviousWord),
+        ("Alt-N", NextParagraph),
+        ("Ctrl-Down", NextParagraph),
+        ("Alt-P", PreviousParagraph),
+        ("Ctrl-Up", PreviousParagraph),
+        ("Alt-<", TopOfBuffer),
+        ("Alt->", BottomOfBuffer),
+        ("Alt-M", MatchingBracket),
+        ("Ctrl-H", DeleteChar),
+        ("Backspace", DeleteChar),
+        ("Ctrl-D", DeleteNextChar),
+        ("Delete", DeleteNextChar),
+        ("Ctrl-W", DeleteWord),
+        ("Ctrl-J", DeleteToHeadOfLine),
+        ("Ctrl-K", DeleteToEndOfLine),
+        ("Ctrl-U", Undo),
+        ("Ctrl-R", Redo),
+        ("Ctrl-G", Search),
+        ("Ctrl-M", NewLine),
+        ("Ctrl-I", InsertTab),
+        ("Alt-!", Shell),
+        ("Alt-|", Filter),
+        ("Ctrl-L", Refresh),
+        ("Ctrl-Z", Suspend),
+        ("Alt-W", ToggleWhitespace),
+        ("Alt-L", SelectLanguage),
+        ("Ctrl-\\", Palette),
+        ("Ctrl-?", Help),
+    ]
+};
+
+impl Command {
+    pub fn name(self) -> &'static str {
+        COMMANDS.iter().find(|(c, _, _)| *c == self).unwrap().1
+    }
+
+    pub fn description(self) -> &'static str {
+        COMMANDS.iter().find(|(c, _, _)| *c == self).unwrap().2
+    }
+
+    pub fn from_name(name: &str) -> Option<Command> {
+        COMMANDS
+            .iter()
+            .find(|(_, n, _)| *n == name)
+            .map(|(c, _, _)| *c)
+    }
+
+    pub fn all() -> impl Iterator<Item = Command> {
+        COMMANDS.iter().map(|(c, _, _)| *c)
+    }
+
+    // Commands which can take an argument from command palette instead of asking it with prompt
+    pub fn takes_argument(self) -> bool {
+        use Command::*;
+        matches!(
+            self,
+            Save | Open | Grep | Shell | Filter | SelectLanguage | GotoLine
+        )
+    }
+
+    // Commands which modify text. They are not available in read-only buffers
+    pub fn edits_text(self) -> bool {
+        use Command::*;
+        matches!(
+            self,
+            DeleteChar
+                | DeleteNextChar
+                | DeleteWord
+                | DeleteToHeadOfLine
+                | DeleteToEndOfLine
+                | Undo
+                | Redo
+                | NewLine
+                | InsertTab
+                | Shell
+                | Filter
+        )
+    }
+}
+
+// Make the same key sequence comparable regardless of how terminal sends it. Control characters
+// without ctrl flag (e.g. '\r') are the same as keys with Ctrl (e.g. Ctrl-M) and shifted
+// alphabets with modifiers (e.g. Alt-Shift-V sent as Alt + 'V') are lower case with shift flag.
+fn normalize(seq: &InputSeq) -> InputSeq {
+    let mut seq = seq.clone();
+    match seq.key {
+        KeySeq::Key(b) if b < 0x20 && b != 0x1b => {
+            // Same as decoding input in input.rs
+            seq.key = KeySeq::Key(match b {
+                0x00 | 0x1f => b | 0b0010_0000,
+                0x1c | 0x1d => b | 0b0100_0000,
+                _ => b | 0b0110_0000,
+            });
+            seq.ctrl = true;
+        }
+        KeySeq::Key(b) if b.is_ascii_uppercase() && (seq.ctrl || seq.alt) => {
+            seq.key = KeySeq::Key(b.to_ascii_lowercase());
+            seq.shift = true;
+        }
+        _ => {}
+    }
+    seq
+}
+
+// Parse key notation such as "Ctrl-X", "Alt-Shift-Left", "F5" or "a"
+pub fn parse_key(notation: &str) -> Option<InputSeq> {
+    use KeySeq::*;
+
+    let mut seq = InputSeq::new(Unidentified);
+    let mut rest = notation;
+    while let Some((modifier, key)) = rest.split_once('-') {
+        if key.is_empty() {
+            break; // e.g. "Alt--"
+        }
+        match modifier.to_ascii_lowercase().as_str() {
+            "ctrl" | "c" => seq.ctrl = true,
+            "alt" | "meta" | "m" => seq.alt = true,
+            "shift" | "s" => seq.shift = true,
+            _ => return None,
+        }
+        rest = key;
+    }
+
+    let mut chars = rest.chars();
+    let key = match (chars.next(), chars.next()) {
+        (Some('['), None) if seq.ctrl => {
+            seq.ctrl = false;
+            Key(0x1b)
+        }
+        (Some(c), None) if c.is_ascii() && (seq.ctrl || seq.alt) => {
+            Key(c.to_ascii_lowercase() as u8)
+        }
+        (Some(c), None) if c.is_ascii() => Key(c as u8),
+        (Some(c), None) => Utf8Key(c),
+        _ => match rest.to_ascii_lowercase().as_str() {
+            "up" => UpKey,
+            "down" => DownKey,
+            "left" => LeftKey,
+            "right" => RightKey,
+            "pageup" => PageUpKey,
+            "pagedown" => PageDownKey,
+            "home" => HomeKey,
+            "end" => EndKey,
+            "insert" => InsertKey,
+            "delete" => DeleteKey,
+            "backspace" => Key(0x7f),
+            "enter" | "return" => Key(b'\r'),
+            "tab" => Key(b'\t'),
+            "esc" | "escape" => Key(0x1b),
+            "space" => Key(b' '),
+            s => match s.strip_prefix('f').and_then(|n| n.parse().ok()) {
+                Some(n @ 1..=12) => FunctionKey(n),
+                _ => return None,
+            },
+        },
+    };
+    seq.key = key;
+    Some(normalize(&seq))
+}
+
+pub fn format_key(seq: &InputSeq) -> String {
+    use KeySeq::*;
+
+    let seq = normalize(seq);
+    let mut s = String::new();
+    if seq.ctrl || seq.key == Key(0x1b) {
+        s.push_str("Ctrl-");
+    }
+    if seq.alt {
+        s.push_str("Alt-");
+    }
+    if seq.shift {
+        s.push_str("Shift-");
+    }
+    match seq.key {
+        Key(0x1b) => s.push('['),
+        Key(0x7f) => s.push_str("Backspace"),
+        Key(b' ') => s.push_str("Space"),
+        Key(b) if seq.ctrl || seq.alt => s.push(b.to_ascii_uppercase() as char),
+        Key(b) => s.push(b as char),
+        Utf8Key(c) => s.push(c),
+        UpKey => s.push_str("Up"),
+        DownKey => s.push_str("Down"),
+        LeftKey => s.push_str("Left"),
+        RightKey => s.push_str("Right"),
+        PageUpKey => s.push_str("PageUp"),
+        PageDownKey => s.push_str("PageDown"),
+        HomeKey => s.push_str("Home"),
+        EndKey => s.push_str("End"),
+        InsertKey => s.push_str("Insert"),
+        DeleteKey => s.push_str("Delete"),
+        FunctionKey(n) => write!(s, "F{}", n).unwrap(),
+        ref k => write!(s, "{}", k).unwrap(),
+    }
+    s
+}
+
+pub fn format_keys(keys: &[InputSeq]) -> String {
+    let keys: Vec<_> = keys.iter().map(format_key).collect();
+    keys.join(" ")
+}
+
+fn parse_keys(notation: &str) -> Option<Vec<InputSeq>> {
+    let keys = notation
+        .split_whitespace()
+        .map(parse_key)
+        .collect::<Option<Vec<_>>>()?;
+    if keys.is_empty() {
+        None
+    } else {
+        Some(keys)
+    }
+}
+
+pub enum KeyLookup {
+    Command(Command),
+    Prefix, // Keys are the beginning of a chord
+    NotFound,
+}
+
+#[derive(Clone)]
+pub struct Keymap {
+    // Key sequences are normalized. Bindings keep the order for showing help
+    bindings: Vec<(Vec<InputSeq>, Command)>,
+}
+
+impl Default for Keymap {
+    fn default() -> Keymap {
+        let bindings = DEFAULT_BINDINGS
+            .iter()
+            .map(|(keys, cmd)| (parse_keys(keys).unwrap(), *cmd))
+            .collect();
+        Keymap { bindings }
+    }
+}
+
+impl Keymap {
+    // Bind keys to command. Existing bindings which conflict with the keys are removed. Chord such
+    // as "Ctrl-X Ctrl-S" conflicts with "Ctrl-X" since "Ctrl-X" would never wait for next key
+    pub fn bind(&mut self, keys: Vec<InputSeq>, cmd: Option<Command>) {
+        self.bindings
+            .retain(|(k, _)| !k.starts_with(&keys) && !keys.starts_with(k));
+        if let Some(cmd) = cmd {
+            self.bindings.push((keys, cmd));
+        }
+    }
+
+    // Build keymap from entries of [keymap] table on top of the default bindings
+    pub fn from_entries(entries: &BTreeMap<String, String>) -> Result<Keymap, String> {
+        let mut bound: Vec<(&str, Vec<InputSeq>)> = vec![];
+        let mut keymap = Keymap::default();
+        for (notation, name) in entries.iter() {
+            let keys = parse_keys(notation)
+                .ok_or_else(|| format!("Invalid key sequence '{}'", notation))?;
+            let cmd = match name.as_str() {
+                "none" => None,
+                name => Some(
+                    Command::from_name(name)
+                        .ok_or_else(|| format!("Unknown command '{}'", name))?,
+                ),
+            };
+            if let Some((other, _)) = bound
+                .iter()
+                .find(|(_, k)| k.starts_with(&keys) || keys.starts_with(k))
+            {
+                return Err(format!(
+                    "Key sequence '{}' conflicts with '{}'",
+                    notation, other
+                ));
+            }
+            keymap.bind(keys.clone(), cmd);
+            bound.push((notation, keys));
+        }
+
+        Ok(keymap)
+    }
+
+    pub fn lookup(&self, keys: &[InputSeq]) -> KeyLookup {
+        let keys: Vec<_> = keys.iter().map(normalize).collect();
+        let mut found = KeyLookup::NotFound;
+        for (k, cmd) in self.bindings.iter() {
+            if *k == keys {
+                return KeyLookup::Command(*cmd);
+            }
+            if k.starts_with(&keys) {
+                found = KeyLookup::Prefix;
+            }
+        }
+        found
+    }
+
+    pub fn help(&self) -> String {
+        let lines: Vec<_> = COMMANDS
+            .iter()
+            .filter_map(|(cmd, _, desc)| {
+                let keys: Vec<_> = self
+                    .bindings
+                    .iter()
+                    .filter(|(_, c)| c == cmd)
+                    .map(|(k, _)| format_keys(k))
+                    .collect();
+                if keys.is_empty() {
+                    None
+                } else {
+                    Some((keys.join(" or "), desc))
+                }
+            })
+            .collect();
+
+        let width = lines.iter().map(|(k, _)| k.len()).max().unwrap_or(0);
+        let mut help = String::new();
+        for (keys, desc) in lines.iter() {
+            writeln!(help, "    {:width$} : {}", keys, desc, width = width).unwrap();
+        }
+        help.pop(); // Remove last newline
+        help
+    }
+}
@@ -1,9 +1,97 @@
+use crate::error::{Error, Result};
+use crate::glob;
+use crate::highlight::SyntaxHighlight;
+use crate::user_syntax::{self, SyntaxDefinition};
+use std::cmp;
 use std::ffi::OsStr;
+use std::fmt;
+use std::iter;
 use std::path::Path;
+use std::sync::OnceLock;
 
+// Max number of lines scanned to detect indentation of file
+const INDENT_DETECT_LINES: usize = 1000;
+// Number of lines at top and bottom of file where Vim modelines are searched (same as Vim's default)
+const MODELINE_LINES: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Indent {
-    AsIs,
-    Fixed(&'static str),
+    Tab,
+    Spaces(usize),
+}
+
+impl Indent {
+    // Guess indentation from leading whitespaces of lines. Spaces indent width is the most frequent
+    // difference of indent widths between adjacent indented lines. None is returned when no line
+    // is indented.
+    pub fn detect<'a, I: Iterator<Item = &'a str>>(lines: I) -> Option<Indent> {
+        let mut tab_lines = 0;
+        let mut space_lines = 0;
+        let mut widths = [0usize; 9]; // Index is indent width (0 is unused)
+        let mut prev_width = 0;
+
+        for line in lines.take(INDENT_DETECT_LINES) {
+            let trimmed = line.trim_start_matches([' ', '\t']);
+            if trimmed.is_empty() {
+                continue; // Blank lines don't tell anything
+            }
+            let indent = &line[..line.len() - trimmed.len()];
+
+            if indent.starts_with('\t') {
+                tab_lines += 1;
+                prev_width = 0;
+                continue;
+            }
+            if indent.contains('\t') {
+                continue; // Mixed indentation like "  \t" is ambiguous
+            }
+
+            let width = indent.len();
+            if width > 0 {
+                space_lines += 1;
+            }
+            if width > prev_width && width - prev_width < widths.len() {
+                widths[width - prev_width] += 1;
+            }
+            prev_width = width;
+        }
+
+        if tab_lines == 0 && space_lines == 0 {
+            None
+        } else if tab_lines >= space_lines {
+            Some(Indent::Tab)
+        } else {
+            // When counts are the same, smaller width is preferred
+            let (width, _) = widths
+                .iter()
+                .enumerate()
+                .skip(1)
+                .fold((0, 0), |acc, (w, &n)| if n > acc.1 { (w, n) } else { acc });
+            if width == 0 {
+                None
+            } else {
+                Some(Indent::Spaces(width))
+            }
+        }
+    }
+}
+
+impl fmt::Display for Indent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Indent::Tab => write!(f, "tab"),
+            Indent::Spaces(n) => write!(f, "spaces:{}", n),
+        }
+    }
+}
+
+// Edits applied to text buffer just before saving it to file. All of them are disabled by default
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct SaveHooks {
+    // Remove whitespaces at end of each line
+    pub trim_trailing_whitespace: bool,
+    // Remove blank lines at end of buffer so that the file ends with exactly one newline
+    pub trim_trailing_blank_lines: bool,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -16,9 +104,91 @@ pub enum Language {
     Cpp,
     Python,
     Julia,
+    Shell,
+    Make,
+    Dockerfile,
+    Toml,
+    Yaml,
+    Markdown,
+    Json,
+    Custom(usize), // Index of user-defined language
+}
+
+const BUILTIN_LANGUAGES: &[Language] = {
+    use Language::*;
+    &[
+        C, Rust, JavaScript, Go, Cpp, Python, Julia, Shell, Make, Dockerfile, Toml, Yaml, Markdown,
+        Json,
+    ]
+};
+
+// Language registered from user's syntax definition file
+pub struct UserLanguage {
+    name: &'static str,
+    file_exts: &'static [&'static str],
+    indent: Indent,
+    pub syntax: SyntaxHighlight,
+}
+
+impl UserLanguage {
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn file_exts(&self) -> &'static [&'static str] {
+        self.file_exts
+    }
+
+    pub fn indent(&self) -> Indent {
+        self.indent
+    }
+}
+
+// Languages built from user's syntax definitions. Index of each language is the index of
+// Language::Custom
+pub struct UserLanguages(Vec<UserLanguage>);
+
+impl UserLanguages {
+    pub fn new(defs: Vec<SyntaxDefinition>) -> UserLanguages {
+        let langs = defs
+            .into_iter()
+            .enumerate()
+            .map(|(i, mut def)| UserLanguage {
+                name: user_syntax::leak_str(std::mem::take(&mut def.name)),
+                file_exts: user_syntax::leak_strs(std::mem::take(&mut def.file_extensions)),
+                indent: def.indent(),
+                syntax: SyntaxHighlight::from_definition(Language::Custom(i), def),
+            })
+            .collect();
+        UserLanguages(langs)
+    }
+
+    pub fn languages(&self) -> &[UserLanguage] {
+        &self.0
+    }
+}
+
+// User-defined languages are registered only once at startup
+static USER_LANGUAGES: OnceLock<UserLanguages> = OnceLock::new();
+
+fn user_languages() -> &'static [UserLanguage] {
+    USER_LANGUAGES
+        .get()
+        .map(UserLanguages::languages)
+        .unwrap_or(&[])
 }
 
 impl Language {
+    pub(crate) fn register_user_languages(langs: UserLanguages) -> Result<()> {
+        USER_LANGUAGES
+            .set(langs)
+            .map_err(|_| Error::UserLanguagesRegisteredTwice)
+    }
+
+    pub(crate) fn user_language(idx: usize) -> &'static UserLanguage {
+        &user_languages()[idx]
+    }
+
     pub fn name(self) -> &'static str {
         use Language::*;
         match self {
@@ -30,6 +200,29 @@ impl Language {
             Cpp => "c++",
             Python => "python",
             Julia => "julia",
+            Shell => "sh",
+            Make => "make",
+            Dockerfile => "dockerfile",
+            Toml => "toml",
+            Yaml => "yaml",
+            Markdown => "markdown",
+            Json => "json",
+            Custom(idx) => Self::user_language(idx).name(),
+        }
+    }
+
+    // Other names used in modelines
+    fn aliases(self) -> &'static [&'static str] {
+        use Language::*;
+        match self {
+            Plain => &["text"],
+            Cpp => &["cpp"],
+            JavaScript => &["js"],
+            Shell => &["bash", "zsh", "ksh", "shell", "shell-script"],
+            Make => &["makefile"],
+            Yaml => &["yml"],
+            Markdown => &["md"],
+            _ => &[],
         }
     }
 
@@ -44,27 +237,275 @@ impl Language {
             Cpp => &["cpp", "hpp", "cxx", "hxx", "cc", "hh"],
             Python => &["py"],
             Julia => &["jl"],
+            Shell => &["sh", "bash", "zsh", "ksh"],
+            Make => &["mk", "mak"],
+            Dockerfile => &["dockerfile"],
+            Toml => &["toml"],
+            Yaml => &["yaml", "yml"],
+            Markdown => &["md", "markdown", "mkd", "mkdn"],
+            Json => &["json"],
+            Custom(idx) => Self::user_language(idx).file_exts(),
         }
     }
 
+    // Glob patterns of well-known file names which don't tell language by their extensions
+    fn file_names(self) -> &'static [&'static str] {
+        use Language::*;
+        match self {
+            JavaScript => &["Jakefile"],
+            Python => &["SConstruct", "SConscript", "wscript"],
+            Shell => &[
+                ".bashrc",
+                ".bash_profile",
+                ".bash_login",
+                ".bash_logout",
+                ".profile",
+                ".zshrc",
+                ".zshenv",
+                ".zprofile",
+                ".zlogin",
+                ".zlogout",
+                ".kshrc",
+                "PKGBUILD",
+                "APKBUILD",
+            ],
+            Make => &["Makefile", "makefile", "GNUmakefile"],
+            Dockerfile => &["Dockerfile", "Dockerfile.*", "Containerfile"],
+            Toml => &["Cargo.lock", "Pipfile"],
+            Yaml => &[".clang-format", ".clang-tidy"],
+            Json => &[".babelrc", ".eslintrc", "composer.lock", "flake.lock"],
+            _ => &[],
+        }
+    }
+
+    // Interpreter names in shebang. Version suffix such as '3.8' in 'python3.8' is ignored
+    fn interpreters(self) -> &'static [&'static str] {
+        use Language::*;
+        match self {
+            JavaScript => &["node", "nodejs", "deno", "bun"],
+            Python => &["python", "pypy"],
+            Julia => &["julia"],
+            Shell => &["sh", "bash", "zsh", "ksh", "dash", "ash", "mksh"],
+            Make => &["make"],
+            Rust => &["rust-script", "run-cargo-script"],
+            _ => &[],
+        }
+    }
+
+    // All languages except for plain. User-defined languages come first to override built-in ones
+    fn all() -> impl Iterator<Item = Language> {
+        (0..user_languages().len())
+            .map(Language::Custom)
+            .chain(BUILTIN_LANGUAGES.iter().copied())
+    }
+
+    // Names of all languages including plain text, which can be passed to from_name()
+    pub fn names() -> impl Iterator<Item = &'static str> {
+        iter::once(Language::Plain)
+            .chain(Self::all())
+            .map(Language::name)
+    }
+
+    pub fn from_name(name: &str) -> Option<Language> {
+        let name = name.to_ascii_lowercase();
+        iter::once(Language::Plain)
+            .chain(Self::all())
+            .find(|l| l.name() == name || l.aliases().contains(&name.as_str()))
+    }
+
     pub fn indent(self) -> Indent {
         use Language::*;
         match self {
-            Plain | Go => Indent::AsIs,
-            C | Rust | Cpp | Python | Julia => Indent::Fixed("    "),
-            JavaScript => Indent::Fixed("  "),
+            Plain | Go | Make => Indent::Tab,
+            C | Rust | Cpp | Python | Julia | Dockerfile | Toml | Markdown => Indent::Spaces(4),
+            JavaScript | Shell | Yaml | Json => Indent::Spaces(2),
+            Custom(idx) => Self::user_language(idx).indent(),
         }
     }
 
+    // Detect language from file name and file extension
     pub fn detect<P: AsRef<Path>>(path: P) -> Language {
-        use Language::*;
-        if let Some(ext) = path.as_ref().extension().and_then(OsStr::to_str) {
-            for lang in &[C, Rust, JavaScript, Go, Cpp, Python, Julia] {
-                if lang.file_exts().contains(&ext) {
-                    return *lang;
-                }
+        let path = path.as_ref();
+        if let Some(name) = path.file_name().and_then(OsStr::to_str) {
+            if let Some(lang) =
+                Self::all().find(|l| l.file_names().iter().any(|p| glob::matches(p, name)))
+            {
+                return lang;
+            }
+        }
+        if let Some(ext) = path.extension().and_then(OsStr::to_str) {
+            if let Some(lang) = Self::all().find(|l| l.file_exts().contains(&ext)) {
+                return lang;
             }
         }
-        Plain
+        Language::Plain
+    }
+
+    // Detect language from the file path and its content. Modeline is prioritized over file path
+    // and shebang is only used when file path does not tell the language.
+    pub fn detect_with_content<'a, P, I>(path: P, lines: I) -> Language
+    where
+        P: AsRef<Path>,
+        I: DoubleEndedIterator<Item = &'a str> + ExactSizeIterator + Clone,
+    {
+        if let Some(lang) = Self::detect_modeline(lines.clone()) {
+            return lang;
+        }
+        match Self::detect(path) {
+            Language::Plain => lines
+                .clone()
+                .next()
+                .and_then(Self::detect_shebang)
+                .unwrap_or(Language::Plain),
+            lang => lang,
+        }
+    }
+
+    fn detect_shebang(line: &str) -> Option<Language> {
+        let mut args = line.strip_prefix("#!")?.split_whitespace();
+        let mut cmd = args.next()?.rsplit('/').next()?;
+        if cmd == "env" {
+            // Skip options and variable assignments like `#!/usr/bin/env -S FOO=1 python3`
+            cmd = args.find(|a| !a.starts_with('-') && !a.contains('='))?;
+        }
+        let cmd = cmd.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+        Self::all().find(|l| l.interpreters().contains(&cmd))
+    }
+
+    fn detect_modeline<'a, I>(lines: I) -> Option<Language>
+    where
+        I: DoubleEndedIterator<Item = &'a str> + ExactSizeIterator + Clone,
+    {
+        // Emacs modeline must be at the first line or the second line after shebang. Unknown mode
+        // falls through to Vim modeline
+        if let Some(lang) = lines
+            .clone()
+            .take(2)
+            .find_map(emacs_modeline)
+            .and_then(Language::from_name)
+        {
+            return Some(lang);
+        }
+
+        let len = lines.len();
+        let head = lines.clone().take(MODELINE_LINES);
+        let tail = lines.skip(cmp::max(len, MODELINE_LINES) - MODELINE_LINES);
+        head.chain(tail)
+            .find_map(vim_modeline)
+            .and_then(Language::from_name)
+    }
+}
+
+// Parse Emacs modeline such as '-*- mode: python -*-' or '-*- python -*-'
+fn emacs_modeline(line: &str) -> Option<&str> {
+    let (_, rest) = line.split_once("-*-")?;
+    let (vars, _) = rest.split_once("-*-")?;
+    if !vars.contains(':') {
+        return Some(vars.trim());
+    }
+    vars.split(';').find_map(|var| {
+        let (key, value) = var.split_once(':')?;
+        if key.trim().eq_ignore_ascii_case("mode") {
+            Some(value.trim())
+        } else {
+            None
+        }
+    })
+}
+
+// Parse Vim modeline such as 'vim: set ft=python :' or 'vi: filetype=python'
+fn vim_modeline(line: &str) -> Option<&str> {
+    let start = ["vim:", "vi:", "ex:"].iter().find_map(|marker| {
+        line.match_indices(marker)
+            .find(|(i, _)| *i == 0 || line[..*i].ends_with(|c: char| c.is_whitespace()))
+            .map(|(i, m)| i + m.len())
+    })?;
+    line[start..]
+        .split(|c: char| c.is_whitespace() || c == ':')
+        .find_map(|opt| {
+            let (key, value) = opt.split_once('=')?;
+            match key {
+                "ft" | "filetype" | "syn" | "syntax" => Some(value),
+                _ => None,
+            }
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect_indent() {
+        for (lines, expected) in &[
+            (&["foo", "bar"][..], None),
+            (&["", "   "][..], None),
+            (&["if x {", "\tfoo", "}"][..], Some(Indent::Tab)),
+            (&["a", "  b", "    c", "  d"][..], Some(Indent::Spaces(2))),
+            (
+                &["a", "    b", "        c", "d"][..],
+                Some(Indent::Spaces(4)),
+            ),
+            // Indented continuation lines don't hide the most frequent width
+            (
+                &["a", "    b", "      c", "    d", "        e"][..],
+                Some(Indent::Spaces(4)),
+            ),
+            (&["a", "\tb", "\tc", "    d"][..], Some(Indent::Tab)),
+            (&["a", "  \tb"][..], None),
+            // Leading space of block comment lines counts as indentation
+            (&["/*", " * foo", " */"][..], Some(Indent::Spaces(1))),
+        ] {
+            assert_eq!(
+                Indent::detect(lines.iter().copied()),
+                *expected,
+                "{:?}",
+                lines
+            );
+        }
+    }
+
+    #[test]
+    fn detect_language_from_content() {
+        for (path, lines, expected) in &[
+            ("foo", &["#!/bin/sh"][..], Language::Shell),
+            ("foo", &["#!/usr/bin/env python3.8"][..], Language::Python),
+            (
+                "foo",
+                &["#!/usr/bin/env -S node --harmony"][..],
+                Language::JavaScript,
+            ),
+            ("foo.rs", &["#!/bin/sh"][..], Language::Rust),
+            ("Makefile", &["all:"][..], Language::Make),
+            ("Dockerfile.dev", &["FROM alpine"][..], Language::Dockerfile),
+            (".bashrc", &[""][..], Language::Shell),
+            (
+                "foo.txt",
+                &["a", "b", "# vim: set ft=python :"][..],
+                Language::Python,
+            ),
+            ("foo.c", &["/* vi:filetype=cpp */"][..], Language::Cpp),
+            (
+                "foo",
+                &["#!/bin/sh", "# -*- mode: c++ -*-"][..],
+                Language::Cpp,
+            ),
+            ("foo", &["/* -*- js -*- */"][..], Language::JavaScript),
+            ("foo", &["novim: ft=rust"][..], Language::Plain),
+            (
+                "foo",
+                &["# -*- mode: unknown -*-", "# vim: ft=python"][..],
+                Language::Python,
+            ),
+            ("foo.rs", &["// -*- mode: unknown -*-"][..], Language::Rust),
+            (
+                "foo",
+                &["#!/bin/sh", "# -*- mode: unknown -*-"][..],
+                Language::Shell,
+            ),
+        ] {
+            let actual = Language::detect_with_content(path, lines.iter().copied());
+            assert_eq!(actual, *expected, "{:?} {:?}", path, lines);
+        }
     }
 }
@@ -8,24 +8,42 @@
 #![allow(clippy::useless_let_if_seq)]
 #![allow(clippy::cognitive_complexity)]
 
+mod config;
+mod diff;
 mod edit_diff;
 mod editor;
+mod editorconfig;
 mod error;
+mod fuzzy;
+mod glob;
+mod grep;
 mod highlight;
 mod history;
 mod input;
+mod keymap;
 mod language;
+mod project;
 mod prompt;
 mod row;
 mod screen;
+mod shell;
 mod signal;
 mod status_bar;
 mod term_color;
+#[cfg(test)]
+mod test_util;
 mod text_buffer;
+mod theme;
+mod user_syntax;
 
+pub use config::Config;
 pub use editor::Editor;
 pub use error::{Error, Result};
 pub use input::{InputSeq, KeySeq, StdinRawMode};
+pub use keymap::Keymap;
 pub use language::Language;
-pub use screen::{Screen, HELP, VERSION};
+pub use screen::{Screen, VERSION};
+pub use term_color::Background;
 pub use text_buffer::{Lines, TextBuffer};
+pub use theme::{detect_background, disable_colors};
+pub use user_syntax::load_user_syntaxes;
@@ -2,9 +2,10 @@
 //   Build Your Own Text Editor: https://viewsourcecode.org/snaptoken/kilo/index.html
 //   VT100 User Guide: https://vt100.net/docs/vt100-ug/chapter3.html
 use getopts::Options;
-use kiro_editor::{self as kiro, Editor, StdinRawMode, HELP, VERSION};
+use kiro_editor::{self as kiro, Background, Config, Editor, Keymap, StdinRawMode, VERSION};
 use std::env;
 use std::io;
+use std::path::Path;
 use std::process::exit;
 
 #[global_allocator]
@@ -18,22 +19,27 @@ Kiro is a tiny UTF-8 text editor on terminals for Unix-like systems.
 Specify file paths to edit as a command argument or run without argument to
 start to write a new text.
 Help can show up with key mapping Ctrl-?.
+Configuration is loaded from $XDG_CONFIG_HOME/kiro/config.toml or
+~/.config/kiro/config.toml.
 
 Usage:
     {prog} [options] [FILES...]
 
 Mappings:
-    {maps}",
+{maps}",
         prog = program,
-        maps = HELP,
+        maps = Keymap::default().help(),
     );
     println!("{}", opts.usage(&description));
 }
 
-fn edit(files: Vec<String>) -> kiro::Result<()> {
+fn edit(files: Vec<String>, background: Option<Background>, config: Config) -> kiro::Result<()> {
     // TODO: Read input from stdin before start
-    let input = StdinRawMode::new()?.input_keys();
-    Editor::open(input, io::stdout(), None, &files)?.edit()
+    let mut input = StdinRawMode::new()?.input_keys();
+    let pending = kiro::detect_background(background.or(config.background), &mut input)?;
+    // Keys typed while detecting background are handled first
+    let input = pending.into_iter().map(Ok).chain(input);
+    Editor::open(input, io::stdout(), None, &files, config)?.edit()
 }
 
 fn main() {
@@ -43,6 +49,24 @@ fn main() {
     let mut opts = Options::new();
     opts.optflag("v", "version", "Print version");
     opts.optflag("h", "help", "Print this help");
+    opts.optopt(
+        "",
+        "background",
+        "Background color of terminal. Detected by default",
+        "dark|light",
+    );
+    opts.optflag("", "no-color", "Render screen without colors");
+    opts.optopt(
+        "",
+        "config",
+        "Path to config file instead of config.toml in config directory",
+        "PATH",
+    );
+    opts.optflag(
+        "",
+        "no-config",
+        "Start without loading config file and syntax files",
+    );
 
     let matches = match opts.parse(argv) {
         Ok(m) => m,
@@ -62,7 +86,43 @@ fn main() {
         return;
     }
 
-    if let Err(err) = edit(matches.free) {
+    let background = match matches.opt_str("background").map(|s| s.parse()).transpose() {
+        Ok(bg) => bg,
+        Err(e) => {
+            eprintln!("Error: {}. Please see --help for more details", e);
+            exit(1);
+        }
+    };
+
+    let no_config = matches.opt_present("no-config");
+    if matches.opt_present("config") && no_config {
+        eprintln!("Error: --config and --no-config cannot be specified at the same time");
+        exit(1);
+    }
+
+    // Syntax files in config directory are also user config. They are registered before parsing
+    // config file since per-language options in the config file may refer user-defined languages
+    let config = if no_config {
+        Ok(Config::default())
+    } else {
+        kiro::load_user_syntaxes().and_then(|_| match matches.opt_str("config") {
+            Some(path) => Config::parse(Path::new(&path)),
+            None => Config::load_user(),
+        })
+    };
+    let config = match config {
+        Ok(c) => c,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            exit(1);
+        }
+    };
+
+    if matches.opt_present("no-color") || !config.color {
+        kiro::disable_colors();
+    }
+
+    if let Err(err) = edit(matches.free, background, config) {
         eprintln!("Error: {}", err);
         exit(1);
     }
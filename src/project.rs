@@ -0,0 +1,177 @@
+// Walk files in a project directory. '.git' and 'target' directories and files ignored by
+// .gitignore files are skipped.
+//
+// Supported syntax of .gitignore: https://git-scm.com/docs/gitignore#_pattern_format
+//   - Blank lines and lines starting with '#' are ignored
+//   - '!' at start negates the pattern
+//   - '/' at end matches only directories
+//   - Pattern containing '/' (except for the end) is relative to the directory of .gitignore.
+//     Otherwise it matches at any level
+//   - Wildcards are the same as glob.rs
+use crate::glob;
+use std::fs;
+use std::iter;
+use std::path::Path;
+
+// Stop walking on large directories such as home directory
+const MAX_FILES: usize = 100_000;
+
+struct IgnorePattern {
+    dir: String, // Directory of .gitignore relative to root. It ends with '/' unless it is root
+    glob: String,
+    anchored: bool,
+    dir_only: bool,
+    negated: bool,
+}
+
+impl IgnorePattern {
+    fn parse(line: &str, dir: &str) -> Option<IgnorePattern> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(l) => (true, l),
+            None => (false, line),
+        };
+        let (dir_only, line) = match line.strip_suffix('/') {
+            Some(l) => (true, l),
+            None => (false, line),
+        };
+        // '**/foo' is the same as 'foo'
+        let line = line.strip_prefix("**/").unwrap_or(line);
+        let anchored = line.contains('/');
+        let line = line.strip_prefix('/').unwrap_or(line);
+        if line.is_empty() {
+            return None;
+        }
+        Some(IgnorePattern {
+            dir: dir.to_string(),
+            glob: line.to_string(),
+            anchored,
+            dir_only,
+            negated,
+        })
+    }
+
+    fn matches(&self, path: &str, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        let path = match path.strip_prefix(self.dir.as_str()) {
+            Some(p) => p,
+            None => return false,
+        };
+        if self.anchored {
+            return glob::matches(&self.glob, path);
+        }
+        // Not anchored pattern matches at any level
+        iter::once(path)
+            .chain(path.match_indices('/').map(|(i, _)| &path[i + 1..]))
+            .any(|p| glob::matches(&self.glob, p))
+    }
+}
+
+fn is_ignored(patterns: &[IgnorePattern], path: &str, is_dir: bool) -> bool {
+    // Later pattern takes precedence
+    patterns
+        .iter()
+        .rev()
+        .find(|p| p.matches(path, is_dir))
+        .is_some_and(|p| !p.negated)
+}
+
+fn walk(root: &Path, dir: &str, patterns: &mut Vec<IgnorePattern>, files: &mut Vec<String>) {
+    let path = root.join(dir);
+    let num_patterns = patterns.len();
+    if let Ok(content) = fs::read_to_string(path.join(".gitignore")) {
+        patterns.extend(content.lines().filter_map(|l| IgnorePattern::parse(l, dir)));
+    }
+
+    let mut entries: Vec<_> = match fs::read_dir(&path) {
+        Ok(entries) => entries.filter_map(|e| e.ok()).collect(),
+        Err(_) => vec![],
+    };
+    entries.sort_by_key(|e| e.file_name());
+
+    for entry in entries {
+        if files.len() >= MAX_FILES {
+            break;
+        }
+        let name = match entry.file_name().into_string() {
+            Ok(name) => name,
+            Err(_) => continue,
+        };
+        if name == ".git" || name == "target" {
+            continue;
+        }
+        // Symbolic links to directories are not followed to avoid infinite loop
+        let is_dir = match entry.file_type() {
+            Ok(t) if t.is_dir() => true,
+            Ok(_) if entry.path().is_file() => false,
+            _ => continue,
+        };
+        let path = format!("{}{}", dir, name);
+        if is_ignored(patterns, &path, is_dir) {
+            continue;
+        }
+        if is_dir {
+            walk(root, &format!("{}/", path), patterns, files);
+        } else {
+            files.push(path);
+        }
+    }
+
+    patterns.truncate(num_patterns);
+}
+
+// Paths of files in the directory relative to it. Directory separator is '/'
+pub fn files<P: AsRef<Path>>(root: P) -> Vec<String> {
+    let mut files = vec![];
+    walk(root.as_ref(), "", &mut vec![], &mut files);
+    files
+}
+
+#[cfg(test)]
+mod tests {
+    use super::files;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn walk_project_files() {
+        let root = TempDir::new("project-files");
+        for file in &[
+            ".git/HEAD",
+            "target/foo",
+            "README.md",
+            "src/lib.rs",
+            "src/lib.rs.bak",
+            "src/bin/main.rs",
+            "logs/a.log",
+            "logs/keep.log",
+            "docs/index.md",
+            "docs/build/index.html",
+            "build/out",
+        ] {
+            root.write(file, "");
+        }
+        root.write(
+            ".gitignore",
+            "# comment\n*.bak\nlogs/*.log\n!keep.log\n/build/\n",
+        );
+        root.write("docs/.gitignore", "build\n");
+
+        assert_eq!(
+            files(&root),
+            vec![
+                ".gitignore",
+                "README.md",
+                "docs/.gitignore",
+                "docs/index.md",
+                "logs/keep.log",
+                "src/bin/main.rs",
+                "src/lib.rs",
+            ],
+        );
+    }
+}
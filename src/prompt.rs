@@ -1,11 +1,17 @@
 use crate::error::Result;
+use crate::fuzzy;
 use crate::highlight::{Highlight, Highlighting, RegionHighlight};
 use crate::input::{InputSeq, KeySeq};
+use crate::keymap::Command;
+use crate::language::Language;
+use crate::project;
 use crate::row::Row;
 use crate::screen::Screen;
 use crate::status_bar::StatusBar;
 use crate::text_buffer::TextBuffer;
 use std::cmp::{self, Ordering};
+use std::env;
+use std::fs;
 use std::io::Write;
 
 #[derive(PartialEq)]
@@ -35,6 +41,16 @@ pub trait Action: Sized {
     ) -> Result<PromptResult> {
         Ok(result)
     }
+
+    // Returns candidates to complete the input with Tab key. No completion by default
+    fn complete(&mut self, _input: &str) -> Vec<String> {
+        vec![]
+    }
+
+    // Draws on screen after the prompt is rendered. Nothing is drawn by default
+    fn render<W: Write>(&mut self, _prompt: &mut Prompt<'_, W>) -> Result<()> {
+        Ok(())
+    }
 }
 
 pub struct NoAction;
@@ -44,6 +60,231 @@ impl Action for NoAction {
     }
 }
 
+pub struct LanguageSelect;
+impl Action for LanguageSelect {
+    fn new<W: Write>(_prompt: &mut Prompt<'_, W>) -> Self {
+        Self
+    }
+
+    fn complete(&mut self, input: &str) -> Vec<String> {
+        let input = input.to_ascii_lowercase();
+        Language::names()
+            .filter(|n| n.starts_with(&input))
+            .map(str::to_string)
+            .collect()
+    }
+}
+
+pub struct CommandSelect;
+impl Action for CommandSelect {
+    fn new<W: Write>(_prompt: &mut Prompt<'_, W>) -> Self {
+        Self
+    }
+
+    fn complete(&mut self, input: &str) -> Vec<String> {
+        if input.contains(' ') {
+            return vec![]; // Argument is not completed
+        }
+        let names = Command::all()
+            .filter(|c| *c != Command::Palette)
+            .map(Command::name);
+        fuzzy::filter(input, names)
+            .into_iter()
+            .map(str::to_string)
+            .collect()
+    }
+}
+
+// Expand '~' at start of path to home directory
+pub fn expand_home(path: &str) -> String {
+    match (path.strip_prefix('~'), env::var("HOME")) {
+        (Some(rest), Ok(home)) if rest.is_empty() || rest.starts_with('/') => home + rest,
+        _ => path.to_string(),
+    }
+}
+
+pub struct PathComplete;
+impl Action for PathComplete {
+    fn new<W: Write>(_prompt: &mut Prompt<'_, W>) -> Self {
+        Self
+    }
+
+    // Complete entries in the directory of the input. Directories end with '/' so that next
+    // completion can enter them
+    fn complete(&mut self, input: &str) -> Vec<String> {
+        let input = if input == "~" { "~/" } else { input };
+        let (dir, prefix) = match input.rfind('/') {
+            Some(idx) => input.split_at(idx + 1),
+            None => ("", input),
+        };
+        let entries = match fs::read_dir(if dir.is_empty() {
+            ".".to_string()
+        } else {
+            expand_home(dir)
+        }) {
+            Ok(entries) => entries,
+            Err(_) => return vec![],
+        };
+
+        let mut candidates: Vec<_> = entries
+            .filter_map(|entry| {
+                let entry = entry.ok()?;
+                let name = entry.file_name().into_string().ok()?;
+                // Hidden files are candidates only when '.' is input explicitly
+                if !name.starts_with(prefix) || name.starts_with('.') && !prefix.starts_with('.') {
+                    return None;
+                }
+                let slash = if entry.path().is_dir() { "/" } else { "" };
+                Some(format!("{}{}{}", dir, name, slash))
+            })
+            .collect();
+        candidates.sort();
+        candidates
+    }
+}
+
+// Find file in the current directory with fuzzy matching. Top matches are listed in text area
+pub struct FileFinder {
+    files: Vec<String>,
+    matches: Vec<String>,
+    selected: usize,
+}
+
+impl FileFinder {
+    fn update_matches(&mut self, query: &str, max: usize) {
+        self.matches = fuzzy::filter(query, self.files.iter())
+            .into_iter()
+            .take(max)
+            .cloned()
+            .collect();
+        self.selected = 0;
+    }
+}
+
+impl Action for FileFinder {
+    fn new<W: Write>(prompt: &mut Prompt<'_, W>) -> Self {
+        let mut new = Self {
+            files: project::files("."),
+            matches: vec![],
+            selected: 0,
+        };
+        new.update_matches("", prompt.screen.rows());
+        new
+    }
+
+    fn on_seq<W: Write>(
+        &mut self,
+        prompt: &mut Prompt<'_, W>,
+        input: &str,
+        seq: InputSeq,
+    ) -> Result<bool> {
+        use KeySeq::*;
+        match (seq.key, seq.ctrl) {
+            (UpKey, ..) | (Key(b'p'), true) => self.selected = self.selected.saturating_sub(1),
+            (DownKey, ..) | (Key(b'n'), true) => {
+                if self.selected + 1 < self.matches.len() {
+                    self.selected += 1;
+                }
+            }
+            _ => self.update_matches(input, prompt.screen.rows()),
+        }
+        Ok(true)
+    }
+
+    fn on_end<W: Write>(
+        self,
+        prompt: &mut Prompt<'_, W>,
+        result: PromptResult,
+    ) -> Result<PromptResult> {
+        prompt.screen.set_dirty_start(prompt.screen.rowoff); // Redraw text buffer
+        match result {
+            PromptResult::Input(_) => match self.matches.into_iter().nth(self.selected) {
+                Some(path) => Ok(PromptResult::Input(path)),
+                None => {
+                    prompt.screen.set_error_message("No file matched");
+                    Ok(PromptResult::Canceled)
+                }
+            },
+            PromptResult::Canceled => Ok(result),
+        }
+    }
+
+    fn render<W: Write>(&mut self, prompt: &mut Prompt<'_, W>) -> Result<()> {
+        prompt.screen.render_list(&self.matches, self.selected)
+    }
+}
+
+// State of completion while Tab key is repeatedly input
+struct Completion {
+    candidates: Vec<String>,
+    index: Option<usize>,
+}
+
+impl Completion {
+    fn common_prefix(&self) -> &str {
+        let first = &self.candidates[0];
+        let len = self.candidates[1..].iter().fold(first.len(), |len, c| {
+            first[..len]
+                .char_indices()
+                .zip(c.chars())
+                .find(|((_, l), r)| l != r)
+                .map(|((i, _), _)| i)
+                .unwrap_or_else(|| cmp::min(len, c.len()))
+        });
+        &first[..len]
+    }
+
+    // Completes the input. First input is completed with common prefix of candidates. When it can
+    // no longer be extended, candidates are cycled one by one
+    fn next(&mut self, input: &mut String) {
+        if self.candidates.len() == 1 {
+            self.index = Some(0);
+            *input = self.candidates[0].clone();
+            return;
+        }
+        if self.index.is_none() {
+            let prefix = self.common_prefix();
+            if prefix.len() > input.len() {
+                *input = prefix.to_string();
+                return;
+            }
+        }
+        let idx = self
+            .index
+            .map(|i| (i + 1) % self.candidates.len())
+            .unwrap_or(0);
+        self.index = Some(idx);
+        *input = self.candidates[idx].clone();
+    }
+
+    // Only one candidate was completed. Next Tab key starts new completion (e.g. entering directory)
+    fn is_done(&self) -> bool {
+        self.candidates.len() == 1 && self.index.is_some()
+    }
+
+    fn message(&self) -> String {
+        // Directory part of path candidates is omitted since it is the same for all candidates
+        let dir_len = self.candidates[0]
+            .trim_end_matches('/')
+            .rfind('/')
+            .map(|i| i + 1)
+            .unwrap_or(0);
+        let mut msg = String::new();
+        for (i, c) in self.candidates.iter().enumerate() {
+            let c = &c[dir_len..];
+            msg.push(' ');
+            if self.index == Some(i) {
+                msg.push('[');
+                msg.push_str(c);
+                msg.push(']');
+            } else {
+                msg.push_str(c);
+            }
+        }
+        msg
+    }
+}
+
 #[derive(Clone, Copy)]
 enum FindDir {
     Back,
@@ -96,8 +337,7 @@ impl TextSearch {
                 .unwrap_or(0),
             FindDir::Back => self.text[..self.current_offset]
                 .char_indices()
-                .rev()
-                .next()
+                .next_back()
                 .map(|(idx, _)| idx)
                 .unwrap_or_else(|| self.text.len()),
         };
@@ -369,10 +609,22 @@ impl<'a, W: Write> Prompt<'a, W> {
         }
     }
 
-    fn render_screen(&mut self, input: &str, template: &PromptTemplate<'_>) -> Result<()> {
-        self.screen.set_info_message(template.build(input));
+    fn render_screen<A: Action>(
+        &mut self,
+        action: &mut A,
+        input: &str,
+        template: &PromptTemplate<'_>,
+        completion: Option<&Completion>,
+    ) -> Result<()> {
+        let mut msg = template.build(input);
+        if let Some(completion) = completion {
+            // Show candidates following the prompt
+            msg.push_str(&completion.message());
+        }
+        self.screen.set_info_message(msg);
         self.sb.update_from_buf(self.buf);
-        self.screen.render(self.buf, &mut self.hl, self.sb)?;
+        self.screen.render(self.buf, self.hl, self.sb)?;
+        action.render(self)?;
 
         let row = self.screen.rows() + 2;
         let col = template.cursor_col(input);
@@ -391,15 +643,14 @@ impl<'a, W: Write> Prompt<'a, W> {
         let mut action = A::new(self);
         let mut buf = String::new();
         let mut canceled = false;
+        let mut completion: Option<Completion> = None;
 
         let template = {
-            let mut it = prompt.as_ref().splitn(2, "{}");
-            let prefix = it.next().unwrap();
-            let suffix = it.next().unwrap();
+            let (prefix, suffix) = prompt.as_ref().split_once("{}").unwrap();
             PromptTemplate::new(prefix, suffix)
         };
 
-        self.render_screen("", &template)?;
+        self.render_screen(&mut action, "", &template, None)?;
 
         while let Some(seq) = input.next() {
             use KeySeq::*;
@@ -407,15 +658,36 @@ impl<'a, W: Write> Prompt<'a, W> {
             if self.screen.maybe_resize(&mut input)? {
                 self.screen.set_dirty_start(self.screen.rowoff);
                 self.sb.redraw = true;
-                self.render_screen(&buf, &template)?;
+                self.render_screen(&mut action, &buf, &template, completion.as_ref())?;
                 continue;
             }
 
             let seq = seq?;
             let prev_len = buf.len();
+            let completing = completion.is_some();
 
             match (&seq.key, seq.ctrl) {
                 (Unidentified, ..) => continue,
+                (Key(b'i'), true) => {
+                    // Tab key
+                    if completion.as_ref().is_some_and(Completion::is_done) {
+                        completion = None;
+                    }
+                    if completion.is_none() {
+                        let candidates = action.complete(&buf);
+                        if !candidates.is_empty() {
+                            completion = Some(Completion {
+                                candidates,
+                                index: None,
+                            });
+                        }
+                    }
+                    if let Some(completion) = &mut completion {
+                        completion.next(&mut buf);
+                    }
+                    self.render_screen(&mut action, &buf, &template, completion.as_ref())?;
+                    continue;
+                }
                 (Key(b'h'), true) | (Key(0x7f), ..) | (DeleteKey, ..) if !buf.is_empty() => {
                     buf.pop();
                 }
@@ -443,10 +715,13 @@ impl<'a, W: Write> Prompt<'a, W> {
                 _ => {}
             }
 
+            // Other keys than Tab finish the completion
+            completion = None;
+
             let should_render = action.on_seq(self, buf.as_str(), seq)?;
 
-            if should_render || prev_len != buf.len() {
-                self.render_screen(&buf, &template)?;
+            if should_render || completing || prev_len != buf.len() {
+                self.render_screen(&mut action, &buf, &template, None)?;
             }
         }
 
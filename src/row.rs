@@ -2,31 +2,34 @@ use crate::error::{Error, Result};
 use std::ops;
 use unicode_width::UnicodeWidthChar;
 
-const TAB_STOP: usize = 8;
+pub const TAB_STOP: usize = 8;
 
-#[derive(Default)]
 pub struct Row {
     buf: String,
     render: String,
     // Cache of byte indices of characters in `buf`. This will be empty when `buf` only contains
     // single byte characters not to allocate memory.
     indices: Vec<usize>,
+    // Width of tab character in `render`
+    tab_stop: usize,
 }
 
 impl Row {
-    pub fn empty() -> Row {
+    pub fn empty(tab_stop: usize) -> Row {
         Row {
             buf: "".to_string(),
             render: "".to_string(),
             indices: Vec::with_capacity(0),
+            tab_stop,
         }
     }
 
-    pub fn new<S: Into<String>>(line: S) -> Result<Row> {
+    pub fn new<S: Into<String>>(line: S, tab_stop: usize) -> Result<Row> {
         let mut row = Row {
             buf: line.into(),
             render: "".to_string(),
             indices: Vec::with_capacity(0),
+            tab_stop,
         };
         row.update_render()?;
         Ok(row)
@@ -92,7 +95,7 @@ impl Row {
                 loop {
                     self.render.push(' ');
                     index += 1;
-                    if index % TAB_STOP == 0 {
+                    if index % self.tab_stop == 0 {
                         break;
                     }
                 }
@@ -124,14 +127,75 @@ impl Row {
     pub fn rx_from_cx(&self, cx: usize) -> usize {
         self[..cx].chars().fold(0, |rx, ch| {
             if ch == '\t' {
-                // Proceed TAB_STOP spaces then subtract spaces by mod TAB_STOP
-                rx + TAB_STOP - (rx % TAB_STOP)
+                // Proceed tab_stop spaces then subtract spaces by mod tab_stop
+                rx + self.tab_stop - (rx % self.tab_stop)
             } else {
                 rx + ch.width_cjk().unwrap()
             }
         })
     }
 
+    // Inverse of rx_from_cx. Column in the middle of tab or double-width character points the
+    // character
+    pub fn cx_from_rx(&self, rx: usize) -> usize {
+        let mut x = 0;
+        for (cx, ch) in self.buf.chars().enumerate() {
+            x += self.render_size(ch, x).1;
+            if rx < x {
+                return cx;
+            }
+        }
+        self.len()
+    }
+
+    // Returns how many characters and columns the character occupies in `render` text. `rx` is
+    // the column where the character is put.
+    fn render_size(&self, ch: char, rx: usize) -> (usize, usize) {
+        if ch == '\t' {
+            let n = self.tab_stop - (rx % self.tab_stop);
+            (n, n)
+        } else {
+            (1, ch.width_cjk().unwrap())
+        }
+    }
+
+    // Index of character in `render` text. It is different from cx when the row contains tabs since
+    // one tab is expanded to multiple spaces
+    pub fn render_idx_from_cx(&self, cx: usize) -> usize {
+        let (mut idx, mut rx) = (0, 0);
+        for ch in self[..cx].chars() {
+            let (n, w) = self.render_size(ch, rx);
+            idx += n;
+            rx += w;
+        }
+        idx
+    }
+
+    // Inverse of render_idx_from_cx. Index in the middle of spaces expanded from tab points the tab
+    pub fn cx_from_render_idx(&self, render_idx: usize) -> usize {
+        let (mut idx, mut rx) = (0, 0);
+        for (cx, ch) in self.buf.chars().enumerate() {
+            let (n, w) = self.render_size(ch, rx);
+            if render_idx < idx + n {
+                return cx;
+            }
+            idx += n;
+            rx += w;
+        }
+        self.len()
+    }
+
+    pub fn tab_stop(&self) -> usize {
+        self.tab_stop
+    }
+
+    pub fn set_tab_stop(&mut self, tab_stop: usize) {
+        if self.tab_stop != tab_stop {
+            self.tab_stop = tab_stop;
+            self.update_render().unwrap();
+        }
+    }
+
     pub fn insert_char(&mut self, at: usize, c: char) {
         if self.len() <= at {
             self.buf.push(c);
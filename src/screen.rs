@@ -1,48 +1,19 @@
+use crate::config::Config;
 use crate::error::{Error, Result};
-use crate::highlight::Highlighting;
+use crate::highlight::{Highlight, Highlighting};
 use crate::input::{InputSeq, KeySeq};
 use crate::row::Row;
 use crate::signal::SigwinchWatcher;
 use crate::status_bar::StatusBar;
-use crate::term_color::{Color, TermColor};
 use crate::text_buffer::TextBuffer;
+use crate::theme::{Face, Theme};
 use std::cmp;
 use std::io::Write;
+use std::ops::Range;
 use std::time::SystemTime;
 use unicode_width::UnicodeWidthChar;
 
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
-pub const HELP: &str = "\
-    Ctrl-Q                        : Quit
-    Ctrl-S                        : Save to file
-    Ctrl-O                        : Open text buffer
-    Ctrl-X                        : Next text buffer
-    Alt-X                         : Previous text buffer
-    Ctrl-P or UP                  : Move cursor up
-    Ctrl-N or DOWN                : Move cursor down
-    Ctrl-F or RIGHT               : Move cursor right
-    Ctrl-B or LEFT                : Move cursor left
-    Ctrl-A or Alt-LEFT or HOME    : Move cursor to head of line
-    Ctrl-E or Alt-RIGHT or END    : Move cursor to end of line
-    Ctrl-[ or Ctrl-V or PAGE DOWN : Next page
-    Ctrl-] or Alt-V or PAGE UP    : Previous page
-    Alt-F or Ctrl-RIGHT           : Move cursor to next word
-    Alt-B or Ctrl-LEFT            : Move cursor to previous word
-    Alt-N or Ctrl-DOWN            : Move cursor to next paragraph
-    Alt-P or Ctrl-UP              : Move cursor to previous paragraph
-    Alt-<                         : Move cursor to top of file
-    Alt->                         : Move cursor to bottom of file
-    Ctrl-H or BACKSPACE           : Delete character
-    Ctrl-D or DELETE              : Delete next character
-    Ctrl-W                        : Delete a word
-    Ctrl-J                        : Delete until head of line
-    Ctrl-K                        : Delete until end of line
-    Ctrl-U                        : Undo last change
-    Ctrl-R                        : Redo last undo change
-    Ctrl-G                        : Search text
-    Ctrl-M                        : New line
-    Ctrl-L                        : Refresh screen
-    Ctrl-?                        : Show this help";
 
 #[derive(PartialEq)]
 enum StatusMessageKind {
@@ -95,6 +66,43 @@ fn too_small_window(width: usize, height: usize) -> bool {
     width < 1 || height < 3
 }
 
+// Enable mouse tracking of button press/release (1000) reported in SGR format (1006)
+// https://invisible-island.net/xterm/ctlseqs/ctlseqs.html#h2-Mouse-Tracking
+const ENABLE_MOUSE: &[u8] = b"\x1b[?1000h\x1b[?1006h";
+const DISABLE_MOUSE: &[u8] = b"\x1b[?1006l\x1b[?1000l";
+
+// Glyphs to show invisible characters on visible whitespaces mode. They are ASCII characters so that
+// their widths are always 1 as well as the whitespaces they replace
+const TAB_GLYPH: char = '>';
+const TRAILING_SPACE_GLYPH: char = '-';
+const NBSP_GLYPH: char = '+';
+
+// Returns glyphs for each character of rendered text of the row. None means the character is
+// rendered as-is. Rendered text and cursor position are not changed since each whitespace is
+// replaced with one glyph and spaces expanded from tab are kept as spaces.
+fn whitespace_glyphs(row: &Row) -> Vec<Option<char>> {
+    let buf = row.buffer();
+    let trailing = buf.trim_end_matches([' ', '\t']).len();
+    let mut glyphs = Vec::with_capacity(row.render_text().len());
+    let mut rx = 0;
+    for (idx, c) in buf.char_indices() {
+        match c {
+            '\t' => {
+                let width = row.tab_stop() - rx % row.tab_stop();
+                glyphs.push(Some(TAB_GLYPH));
+                glyphs.extend(std::iter::repeat_n(Some(' '), width - 1));
+                rx += width;
+                continue;
+            }
+            ' ' if idx >= trailing => glyphs.push(Some(TRAILING_SPACE_GLYPH)),
+            '\u{a0}' => glyphs.push(Some(NBSP_GLYPH)),
+            _ => glyphs.push(None),
+        }
+        rx += c.width_cjk().unwrap_or(1);
+    }
+    glyphs
+}
+
 #[derive(PartialEq, Clone, Copy, Debug)]
 enum DrawMessage {
     Open,
@@ -136,16 +144,27 @@ pub struct Screen<W: Write> {
     // Dirty line which requires rendering update. After this line must be updated since
     // updating line may affect highlights of succeeding lines
     dirty_start: Option<usize>,
+    // Lines which require rendering update without affecting succeeding lines
+    dirty_lines: Vec<usize>,
     // Watch resize signal
     sigwinch: SigwinchWatcher,
-    term_color: TermColor,
+    theme: Theme,
+    // Render tabs, trailing spaces and non-breaking spaces with glyphs
+    show_whitespace: bool,
+    // Mouse tracking is enabled while the screen is shown
+    mouse: bool,
     pub cursor_moved: bool,
     pub rowoff: usize, // Row scroll offset
     pub coloff: usize, // Column scroll offset
 }
 
 impl<W: Write> Screen<W> {
-    pub fn new<I>(size: Option<(usize, usize)>, input: I, mut output: W) -> Result<Self>
+    pub fn new<I>(
+        size: Option<(usize, usize)>,
+        input: I,
+        mut output: W,
+        config: &Config,
+    ) -> Result<Self>
     where
         I: Iterator<Item = Result<InputSeq>>,
     {
@@ -165,6 +184,10 @@ impl<W: Write> Screen<W> {
         // https://invisible-island.net/xterm/ctlseqs/ctlseqs.html (CSI ? Pm h)
         output.write(b"\x1b[?1049h")?;
 
+        if config.mouse {
+            output.write(ENABLE_MOUSE)?;
+        }
+
         Ok(Self {
             output,
             rx: 0,
@@ -177,8 +200,11 @@ impl<W: Write> Screen<W> {
             )),
             draw_message: DrawMessage::Open,
             dirty_start: Some(0), // Render entire screen at first paint
+            dirty_lines: vec![],
             sigwinch: SigwinchWatcher::new()?,
-            term_color: TermColor::from_env(),
+            theme: Theme::from_env(config.theme.as_ref()),
+            show_whitespace: config.show_whitespace,
+            mouse: config.mouse,
             cursor_moved: true,
             rowoff: 0,
             coloff: 0,
@@ -202,7 +228,7 @@ impl<W: Write> Screen<W> {
     fn draw_status_bar<B: Write>(&self, mut buf: B, status_bar: &StatusBar) -> Result<()> {
         write!(buf, "\x1b[{}H", self.rows() + 1)?;
 
-        buf.write(self.term_color.sequence(Color::Invert))?;
+        buf.write(self.theme.sequence(Face::StatusBar))?;
 
         let left = status_bar.left();
         // TODO: Handle multi-byte chars correctly
@@ -211,7 +237,7 @@ impl<W: Write> Screen<W> {
 
         let rest_len = self.num_cols - left.len();
         if rest_len == 0 {
-            buf.write(self.term_color.sequence(Color::Reset))?;
+            buf.write(self.theme.reset())?;
             return Ok(());
         }
 
@@ -220,7 +246,7 @@ impl<W: Write> Screen<W> {
             for _ in 0..rest_len {
                 buf.write(b" ")?;
             }
-            buf.write(self.term_color.sequence(Color::Reset))?;
+            buf.write(self.theme.reset())?;
             return Ok(());
         }
 
@@ -229,7 +255,7 @@ impl<W: Write> Screen<W> {
         }
         buf.write(right.as_bytes())?;
 
-        buf.write(self.term_color.sequence(Color::Reset))?;
+        buf.write(self.theme.reset())?;
         Ok(())
     }
 
@@ -239,15 +265,13 @@ impl<W: Write> Screen<W> {
 
         write!(buf, "\x1b[{}H", self.num_rows + 2)?;
 
-        if message.kind == StatusMessageKind::Error {
-            buf.write(self.term_color.sequence(Color::RedBg))?;
-        }
-
+        let face = match message.kind {
+            StatusMessageKind::Info => Face::Message,
+            StatusMessageKind::Error => Face::ErrorMessage,
+        };
+        buf.write(self.theme.sequence(face))?;
         buf.write(text.as_bytes())?;
-
-        if message.kind != StatusMessageKind::Info {
-            buf.write(self.term_color.sequence(Color::Reset))?;
-        }
+        buf.write(self.theme.reset())?;
 
         buf.write(b"\x1b[K")?;
         Ok(())
@@ -257,7 +281,7 @@ impl<W: Write> Screen<W> {
         self.write_flush(b"\x1b[?25l")?; // Hide cursor
 
         let mut buf = Vec::with_capacity((self.rows() + 2 + self.num_cols) * 3);
-        buf.write(self.term_color.sequence(Color::Reset))?;
+        buf.write(self.theme.reset())?;
 
         for y in 0..self.rows() {
             write!(buf, "\x1b[{}H", y + 1)?;
@@ -267,23 +291,23 @@ impl<W: Write> Screen<W> {
                 let welcome = self.trim_line(&msg_buf);
                 let padding = (self.num_cols - welcome.len()) / 2;
                 if padding > 0 {
-                    buf.write(self.term_color.sequence(Color::NonText))?;
+                    buf.write(self.theme.sequence(Face::NonText))?;
                     buf.write(b"~")?;
-                    buf.write(self.term_color.sequence(Color::Reset))?;
+                    buf.write(self.theme.reset())?;
                     for _ in 0..padding - 1 {
                         buf.write(b" ")?;
                     }
                 }
                 buf.write(welcome.as_bytes())?;
             } else {
-                buf.write(self.term_color.sequence(Color::NonText))?;
+                buf.write(self.theme.sequence(Face::NonText))?;
                 buf.write(b"~")?;
             }
 
             buf.write(b"\x1b[K")?;
         }
 
-        buf.write(self.term_color.sequence(Color::Reset))?;
+        buf.write(self.theme.reset())?;
         self.draw_status_bar(&mut buf, status_bar)?;
         if let Some(message) = &self.message {
             self.draw_message_bar(&mut buf, message)?;
@@ -300,18 +324,22 @@ impl<W: Write> Screen<W> {
     fn draw_rows<B: Write>(
         &self,
         mut buf: B,
-        dirty_start: usize,
+        dirty_start: Option<usize>,
         rows: &[Row],
         hl: &Highlighting,
     ) -> Result<()> {
         let row_len = rows.len();
 
-        buf.write(self.term_color.sequence(Color::Reset))?;
+        buf.write(self.theme.reset())?;
 
         for y in 0..self.rows() {
             let file_row = y + self.rowoff;
 
-            if file_row < dirty_start {
+            let dirty = match dirty_start {
+                Some(start) => start <= file_row,
+                None => false,
+            };
+            if !dirty && !self.dirty_lines.contains(&file_row) {
                 continue;
             }
 
@@ -319,14 +347,24 @@ impl<W: Write> Screen<W> {
             write!(buf, "\x1b[{}H", y + 1)?;
 
             if file_row >= row_len {
-                buf.write(self.term_color.sequence(Color::NonText))?;
+                buf.write(self.theme.sequence(Face::NonText))?;
                 buf.write(b"~")?;
             } else {
                 let row = &rows[file_row];
+                let glyphs = if self.show_whitespace {
+                    whitespace_glyphs(row)
+                } else {
+                    vec![]
+                };
 
                 let mut col = 0;
-                let mut prev_color = Color::Reset;
-                for (c, hl) in row.render_text().chars().zip(hl.lines[file_row].iter()) {
+                let mut prev_face = Face::Text(Highlight::Normal);
+                for (i, (c, hl)) in row
+                    .render_text()
+                    .chars()
+                    .zip(hl.lines[file_row].iter())
+                    .enumerate()
+                {
                     col += c.width_cjk().unwrap_or(1);
                     if col <= self.coloff {
                         continue;
@@ -334,13 +372,15 @@ impl<W: Write> Screen<W> {
                         break;
                     }
 
-                    let color = hl.color();
-                    if color != prev_color {
-                        if prev_color.has_bg_color() {
-                            buf.write(self.term_color.sequence(Color::Reset))?;
-                        }
-                        buf.write(self.term_color.sequence(color))?;
-                        prev_color = color;
+                    // Highlights such as search matches are kept on glyphs
+                    let (c, face) = match (glyphs.get(i), hl) {
+                        (Some(Some(glyph)), Highlight::Normal) => (*glyph, Face::NonText),
+                        (Some(Some(glyph)), _) => (*glyph, Face::Text(*hl)),
+                        _ => (c, Face::Text(*hl)),
+                    };
+                    if face != prev_face {
+                        buf.write(self.theme.sequence(face))?;
+                        prev_face = face;
                     }
 
                     write!(buf, "{}", c)?;
@@ -349,7 +389,7 @@ impl<W: Write> Screen<W> {
 
             // Ensure to end with reset color sequence. Otherwise, when background color is highlighted
             // at the end of line, highlight will continue to the end of last column in terminal window.
-            buf.write(self.term_color.sequence(Color::Reset))?;
+            buf.write(self.theme.reset())?;
 
             // Erases the part of the line to the right of the cursor. http://vt100.net/docs/vt100-ug/chapter3.html#EL
             buf.write(b"\x1b[K")?;
@@ -369,6 +409,7 @@ impl<W: Write> Screen<W> {
         let draw_message = self.draw_message;
 
         if self.dirty_start.is_none()
+            && self.dirty_lines.is_empty()
             && !status_bar.redraw
             && draw_message == DrawMessage::DoNothing
         {
@@ -385,8 +426,8 @@ impl<W: Write> Screen<W> {
         self.write_flush(b"\x1b[?25l")?;
 
         let mut buf = Vec::with_capacity((self.rows() + 2) * self.num_cols);
-        if let Some(s) = self.dirty_start {
-            self.draw_rows(&mut buf, s, text_buf.rows(), hl)?;
+        if self.dirty_start.is_some() || !self.dirty_lines.is_empty() {
+            self.draw_rows(&mut buf, self.dirty_start, text_buf.rows(), hl)?;
         }
 
         // When message bar opens/closes, position of status bar is changed
@@ -439,14 +480,7 @@ impl<W: Write> Screen<W> {
         }
 
         // Adjust scroll position when cursor is outside screen
-        if cy < self.rowoff {
-            // Scroll up when cursor is above the top of window
-            self.rowoff = cy;
-        }
-        if cy >= self.rowoff + self.rows() {
-            // Scroll down when cursor is below the bottom of screen
-            self.rowoff = cy - self.rows() + 1;
-        }
+        self.rowoff = self.visible_rows(cy).start;
         if self.rx < self.coloff {
             self.coloff = self.rx;
         }
@@ -463,6 +497,20 @@ impl<W: Write> Screen<W> {
         }
     }
 
+    // Lines shown on screen after scrolling to the line
+    pub fn visible_rows(&self, cy: usize) -> Range<usize> {
+        let top = if cy < self.rowoff {
+            // Scroll up when cursor is above the top of window
+            cy
+        } else if cy >= self.rowoff + self.rows() {
+            // Scroll down when cursor is below the bottom of screen
+            cy - self.rows() + 1
+        } else {
+            self.rowoff
+        };
+        top..top + self.rows()
+    }
+
     fn update_message_bar(&mut self) -> Result<()> {
         if let Some(m) = &self.message {
             if SystemTime::now().duration_since(m.timestamp)?.as_secs() > 5 {
@@ -478,6 +526,7 @@ impl<W: Write> Screen<W> {
     fn after_render(&mut self) {
         // Clear state
         self.dirty_start = None;
+        self.dirty_lines.clear();
         self.cursor_moved = false;
         self.draw_message = DrawMessage::DoNothing;
     }
@@ -496,12 +545,8 @@ impl<W: Write> Screen<W> {
         Ok(())
     }
 
-    pub fn render_help(&mut self) -> Result<()> {
-        let help: Vec<_> = HELP
-            .split('\n')
-            .skip_while(|s| !s.contains(':'))
-            .map(str::trim_start)
-            .collect();
+    pub fn render_help(&mut self, help: &str) -> Result<()> {
+        let help: Vec<_> = help.split('\n').map(str::trim_start).collect();
         let rows = self.rows();
 
         let vertical_margin = if help.len() < rows {
@@ -531,12 +576,12 @@ impl<W: Write> Screen<W> {
             buf.write(left_pad.as_bytes())?;
 
             let help = &help[idx][..cmp::min(help[idx].len(), self.num_cols)];
-            buf.write(self.term_color.sequence(Color::Cyan))?;
+            buf.write(self.theme.sequence(Face::Text(Highlight::SpecialVar)))?;
             let mut cols = help.split(':');
             if let Some(col) = cols.next() {
                 buf.write(col.as_bytes())?;
             }
-            buf.write(self.term_color.sequence(Color::Reset))?;
+            buf.write(self.theme.reset())?;
             if let Some(col) = cols.next() {
                 write!(buf, ":{}", col)?;
             }
@@ -552,6 +597,32 @@ impl<W: Write> Screen<W> {
         self.write_flush(&buf)
     }
 
+    // Render items such as file paths in text area instead of text buffer. Selected item is
+    // highlighted
+    pub fn render_list<S: AsRef<str>>(&mut self, items: &[S], selected: usize) -> Result<()> {
+        let mut buf = Vec::with_capacity(self.rows() * self.num_cols);
+        buf.write(self.theme.reset())?;
+        for y in 0..self.rows() {
+            write!(buf, "\x1b[{}H", y + 1)?;
+            if let Some(item) = items.get(y) {
+                let item: String = item
+                    .as_ref()
+                    .chars()
+                    .take(self.num_cols.saturating_sub(2))
+                    .collect();
+                if y == selected {
+                    buf.write(self.theme.sequence(Face::Text(Highlight::Search)))?;
+                    write!(buf, "> {}", item)?;
+                    buf.write(self.theme.reset())?;
+                } else {
+                    write!(buf, "  {}", item)?;
+                }
+            }
+            buf.write(b"\x1b[K")?;
+        }
+        self.write_flush(&buf)
+    }
+
     pub fn set_dirty_start(&mut self, start: usize) {
         if let Some(s) = self.dirty_start {
             if s < start {
@@ -561,6 +632,23 @@ impl<W: Write> Screen<W> {
         self.dirty_start = Some(start);
     }
 
+    pub fn set_dirty_line(&mut self, y: usize) {
+        if !self.dirty_lines.contains(&y) {
+            self.dirty_lines.push(y);
+        }
+    }
+
+    pub fn show_whitespace(&self) -> bool {
+        self.show_whitespace
+    }
+
+    pub fn set_show_whitespace(&mut self, show: bool) {
+        if self.show_whitespace != show {
+            self.show_whitespace = show;
+            self.set_dirty_start(self.rowoff);
+        }
+    }
+
     pub fn maybe_resize<I>(&mut self, input: I) -> Result<bool>
     where
         I: Iterator<Item = Result<InputSeq>>,
@@ -568,7 +656,14 @@ impl<W: Write> Screen<W> {
         if !self.sigwinch.notified() {
             return Ok(false); // Did not receive signal
         }
+        self.resize(input)?;
+        Ok(true)
+    }
 
+    fn resize<I>(&mut self, input: I) -> Result<()>
+    where
+        I: Iterator<Item = Result<InputSeq>>,
+    {
         let (w, h) = get_window_size(input, &mut self.output)?;
         if too_small_window(w, h) {
             return Err(Error::TooSmallWindow(w, h));
@@ -578,7 +673,29 @@ impl<W: Write> Screen<W> {
         self.num_cols = w;
         self.dirty_start = Some(0);
 
-        Ok(true)
+        Ok(())
+    }
+
+    // Disable mouse tracking and back to normal screen buffer before suspending the process
+    pub fn suspend(&mut self) -> Result<()> {
+        if self.mouse {
+            self.output.write(DISABLE_MOUSE)?;
+        }
+        self.write_flush(b"\x1b[?1049l")
+    }
+
+    // Enter alternate screen buffer again and render entire screen. Window may be resized while
+    // the process was suspended
+    pub fn resume<I>(&mut self, input: I) -> Result<()>
+    where
+        I: Iterator<Item = Result<InputSeq>>,
+    {
+        self.output.write(b"\x1b[?1049h")?;
+        if self.mouse {
+            self.output.write(ENABLE_MOUSE)?;
+        }
+        self.output.flush()?;
+        self.resize(input)
     }
 
     fn set_message(&mut self, m: Option<StatusMessage>) {
@@ -643,6 +760,11 @@ impl<W: Write> Drop for Screen<W> {
         // > This control combines the effects of the 1 0 4 7 and 1 0 4 8  modes. Use this with
         // > terminfo-based applications rather than the 4 7  mode.
         // https://invisible-island.net/xterm/ctlseqs/ctlseqs.html
+        if self.mouse {
+            self.output
+                .write(DISABLE_MOUSE)
+                .expect("Disable mouse tracking");
+        }
         self.write_flush(b"\x1b[?1049l\x1b[H")
             .expect("Back to normal screen buffer");
     }
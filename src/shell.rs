@@ -0,0 +1,129 @@
+// Run shell command with 'sh -c'. Standard output and standard error are captured so that the
+// command does not break the screen. Text given as input is written to standard input.
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+use std::thread;
+
+pub struct Output {
+    pub stdout: String,
+    pub stderr: String,
+    // Error message for non-zero exit status
+    pub failure: Option<String>,
+}
+
+impl Output {
+    // First line of stderr is shown in message bar
+    pub fn stderr_line(&self) -> Option<&str> {
+        self.stderr.lines().find(|l| !l.trim().is_empty())
+    }
+
+    // Message of the failure with the first line of stderr
+    pub fn error_message(&self) -> Option<String> {
+        let failure = self.failure.as_ref()?;
+        Some(match self.stderr_line() {
+            Some(line) => format!("{}: {}", failure, line),
+            None => failure.clone(),
+        })
+    }
+}
+
+// Parse line range before command such as '3,10 sort'. '.' means the current line. Returned range
+// is 0-based and its end is exclusive. None means no range was given
+pub fn parse_range(input: &str, cur: usize) -> (Option<(usize, usize)>, &str) {
+    let input = input.trim_start();
+    let (range, cmd) = match input.split_once(char::is_whitespace) {
+        Some((range, cmd)) => (range, cmd.trim_start()),
+        None => return (None, input),
+    };
+    let line = |s: &str| match s {
+        "." => Some(cur),
+        s => s.parse::<usize>().ok().filter(|&n| n > 0).map(|n| n - 1),
+    };
+    let parsed = match range.split_once(',') {
+        Some((start, end)) => line(start).zip(line(end)),
+        None if range == "." => Some((cur, cur)),
+        None => None,
+    };
+    match parsed {
+        Some((start, end)) if start <= end => (Some((start, end + 1)), cmd),
+        _ => (None, input),
+    }
+}
+
+pub fn run(cmd: &str, input: Option<String>) -> io::Result<Output> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(cmd)
+        .stdin(if input.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    // Write input in another thread to avoid deadlock when the command writes large output
+    // before reading all input
+    let writer = match (child.stdin.take(), input) {
+        (Some(mut stdin), Some(input)) => {
+            Some(thread::spawn(move || stdin.write_all(input.as_bytes())))
+        }
+        _ => None,
+    };
+    let output = child.wait_with_output()?;
+    if let Some(writer) = writer {
+        match writer.join() {
+            // Command may exit without reading input (e.g. 'echo foo')
+            Ok(Err(e)) if e.kind() != io::ErrorKind::BrokenPipe => return Err(e),
+            _ => {}
+        }
+    }
+
+    let stdout = String::from_utf8(output.stdout)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Output is not UTF-8 text"))?;
+    let failure = if output.status.success() {
+        None
+    } else {
+        Some(match output.status.code() {
+            Some(code) => format!("Command exited with status {}", code),
+            None => "Command was terminated by signal".to_string(),
+        })
+    };
+    Ok(Output {
+        stdout,
+        stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        failure,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn run_command() {
+        let out = run("echo foo; echo bar >&2", None).unwrap();
+        assert_eq!(out.stdout, "foo\n");
+        assert_eq!(out.stderr_line(), Some("bar"));
+        assert!(out.failure.is_none());
+
+        let out = run("sort", Some("b\na\n".to_string())).unwrap();
+        assert_eq!(out.stdout, "a\nb\n");
+
+        let out = run("echo oops >&2; exit 3", Some("foo".to_string())).unwrap();
+        assert_eq!(out.failure.as_deref(), Some("Command exited with status 3"));
+        assert_eq!(out.stderr_line(), Some("oops"));
+    }
+
+    #[test]
+    fn parse_line_range() {
+        assert_eq!(parse_range("sort", 4), (None, "sort"));
+        assert_eq!(parse_range("sort -r", 4), (None, "sort -r"));
+        assert_eq!(parse_range("2,3 sort -r", 4), (Some((1, 3)), "sort -r"));
+        assert_eq!(parse_range(". tr a-z A-Z", 4), (Some((4, 5)), "tr a-z A-Z"));
+        assert_eq!(parse_range(".,10  sort", 4), (Some((4, 10)), "sort"));
+        assert_eq!(parse_range("3,2 sort", 4), (None, "3,2 sort"));
+        assert_eq!(parse_range("0,2 sort", 4), (None, "0,2 sort"));
+    }
+}
@@ -1,5 +1,5 @@
 use crate::error::Result;
-use signal_hook::consts::SIGWINCH;
+use signal_hook::consts::{SIGTSTP, SIGWINCH};
 use signal_hook::{self, SigId};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
@@ -26,3 +26,10 @@ impl Drop for SigwinchWatcher {
         signal_hook::low_level::unregister(self.signal_id);
     }
 }
+
+// Stop this process as Ctrl-Z does in shell. This function returns after the process is continued
+// by SIGCONT (e.g. 'fg' command)
+pub fn suspend() -> Result<()> {
+    signal_hook::low_level::raise(SIGTSTP)?;
+    Ok(())
+}
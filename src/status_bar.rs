@@ -1,10 +1,11 @@
-use crate::language::Language;
+use crate::language::{Indent, Language};
 use crate::text_buffer::TextBuffer;
 
 pub struct StatusBar {
     pub modified: bool,
     pub filename: String,
     pub lang: Language,
+    pub indent: Indent,
     pub buf_pos: (usize, usize),
     pub line_pos: (usize, usize),
     pub redraw: bool,
@@ -34,6 +35,7 @@ impl StatusBar {
     setter!(set_modified, modified, bool);
     setter!(set_filename, filename, &str, filename.to_string());
     setter!(set_lang, lang, Language);
+    setter!(set_indent, indent, Indent);
     setter!(set_line_pos, line_pos, (usize, usize));
 
     pub fn from_buffer(buf: &TextBuffer, buf_pos: (usize, usize)) -> Self {
@@ -41,6 +43,7 @@ impl StatusBar {
             modified: buf.modified(),
             filename: buf.filename().to_string(),
             lang: buf.lang(),
+            indent: buf.indent(),
             buf_pos,
             line_pos: (buf.cy() + 1, buf.rows().len()),
             redraw: false,
@@ -58,13 +61,14 @@ impl StatusBar {
     }
 
     pub fn right(&self) -> String {
-        let (lang, (y, len)) = (self.lang, self.line_pos);
-        format!("{} {}/{}", lang.name(), y, len)
+        let (lang, indent, (y, len)) = (self.lang, self.indent, self.line_pos);
+        format!("{} {} {}/{}", lang.name(), indent, y, len)
     }
 
     pub fn update_from_buf(&mut self, buf: &TextBuffer) {
         self.set_modified(buf.modified());
         self.set_lang(buf.lang());
+        self.set_indent(buf.indent());
         self.set_filename(buf.filename());
         self.set_line_pos((buf.cy() + 1, buf.rows().len()));
     }
@@ -1,4 +1,8 @@
+use crate::error::{Error, Result};
+use crate::input::{InputSeq, KeySeq};
 use std::env;
+use std::io::Write;
+use std::str::FromStr;
 use term::terminfo::TermInfo;
 
 #[derive(PartialEq, Clone, Copy)]
@@ -15,17 +19,11 @@ pub enum Color {
     RedBg,
     YellowBg,
     OrangeBg,
+    GrayBg,
     NonText,
     Invert,
 }
 
-impl Color {
-    pub fn has_bg_color(self) -> bool {
-        use Color::*;
-        matches!(self, YellowBg | RedBg | OrangeBg)
-    }
-}
-
 #[inline]
 fn true_colors_sequence(color: Color) -> &'static [u8] {
     macro_rules! rgb_color {
@@ -68,11 +66,68 @@ fn true_colors_sequence(color: Color) -> &'static [u8] {
             rgb_color!(bg, 0xd6, 0x5d, 0x0e),
         )
         .as_bytes(),
+        GrayBg => concat!(
+            rgb_color!(fg, 0xfb, 0xf1, 0xc7),
+            rgb_color!(bg, 0x66, 0x5c, 0x54),
+        )
+        .as_bytes(),
         NonText => rgb_color!(fg, 0x66, 0x5c, 0x54).as_bytes(),
         Invert => b"\x1b[7m",
     }
 }
 
+#[inline]
+fn true_colors_light_sequence(color: Color) -> &'static [u8] {
+    macro_rules! rgb_color {
+        (fg, $r:expr, $g:expr, $b:expr) => {
+            concat!("\x1b[38;2;", $r, ';', $g, ';', $b, "m")
+        };
+        (bg, $r:expr, $g:expr, $b:expr) => {
+            concat!("\x1b[48;2;", $r, ';', $g, ';', $b, "m")
+        };
+    }
+
+    use Color::*;
+    match color {
+        Reset => concat!(
+            "\x1b[39;0m",
+            rgb_color!(fg, 0x3c, 0x38, 0x36),
+            rgb_color!(bg, 0xfb, 0xf1, 0xc7),
+        )
+        .as_bytes(),
+        Red => rgb_color!(fg, 0x9d, 0x00, 0x06).as_bytes(),
+        Green => rgb_color!(fg, 0x79, 0x74, 0x0e).as_bytes(),
+        Gray => rgb_color!(fg, 0x7c, 0x6f, 0x64).as_bytes(),
+        Yellow => rgb_color!(fg, 0xb5, 0x76, 0x14).as_bytes(),
+        Orange => rgb_color!(fg, 0xaf, 0x3a, 0x03).as_bytes(),
+        Blue => rgb_color!(fg, 0x07, 0x66, 0x78).as_bytes(),
+        Purple => rgb_color!(fg, 0x8f, 0x3f, 0x71).as_bytes(),
+        Cyan => rgb_color!(fg, 0x42, 0x7b, 0x58).as_bytes(),
+        RedBg => concat!(
+            rgb_color!(fg, 0xfb, 0xf1, 0xc7),
+            rgb_color!(bg, 0xcc, 0x24, 0x1d),
+        )
+        .as_bytes(),
+        YellowBg => concat!(
+            rgb_color!(fg, 0x3c, 0x38, 0x36),
+            rgb_color!(bg, 0xfa, 0xbd, 0x2f),
+        )
+        .as_bytes(),
+        OrangeBg => concat!(
+            rgb_color!(fg, 0x3c, 0x38, 0x36),
+            rgb_color!(bg, 0xfe, 0x80, 0x19),
+        )
+        .as_bytes(),
+        GrayBg => concat!(
+            rgb_color!(fg, 0x3c, 0x38, 0x36),
+            rgb_color!(bg, 0xd5, 0xc4, 0xa1),
+        )
+        .as_bytes(),
+        NonText => rgb_color!(fg, 0xbd, 0xae, 0x93).as_bytes(),
+        Invert => b"\x1b[7m",
+    }
+}
+
 // From color palette of gruvbox: https://github.com/morhetz/gruvbox#palette
 //
 // 'm' sets attributes to text printed after: https://vt100.net/docs/vt100-ug/chapter3.html#SGR
@@ -100,11 +155,34 @@ fn colors_256_sequence(color: Color) -> &'static [u8] {
         RedBg => b"\x1b[38;5;230m\x1b[48;5;124m",
         YellowBg => b"\x1b[38;5;235m\x1b[48;5;214m",
         OrangeBg => b"\x1b[38;5;235m\x1b[48;5;166m",
+        GrayBg => b"\x1b[38;5;230m\x1b[48;5;241m",
         NonText => b"\x1b[38;5;241m",
         Invert => b"\x1b[7m",
     }
 }
 
+#[inline]
+fn colors_256_light_sequence(color: Color) -> &'static [u8] {
+    use Color::*;
+    match color {
+        Reset => b"\x1b[39;0m\x1b[38;5;237m\x1b[48;5;230m",
+        Red => b"\x1b[38;5;88m",
+        Green => b"\x1b[38;5;100m",
+        Gray => b"\x1b[38;5;244m",
+        Yellow => b"\x1b[38;5;136m",
+        Orange => b"\x1b[38;5;130m",
+        Blue => b"\x1b[38;5;24m",
+        Purple => b"\x1b[38;5;96m",
+        Cyan => b"\x1b[38;5;66m",
+        RedBg => b"\x1b[38;5;230m\x1b[48;5;124m",
+        YellowBg => b"\x1b[38;5;237m\x1b[48;5;214m",
+        OrangeBg => b"\x1b[38;5;237m\x1b[48;5;208m",
+        GrayBg => b"\x1b[38;5;237m\x1b[48;5;250m",
+        NonText => b"\x1b[38;5;248m",
+        Invert => b"\x1b[7m",
+    }
+}
+
 #[inline]
 fn colors_16_sequence(color: Color) -> &'static [u8] {
     use Color::*;
@@ -121,48 +199,234 @@ fn colors_16_sequence(color: Color) -> &'static [u8] {
         RedBg => b"\x1b[97m\x1b[41m",
         YellowBg => b"\x1b[103m\x1b[30m",
         OrangeBg => b"\x1b[107m\x1b[30m", // White BG color is used instead of orange
+        GrayBg => b"\x1b[97m\x1b[100m",
         NonText => b"\x1b[37m",
         Invert => b"\x1b[7m",
     }
 }
 
-#[derive(Clone, Copy)]
+// Bright colors are hard to read on light background. Use normal colors instead
+#[inline]
+fn colors_16_light_sequence(color: Color) -> &'static [u8] {
+    use Color::*;
+    match color {
+        Reset => b"\x1b[39;0m",
+        Red => b"\x1b[31m",
+        Green => b"\x1b[32m",
+        Gray => b"\x1b[90m",
+        Yellow => b"\x1b[33m",
+        Orange => b"\x1b[91m", // No orange color in 16 colors. Use brighter red instead
+        Blue => b"\x1b[34m",
+        Purple => b"\x1b[35m",
+        Cyan => b"\x1b[36m",
+        RedBg => b"\x1b[97m\x1b[41m",
+        YellowBg => b"\x1b[103m\x1b[30m",
+        OrangeBg => b"\x1b[43m\x1b[30m", // Darker yellow BG color is used instead of orange
+        GrayBg => b"\x1b[30m\x1b[47m",
+        NonText => b"\x1b[90m",
+        Invert => b"\x1b[7m",
+    }
+}
+
+// Only text attributes are available on monochrome terminals. Highlights which must be
+// distinguishable such as search matches and status bar are rendered with bold, reverse and
+// underline. Colors of tokens are ignored except for keywords rendered with bold
+#[inline]
+fn monochrome_sequence(color: Color) -> &'static [u8] {
+    use Color::*;
+    match color {
+        Reset => b"\x1b[0m",
+        Blue | Red => b"\x1b[1m",
+        Green | Gray | Yellow | Orange | Purple | Cyan | NonText => b"",
+        RedBg => b"\x1b[1m\x1b[7m",
+        YellowBg => b"\x1b[7m",
+        OrangeBg => b"\x1b[1m\x1b[4m\x1b[7m",
+        GrayBg => b"\x1b[4m",
+        Invert => b"\x1b[7m",
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
 pub enum TermColor {
     TrueColors,
     Colors256,
     Colors16,
+    NoColor,
 }
 
 impl TermColor {
     pub fn from_env() -> TermColor {
-        env::var("COLORTERM")
-            .ok()
-            .and_then(|v| {
-                if v == "truecolor" {
-                    Some(TermColor::TrueColors)
-                } else {
-                    None
+        // https://no-color.org/
+        if env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty()) {
+            return TermColor::NoColor;
+        }
+
+        if env::var("COLORTERM").is_ok_and(|v| v == "truecolor") {
+            return TermColor::TrueColors;
+        }
+
+        match TermInfo::from_env() {
+            Ok(info) => match info.numbers.get("colors") {
+                Some(256) => TermColor::Colors256,
+                Some(colors) if *colors >= 8 => TermColor::Colors16,
+                _ => TermColor::NoColor, // e.g. TERM=dumb
+            },
+            Err(_) => TermColor::Colors16,
+        }
+    }
+
+    pub fn sequence(self, color: Color, background: Background) -> &'static [u8] {
+        use Background::*;
+        match (self, background) {
+            (TermColor::TrueColors, Dark) => true_colors_sequence(color),
+            (TermColor::TrueColors, Light) => true_colors_light_sequence(color),
+            (TermColor::Colors256, Dark) => colors_256_sequence(color),
+            (TermColor::Colors256, Light) => colors_256_light_sequence(color),
+            (TermColor::Colors16, Dark) => colors_16_sequence(color),
+            (TermColor::Colors16, Light) => colors_16_light_sequence(color),
+            (TermColor::NoColor, _) => monochrome_sequence(color),
+        }
+    }
+}
+
+// Background color of terminal to choose color palette
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Background {
+    Dark,
+    Light,
+}
+
+impl FromStr for Background {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Background> {
+        match s {
+            "dark" => Ok(Background::Dark),
+            "light" => Ok(Background::Light),
+            _ => Err(Error::UnknownBackground(s.to_string())),
+        }
+    }
+}
+
+impl Background {
+    // Query background color to terminal with OSC 11. Since terminals which don't support OSC 11
+    // never respond, primary device attributes (DA1) is also queried. All terminals respond to DA1
+    // and responses are returned in order. When DA1 response arrives first, OSC 11 is not supported.
+    // https://invisible-island.net/xterm/ctlseqs/ctlseqs.html#h3-Operating-System-Commands
+    //
+    // Reading input times out periodically with Unidentified. When no response arrives until the
+    // deadline, background is detected from environment. Keys input while waiting for responses
+    // are returned so that they are not lost.
+    pub fn detect<I, W>(input: I, mut output: W) -> Result<(Background, Vec<InputSeq>)>
+    where
+        I: Iterator<Item = Result<InputSeq>>,
+        W: Write,
+    {
+        // Give up after about 1 second on 100ms timeout of reading input
+        const MAX_TIMEOUTS: usize = 10;
+
+        output.write(b"\x1b]11;?\x1b\\\x1b[c")?;
+        output.flush()?;
+
+        let mut detected = None;
+        let mut pending = vec![];
+        let mut timeouts = 0;
+        for seq in input {
+            let seq = seq?;
+            match seq.key {
+                KeySeq::BackgroundColor(r, g, b) => detected = Some(Self::from_rgb(r, g, b)),
+                KeySeq::DeviceAttributes => break,
+                KeySeq::Unidentified => {
+                    timeouts += 1;
+                    if timeouts >= MAX_TIMEOUTS {
+                        break;
+                    }
                 }
-            })
-            .or_else(|| {
-                TermInfo::from_env().ok().and_then(|info| {
-                    info.numbers.get("colors").map(|colors| {
-                        if *colors == 256 {
-                            TermColor::Colors256
-                        } else {
-                            TermColor::Colors16
-                        }
-                    })
-                })
-            })
-            .unwrap_or(TermColor::Colors16)
-    }
-
-    pub fn sequence(self, color: Color) -> &'static [u8] {
-        match self {
-            TermColor::TrueColors => true_colors_sequence(color),
-            TermColor::Colors256 => colors_256_sequence(color),
-            TermColor::Colors16 => colors_16_sequence(color),
+                _ => pending.push(seq),
+            }
+        }
+
+        Ok((detected.unwrap_or_else(Self::from_env), pending))
+    }
+
+    // Fall back to $COLORFGBG such as "15;0" or "15;default;0" set by some terminals like rxvt
+    pub fn from_env() -> Background {
+        env::var("COLORFGBG")
+            .ok()
+            .and_then(|v| Self::from_colorfgbg(&v))
+            .unwrap_or(Background::Dark)
+    }
+
+    pub fn from_colorfgbg(value: &str) -> Option<Background> {
+        match value.rsplit(';').next()?.parse() {
+            Ok(7) | Ok(9..=15) => Some(Background::Light),
+            Ok(_) => Some(Background::Dark),
+            Err(_) => None,
+        }
+    }
+
+    fn from_rgb(r: u8, g: u8, b: u8) -> Background {
+        // Perceived brightness (ITU-R BT.601)
+        let luma = 299 * u32::from(r) + 587 * u32::from(g) + 114 * u32::from(b);
+        if luma > 128 * 1000 {
+            Background::Light
+        } else {
+            Background::Dark
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+
+    fn inputs(keys: Vec<KeySeq>) -> impl Iterator<Item = Result<InputSeq>> {
+        keys.into_iter().map(|k| Ok(InputSeq::new(k)))
+    }
+
+    #[test]
+    fn detect_background() {
+        use KeySeq::*;
+
+        for (keys, expected) in &[
+            (vec![BackgroundColor(0xfb, 0xf1, 0xc7)], Background::Light),
+            (vec![BackgroundColor(0x28, 0x28, 0x28)], Background::Dark),
+            (
+                vec![Utf8Key('a'), BackgroundColor(0xff, 0xff, 0xff)],
+                Background::Light,
+            ),
+        ] {
+            let mut keys = keys.clone();
+            keys.push(DeviceAttributes);
+            keys.push(Key(b'a')); // Not consumed
+            let mut input = inputs(keys);
+            let (bg, pending) = Background::detect(&mut input, io::sink()).unwrap();
+            assert_eq!(bg, *expected);
+            assert_eq!(input.count(), 1);
+            assert!(pending.iter().all(|s| s.key == Utf8Key('a')));
+        }
+
+        // Give up when terminal does not respond. Keys input while waiting are kept
+        let mut keys = vec![Unidentified; 3];
+        keys.push(Key(b'x'));
+        keys.extend(vec![Unidentified; 20]);
+        let mut input = inputs(keys);
+        let (_, pending) = Background::detect(&mut input, io::sink()).unwrap();
+        assert_eq!(pending, vec![InputSeq::new(Key(b'x'))]);
+        assert_eq!(input.count(), 20 - (10 - 3)); // Gave up on the 10th timeout
+    }
+
+    #[test]
+    fn background_from_colorfgbg() {
+        for (value, expected) in &[
+            ("15;0", Some(Background::Dark)),
+            ("0;15", Some(Background::Light)),
+            ("0;default;7", Some(Background::Light)),
+            ("7;8", Some(Background::Dark)),
+            ("", None),
+        ] {
+            assert_eq!(Background::from_colorfgbg(value), *expected, "{:?}", value);
         }
     }
 }
@@ -0,0 +1,54 @@
+// Helpers shared by unit tests
+use std::env;
+use std::fs;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static NUM_TEMP_DIRS: AtomicUsize = AtomicUsize::new(0);
+
+// Temporary directory unique to each test. It is removed with its contents on drop so that files
+// created by previous runs or by other tests running in parallel don't affect the test
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new(name: &str) -> TempDir {
+        let n = NUM_TEMP_DIRS.fetch_add(1, Ordering::Relaxed);
+        let dir = format!("kiro-test-{}-{}-{}", name, process::id(), n);
+        let path = env::temp_dir().join(dir);
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        TempDir(path)
+    }
+
+    // Create a file in the directory and return its path
+    pub fn write<C: AsRef<[u8]>>(&self, name: &str, content: C) -> PathBuf {
+        let path = self.0.join(name);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).unwrap();
+        }
+        fs::write(&path, content).unwrap();
+        path
+    }
+}
+
+impl Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for TempDir {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
@@ -1,11 +1,14 @@
-use crate::edit_diff::{EditDiff, UndoRedo};
+use crate::config::Config;
+use crate::diff;
+use crate::edit_diff::{DirtyLines, EditDiff, UndoRedo};
+use crate::editorconfig::{Charset, EditorConfig, EndOfLine};
 use crate::error::Result;
 use crate::history::History;
-use crate::language::{Indent, Language};
-use crate::row::Row;
+use crate::language::{Indent, Language, SaveHooks};
+use crate::row::{Row, TAB_STOP};
 use std::cmp;
-use std::fs::File;
-use std::io::{self, BufRead, Write};
+use std::fs::{self, File};
+use std::io::{self, Write};
 use std::path::{Path, PathBuf};
 use std::slice;
 
@@ -41,6 +44,7 @@ pub enum CursorDir {
     Down,
 }
 
+#[derive(Clone)]
 pub struct Lines<'a>(slice::Iter<'a, Row>);
 
 impl<'a> Iterator for Lines<'a> {
@@ -56,6 +60,12 @@ impl<'a> Iterator for Lines<'a> {
     }
 }
 
+impl<'a> DoubleEndedIterator for Lines<'a> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.0.next_back().map(|r| r.buffer())
+    }
+}
+
 impl<'a> ExactSizeIterator for Lines<'a> {}
 
 pub struct TextBuffer {
@@ -76,13 +86,28 @@ pub struct TextBuffer {
     modified: bool,
     // Language which current buffer belongs to
     lang: Language,
+    // Indentation detected from the file content or configured by .editorconfig. When it is None,
+    // indentation of the language is used
+    indent: Option<Indent>,
+    // Width of tab character on screen
+    tab_stop: usize,
+    // Newline and character encoding used for reading/writing the file
+    eol: EndOfLine,
+    charset: Charset,
+    // When false, newline is not written after the last line on save
+    final_newline: bool,
+    // Overrides trimming trailing whitespaces on save by .editorconfig. When it is None, save hooks
+    // of the language are used
+    trim_trailing_whitespace: Option<bool>,
     // History per undo point for undo/redo
     history: History,
     // Flag to ensure at most one undo point per one key input
     inserted_undo: bool,
-    // Flag to require screen update
+    // Lines modified by the current key input to require screen update
     // TODO: Merge with Screen's dirty_start field by using RenderContext struct
-    dirty_start: Option<usize>,
+    dirty: Option<DirtyLines>,
+    // Buffer which only shows results such as search results. It is never modified nor saved
+    read_only: bool,
 }
 
 impl TextBuffer {
@@ -91,76 +116,127 @@ impl TextBuffer {
             cx: 0,
             cy: 0,
             file: None,
-            row: vec![Row::empty()], // Ensure that every text ends with newline
+            row: vec![Row::empty(TAB_STOP)], // Ensure that every text ends with newline
             undo_count: 0,
             modified: false,
             lang: Language::Plain,
+            indent: None,
+            tab_stop: TAB_STOP,
+            eol: EndOfLine::Lf,
+            charset: Charset::Utf8,
+            final_newline: true,
+            trim_trailing_whitespace: None,
             history: History::default(),
             inserted_undo: false,
-            dirty_start: Some(0), // Ensure to render first screen
+            dirty: Some(DirtyLines::new(0, 0)), // Ensure to render first screen
+            read_only: false,
         }
     }
 
     pub fn with_lines<S: AsRef<str>, I: Iterator<Item = S>>(lines: I) -> Result<Self> {
-        Ok(Self {
-            cx: 0,
-            cy: 0,
-            file: None,
-            row: lines.map(|s| Row::new(s.as_ref())).collect::<Result<_>>()?,
-            undo_count: 0,
-            modified: false,
-            lang: Language::Plain,
-            history: History::default(),
-            inserted_undo: false,
-            dirty_start: Some(0), // Ensure to render first screen
-        })
+        let mut buf = Self::empty();
+        buf.row = lines
+            .map(|s| Row::new(s.as_ref(), TAB_STOP))
+            .collect::<Result<_>>()?;
+        Ok(buf)
+    }
+
+    // Read-only text buffer to show lines such as search results. The name is shown as its file name
+    pub fn read_only<S: AsRef<str>, I: Iterator<Item = S>>(name: &str, lines: I) -> Result<Self> {
+        let mut buf = Self::with_lines(lines)?;
+        if buf.row.is_empty() {
+            buf.row.push(Row::empty(TAB_STOP));
+        }
+        buf.file = Some(FilePath::from_string(name));
+        buf.read_only = true;
+        Ok(buf)
+    }
+
+    // Empty text buffer configured with user's config
+    pub fn with_config(config: &Config) -> Self {
+        let mut buf = Self::empty();
+        buf.apply_editorconfig(&config.editing);
+        buf
     }
 
-    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+    pub fn open<P: AsRef<Path>>(path: P, user_config: &Config) -> Result<Self> {
         let path = path.as_ref();
-        let file = Some(FilePath::from(path));
-        if !path.exists() {
-            // When the path does not exist, consider it as a new file
-            let mut buf = Self::empty();
-            buf.file = file;
-            buf.undo_count = 0;
-            buf.modified = false;
-            buf.lang = Language::detect(path);
-            return Ok(buf);
-        }
-
-        let row = io::BufReader::new(File::open(path)?)
-            .lines()
-            .map(|r| Row::new(r?))
-            .collect::<Result<_>>()?;
+        // Unreadable .editorconfig in ancestor directories should not prevent opening the file
+        let config = EditorConfig::load(path).unwrap_or_default();
+
+        let mut buf = Self::empty();
+        buf.file = Some(FilePath::from(path));
+
+        // When the path does not exist, consider it as a new file
+        if path.exists() {
+            let charset = config.charset.unwrap_or(Charset::Utf8);
+            let eol = config.end_of_line.unwrap_or(EndOfLine::Lf);
+            let text = charset.decode(fs::read(path)?)?;
+            buf.row = eol
+                .lines(&text)
+                .into_iter()
+                .map(|l| Row::new(l, TAB_STOP))
+                .collect::<Result<_>>()?;
+        }
 
-        Ok(Self {
-            cx: 0,
-            cy: 0,
-            file,
-            row,
-            undo_count: 0,
-            modified: false,
-            lang: Language::detect(path),
-            history: History::default(),
-            inserted_undo: false,
-            dirty_start: Some(0),
-        })
+        buf.lang = Language::detect_with_content(path, buf.lines());
+        buf.apply_editorconfig(&user_config.editing);
+        buf.apply_editorconfig(&config);
+
+        // Indentation in .editorconfig is prioritized over detected one, and detected one is
+        // prioritized over user's config
+        if let Some(detected) = Indent::detect(buf.lines()) {
+            buf.indent = config.indent(detected).or(Some(detected));
+        }
+
+        Ok(buf)
     }
 
-    fn set_dirty_start(&mut self, line: usize) {
-        if let Some(l) = self.dirty_start {
-            if l <= line {
-                return;
-            }
+    fn apply_editorconfig(&mut self, config: &EditorConfig) {
+        if let Some(width) = config.tab_width() {
+            self.set_tab_stop(width);
+        }
+        if let Some(eol) = config.end_of_line {
+            self.eol = eol;
+        }
+        if let Some(charset) = config.charset {
+            self.charset = charset;
+        }
+        if let Some(indent) = config.indent(self.indent()) {
+            self.indent = Some(indent);
+        }
+        if let Some(final_newline) = config.insert_final_newline {
+            self.final_newline = final_newline;
+        }
+        if config.trim_trailing_whitespace.is_some() {
+            self.trim_trailing_whitespace = config.trim_trailing_whitespace;
         }
-        self.dirty_start = Some(line);
+    }
+
+    fn set_tab_stop(&mut self, tab_stop: usize) {
+        if self.tab_stop == tab_stop {
+            return;
+        }
+        self.tab_stop = tab_stop;
+        for row in self.row.iter_mut() {
+            row.set_tab_stop(tab_stop);
+        }
+        self.set_dirty(DirtyLines::new(0, self.row.len()));
+    }
+
+    fn set_dirty(&mut self, lines: DirtyLines) {
+        self.dirty = Some(match self.dirty {
+            Some(d) => DirtyLines::new(cmp::min(d.start, lines.start), cmp::max(d.end, lines.end)),
+            None => lines,
+        });
     }
 
     fn apply_diff(&mut self, diff: &EditDiff, which: UndoRedo) {
-        let (x, y) = diff.apply(&mut self.row, which);
+        let len = self.row.len();
+        let (x, y) = diff.apply(&mut self.row, which, self.tab_stop);
         self.set_cursor(x, y);
-        self.set_dirty_start(y);
+        let delta = self.row.len() as isize - len as isize;
+        self.dirty = Some(DirtyLines::extend(self.dirty, y, delta));
     }
 
     fn new_diff(&mut self, diff: EditDiff) {
@@ -182,11 +258,9 @@ impl TextBuffer {
     // This method must be called after handling one key input.
     // TODO: This should be replaced with Drop when separating logic to edit text buffer from TextBuffer
     // by introducing RenderContext.
-    pub fn finish_edit(&mut self) -> Option<usize> {
+    pub fn finish_edit(&mut self) -> Option<DirtyLines> {
         self.inserted_undo = false;
-        let dirty_start = self.dirty_start;
-        self.dirty_start = None;
-        dirty_start
+        self.dirty.take()
     }
 
     pub fn insert_char(&mut self, ch: char) {
@@ -199,10 +273,10 @@ impl TextBuffer {
 
     pub fn insert_tab(&mut self) {
         self.insert_undo_point();
-        match self.lang.indent() {
-            Indent::AsIs => self.insert_char('\t'),
-            Indent::Fixed(indent) => {
-                self.new_diff(EditDiff::Insert(self.cx, self.cy, indent.to_owned()));
+        match self.indent() {
+            Indent::Tab => self.insert_char('\t'),
+            Indent::Spaces(width) => {
+                self.new_diff(EditDiff::Insert(self.cx, self.cy, " ".repeat(width)));
             }
         }
     }
@@ -311,6 +385,120 @@ impl TextBuffer {
         }
     }
 
+    // Check lines before modifying the buffer so that the buffer is not changed on error
+    fn check_lines<S: AsRef<str>>(&self, lines: &[S]) -> Result<()> {
+        for line in lines {
+            Row::new(line.as_ref(), self.tab_stop)?;
+        }
+        Ok(())
+    }
+
+    // Close the undo point so that following inputs are not squashed into the current change
+    fn close_undo_point(&mut self) {
+        self.inserted_undo = false;
+        self.insert_undo_point();
+    }
+
+    // Replace lines in range of start..end with the new lines. Lines which are not changed are
+    // kept as-is
+    fn replace_range<S: AsRef<str>>(&mut self, start: usize, end: usize, lines: &[S]) {
+        let common = cmp::min(end - start, lines.len());
+        for (y, line) in (start..).zip(lines[..common].iter().map(AsRef::as_ref)) {
+            if self.row[y].buffer() == line {
+                continue;
+            }
+            let prev = self.row[y].buffer().to_owned();
+            if !prev.is_empty() {
+                self.new_diff(EditDiff::Truncate(y, prev));
+            }
+            if !line.is_empty() {
+                self.new_diff(EditDiff::Append(y, line.to_owned()));
+            }
+        }
+        for (y, line) in (start + common..).zip(lines[common..].iter()) {
+            self.new_diff(EditDiff::InsertLine(y, line.as_ref().to_owned()));
+        }
+        for y in (start + common..end).rev() {
+            let removed = self.row[y].buffer().to_owned();
+            self.new_diff(EditDiff::DeleteLine(y, removed));
+        }
+    }
+
+    // Replace lines in range of start..end with the new lines as one undoable change. Cursor is
+    // put at the start of the range
+    pub fn replace_lines<S: AsRef<str>>(
+        &mut self,
+        start: usize,
+        end: usize,
+        lines: &[S],
+    ) -> Result<()> {
+        self.check_lines(lines)?;
+        self.insert_undo_point();
+        self.replace_range(start, end, lines);
+        self.close_undo_point();
+        self.set_cursor(0, cmp::min(start, self.row.len()));
+        Ok(())
+    }
+
+    // Replace entire buffer with the lines as one undoable change. Only changed lines are modified
+    // so that cursor stays at the same line
+    pub fn update_lines<S: AsRef<str>>(&mut self, lines: &[S]) -> Result<()> {
+        self.check_lines(lines)?;
+        let hunks = {
+            let old: Vec<_> = self.lines().collect();
+            diff::diff_lines(&old, lines)
+        };
+        if hunks.is_empty() {
+            return Ok(());
+        }
+
+        // Find the line where cursor should be after the change
+        let (cx, cy) = (self.cx, self.cy);
+        let mut delta = 0;
+        let mut new_cy = None;
+        for hunk in hunks.iter() {
+            if hunk.old.end <= cy {
+                delta += hunk.new.len() as isize - hunk.old.len() as isize;
+            } else {
+                if hunk.old.start <= cy {
+                    let offset = cmp::min(cy - hunk.old.start, hunk.new.len().saturating_sub(1));
+                    new_cy = Some(hunk.new.start + offset);
+                }
+                break;
+            }
+        }
+        let cy = new_cy.unwrap_or_else(|| cy.saturating_add_signed(delta));
+
+        self.insert_undo_point();
+        // Apply from the last hunk so that positions of preceding hunks are not shifted
+        for hunk in hunks.iter().rev() {
+            self.replace_range(hunk.old.start, hunk.old.end, &lines[hunk.new.clone()]);
+        }
+        self.close_undo_point();
+
+        let cy = cmp::min(cy, self.row.len());
+        let cx = cmp::min(cx, self.row.get(cy).map(Row::len).unwrap_or(0));
+        self.set_cursor(cx, cy);
+        Ok(())
+    }
+
+    // Insert text which may contain newlines at cursor. Cursor moves to the end of the text
+    pub fn insert_text(&mut self, text: &str) -> Result<()> {
+        let (cx, cy) = (self.cx, self.cy);
+        let (prefix, suffix, end) = match self.row.get(cy) {
+            Some(row) => (row[..cx].to_owned(), row[cx..].to_owned(), cy + 1),
+            None => (String::new(), String::new(), cy), // At end of buffer
+        };
+        let mut lines: Vec<_> = text.split('\n').map(|l| l.to_owned()).collect();
+        let last = lines.len() - 1;
+        let x = if last == 0 { cx } else { 0 } + lines[last].chars().count();
+        lines[0].insert_str(0, &prefix);
+        lines[last].push_str(&suffix);
+        self.replace_lines(cy, end, &lines)?;
+        self.set_cursor(x, cy + last);
+        Ok(())
+    }
+
     pub fn move_cursor_one(&mut self, dir: CursorDir) {
         match dir {
             CursorDir::Up => self.cy = self.cy.saturating_sub(1),
@@ -468,6 +656,10 @@ impl TextBuffer {
             .unwrap_or("[No Name]")
     }
 
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
     pub fn modified(&self) -> bool {
         self.undo_count != 0 || self.modified
     }
@@ -476,6 +668,18 @@ impl TextBuffer {
         self.lang
     }
 
+    pub fn indent(&self) -> Indent {
+        self.indent.unwrap_or_else(|| self.lang.indent())
+    }
+
+    // Hooks configured by user are overridden by .editorconfig
+    fn save_hooks(&self, mut hooks: SaveHooks) -> SaveHooks {
+        if let Some(trim) = self.trim_trailing_whitespace {
+            hooks.trim_trailing_whitespace = trim;
+        }
+        hooks
+    }
+
     pub fn cy(&self) -> usize {
         self.cy
     }
@@ -486,7 +690,20 @@ impl TextBuffer {
 
     pub fn set_file<S: Into<String>>(&mut self, file_path: S) {
         let file = FilePath::from_string(file_path);
-        self.lang = Language::detect(&file.path);
+        // Detect language only when unnamed buffer is saved first time. Language of the named
+        // buffer may be chosen by user
+        if self.file.is_none() {
+            self.lang = Language::detect_with_content(&file.path, self.lines());
+        }
+        // Properties from .editorconfig for the previous path must not remain
+        self.eol = EndOfLine::Lf;
+        self.charset = Charset::Utf8;
+        self.final_newline = true;
+        self.trim_trailing_whitespace = None;
+        // Broken .editorconfig should not prevent saving the buffer
+        if let Ok(config) = EditorConfig::load(&file.path) {
+            self.apply_editorconfig(&config);
+        }
         self.file = Some(file);
     }
 
@@ -498,32 +715,92 @@ impl TextBuffer {
         self.lang = lang;
     }
 
-    pub fn save(&mut self) -> std::result::Result<String, String> {
+    fn trim_trailing_whitespaces(&mut self) {
+        for y in 0..self.row.len() {
+            let line = self.row[y].buffer();
+            let trimmed = line.trim_end_matches(|c: char| c.is_whitespace());
+            if trimmed.len() < line.len() {
+                let removed = line[trimmed.len()..].to_owned();
+                self.new_diff(EditDiff::Truncate(y, removed));
+            }
+        }
+    }
+
+    fn trim_trailing_blank_lines(&mut self) {
+        // Keep at least one line since text buffer is never empty
+        while self.row.len() > 1 && self.row[self.row.len() - 1].buffer().is_empty() {
+            let y = self.row.len() - 1;
+            self.new_diff(EditDiff::DeleteLine(y, "".to_string()));
+        }
+    }
+
+    fn run_save_hooks(&mut self, hooks: SaveHooks) {
+        let hooks = self.save_hooks(hooks);
+        let cursor = self.cursor();
+
+        if hooks.trim_trailing_whitespace {
+            self.trim_trailing_whitespaces();
+        }
+        if hooks.trim_trailing_blank_lines {
+            self.trim_trailing_blank_lines();
+        }
+
+        // Changes on save should be one undo entry
+        if self.history.finish_ongoing_edit() {
+            self.undo_count = self.undo_count.saturating_add(1);
+        }
+
+        // Applying diffs moves cursor. Restore it not to move cursor on save
+        let (cx, cy) = cursor;
+        let cy = cmp::min(cy, self.row.len());
+        let len = self.row.get(cy).map(Row::len).unwrap_or(0);
+        self.set_cursor(cmp::min(cx, len), cy);
+    }
+
+    fn encode(&self) -> std::result::Result<Vec<u8>, String> {
+        let mut bytes = self.charset.bom().to_vec();
+        let eol = self.eol.as_str();
+        for (i, line) in self.row.iter().enumerate() {
+            self.charset.encode(line.buffer(), &mut bytes)?;
+            if self.final_newline || i + 1 < self.row.len() {
+                self.charset.encode(eol, &mut bytes)?;
+            }
+        }
+        Ok(bytes)
+    }
+
+    pub fn save(&mut self, hooks: SaveHooks) -> std::result::Result<String, String> {
         self.insert_undo_point();
 
-        let file = if let Some(file) = &self.file {
-            file
-        } else {
+        if self.file.is_none() {
             return Ok("".to_string()); // Canceled
-        };
+        }
+        if self.read_only {
+            return Err("Buffer is read-only".to_string());
+        }
+
+        self.run_save_hooks(hooks);
+
+        let bytes = self.encode()?;
+        let file = self.file.as_ref().unwrap();
 
         let f = match File::create(&file.path) {
             Ok(f) => f,
             Err(e) => return Err(format!("Could not save: {}", e)),
         };
         let mut f = io::BufWriter::new(f);
-        let mut bytes = 0;
-        for line in self.row.iter() {
-            let b = line.buffer();
-            writeln!(f, "{}", b).map_err(|e| format!("Could not write to file: {}", e))?;
-            bytes += b.as_bytes().len() + 1;
-        }
+        f.write_all(&bytes)
+            .map_err(|e| format!("Could not write to file: {}", e))?;
         f.flush()
             .map_err(|e| format!("Could not flush to file: {}", e))?;
 
         self.undo_count = 0;
         self.modified = false;
-        Ok(format!("{} bytes written to {}", bytes, &file.display))
+        Ok(format!(
+            "{} bytes written to {}",
+            bytes.len(),
+            &file.display
+        ))
     }
 
     pub fn set_cursor(&mut self, x: usize, y: usize) {
@@ -535,11 +812,13 @@ impl TextBuffer {
         (self.cx, self.cy)
     }
 
-    fn after_undoredo(&mut self, state: Option<(usize, usize, usize, bool)>) -> bool {
+    fn after_undoredo(&mut self, state: Option<(usize, usize, Option<DirtyLines>, bool)>) -> bool {
         match state {
-            Some((x, y, s, _)) => {
+            Some((x, y, dirty, _)) => {
                 self.set_cursor(x, y);
-                self.set_dirty_start(s);
+                if let Some(dirty) = dirty {
+                    self.set_dirty(dirty);
+                }
                 true
             }
             None => false,
@@ -547,7 +826,7 @@ impl TextBuffer {
     }
 
     pub fn undo(&mut self) -> bool {
-        let state = self.history.undo(&mut self.row);
+        let state = self.history.undo(&mut self.row, self.tab_stop);
         if let Some((_, _, _, edited)) = state {
             // If edited is true, it means that undo target is the ongoing change. In the case,
             // undo point is not consumed and undo count should not be decreased
@@ -560,7 +839,7 @@ impl TextBuffer {
     }
 
     pub fn redo(&mut self) -> bool {
-        let state = self.history.redo(&mut self.row);
+        let state = self.history.redo(&mut self.row, self.tab_stop);
         if let Some((_, _, _, edited)) = state {
             // If edited is true, it means that redo target is the ongoing change. In the case,
             // redo does not happen since the new ongoing change is happening and undo count should
@@ -0,0 +1,514 @@
+// Color theme loaded from [theme] table of 'config.toml'. Each sub-table styles an element of
+// screen. Elements which are not defined in the table use the default colors. A theme can also be
+// put in 'themes' directory of the config directory as '<name>.toml' and selected by
+// 'theme = "<name>"'. The file contains the tables without 'theme.' prefix.
+//
+// Example of TOML file:
+//
+//   [theme.normal]
+//   fg = "#ebdbb2"
+//   bg = "#1d2021"
+//
+//   [theme.comment]
+//   fg = 245
+//   italic = true
+//
+//   [theme.status_bar]
+//   fg = "black"
+//   bg = "bright-blue"
+//   bold = true
+use crate::error::{Error, Result};
+use crate::highlight::Highlight;
+use crate::input::{self, InputSeq};
+use crate::term_color::{Background, Color, TermColor};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+
+// Elements of screen which can be styled
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Face {
+    Text(Highlight),
+    NonText,
+    StatusBar,
+    Message,
+    ErrorMessage,
+}
+
+const FACES: &[(&str, Face)] = {
+    use Face::*;
+    use Highlight::*;
+    &[
+        ("normal", Text(Normal)),
+        ("number", Text(Number)),
+        ("string", Text(String)),
+        ("comment", Text(Comment)),
+        ("keyword", Text(Keyword)),
+        ("type", Text(Type)),
+        ("definition", Text(Definition)),
+        ("char", Text(Char)),
+        ("statement", Text(Statement)),
+        ("boolean", Text(Boolean)),
+        ("special_var", Text(SpecialVar)),
+        ("search", Text(Search)),
+        ("match", Text(Match)),
+        ("bracket", Text(Bracket)),
+        ("non_text", NonText),
+        ("status_bar", StatusBar),
+        ("message", Message),
+        ("error_message", ErrorMessage),
+    ]
+};
+
+// Default colors of 16 colors in xterm. Used to find the nearest color
+const ANSI_COLORS: [(u8, u8, u8); 16] = [
+    (0, 0, 0),
+    (205, 0, 0),
+    (0, 205, 0),
+    (205, 205, 0),
+    (0, 0, 238),
+    (205, 0, 205),
+    (0, 205, 205),
+    (229, 229, 229),
+    (127, 127, 127),
+    (255, 0, 0),
+    (0, 255, 0),
+    (255, 255, 0),
+    (92, 92, 255),
+    (255, 0, 255),
+    (0, 255, 255),
+    (255, 255, 255),
+];
+
+const ANSI_COLOR_NAMES: [&str; 8] = [
+    "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+];
+
+// Levels of each RGB component in 6x6x6 color cube of 256 colors
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+fn distance((r1, g1, b1): (u8, u8, u8), (r2, g2, b2): (u8, u8, u8)) -> u32 {
+    let d = |a: u8, b: u8| (i32::from(a) - i32::from(b)).pow(2) as u32;
+    d(r1, r2) + d(g1, g2) + d(b1, b2)
+}
+
+fn nearest_index<I: Iterator<Item = (u8, (u8, u8, u8))>>(rgb: (u8, u8, u8), colors: I) -> u8 {
+    colors
+        .min_by_key(|(_, c)| distance(rgb, *c))
+        .map(|(i, _)| i)
+        .unwrap_or(0)
+}
+
+// Color in theme file: "#rrggbb", index of 256 colors, or name of 16 colors such as "red" or
+// "bright-red"
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ThemeColor {
+    Rgb(u8, u8, u8),
+    Indexed(u8), // 0..=15 are 16 colors
+}
+
+impl ThemeColor {
+    fn parse(s: &str) -> Option<ThemeColor> {
+        if let Some(hex) = s.strip_prefix('#') {
+            if hex.len() != 6 || !hex.is_ascii() {
+                return None;
+            }
+            let c = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+            return Some(ThemeColor::Rgb(c(0)?, c(2)?, c(4)?));
+        }
+        if let Ok(idx) = s.parse() {
+            return Some(ThemeColor::Indexed(idx));
+        }
+        let (name, bright) = match s.strip_prefix("bright-") {
+            Some(name) => (name, 8),
+            None if s == "gray" || s == "grey" => ("black", 8),
+            None => (s, 0),
+        };
+        let idx = ANSI_COLOR_NAMES.iter().position(|n| *n == name)?;
+        Some(ThemeColor::Indexed(idx as u8 + bright))
+    }
+
+    fn rgb(self) -> (u8, u8, u8) {
+        match self {
+            ThemeColor::Rgb(r, g, b) => (r, g, b),
+            ThemeColor::Indexed(i) if i < 16 => ANSI_COLORS[i as usize],
+            ThemeColor::Indexed(i) if i < 232 => {
+                let i = i - 16;
+                let l = |n: u8| CUBE_LEVELS[n as usize];
+                (l(i / 36), l(i / 6 % 6), l(i % 6))
+            }
+            ThemeColor::Indexed(i) => {
+                let v = 8 + (i - 232) * 10;
+                (v, v, v)
+            }
+        }
+    }
+
+    fn to_256(self) -> u8 {
+        match self {
+            ThemeColor::Indexed(i) => i,
+            ThemeColor::Rgb(r, g, b) => {
+                // Only colors in the cube and grays since 16 colors depend on terminal
+                let colors = (16..=255).map(|i| (i, ThemeColor::Indexed(i).rgb()));
+                nearest_index((r, g, b), colors)
+            }
+        }
+    }
+
+    fn to_16(self) -> u8 {
+        match self {
+            ThemeColor::Indexed(i) if i < 16 => i,
+            _ => nearest_index(self.rgb(), (0..16).map(|i| (i, ANSI_COLORS[i as usize]))),
+        }
+    }
+
+    // Write SGR sequence of the color degrading it to colors which the terminal supports
+    fn write_sgr(self, out: &mut Vec<u8>, term_color: TermColor, bg: bool) {
+        let param = if bg { 48 } else { 38 };
+        let write_16 = |out: &mut Vec<u8>, i: u8| {
+            let base = match (bg, i < 8) {
+                (false, true) => 30,
+                (false, false) => 90 - 8,
+                (true, true) => 40,
+                (true, false) => 100 - 8,
+            };
+            write!(out, "\x1b[{}m", base + u32::from(i)).unwrap();
+        };
+        match (term_color, self) {
+            (TermColor::NoColor, _) => {}
+            (_, ThemeColor::Indexed(i)) if i < 16 => write_16(out, i),
+            (TermColor::TrueColors, ThemeColor::Rgb(r, g, b)) => {
+                write!(out, "\x1b[{};2;{};{};{}m", param, r, g, b).unwrap();
+            }
+            (TermColor::TrueColors, c) | (TermColor::Colors256, c) => {
+                write!(out, "\x1b[{};5;{}m", param, c.to_256()).unwrap();
+            }
+            (TermColor::Colors16, c) => write_16(out, c.to_16()),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum ColorDefinition {
+    Index(u8),
+    Name(String),
+}
+
+#[derive(Deserialize, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+struct StyleDefinition {
+    fg: Option<ColorDefinition>,
+    bg: Option<ColorDefinition>,
+    bold: bool,
+    italic: bool,
+    underline: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Default, Debug)]
+pub struct Style {
+    pub fg: Option<ThemeColor>,
+    pub bg: Option<ThemeColor>,
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+}
+
+impl Style {
+    fn sequence(&self, term_color: TermColor) -> Vec<u8> {
+        let mut seq = vec![];
+        if let Some(fg) = self.fg {
+            fg.write_sgr(&mut seq, term_color, false);
+        }
+        if let Some(bg) = self.bg {
+            bg.write_sgr(&mut seq, term_color, true);
+        }
+        if self.bold {
+            seq.extend_from_slice(b"\x1b[1m");
+        }
+        if self.italic {
+            seq.extend_from_slice(b"\x1b[3m");
+        }
+        if self.underline {
+            seq.extend_from_slice(b"\x1b[4m");
+        }
+        seq
+    }
+}
+
+// Styles defined in [theme] table of config file or a theme file
+#[derive(Default, Debug)]
+pub struct ThemeDefinition {
+    styles: HashMap<Face, Style>,
+}
+
+impl ThemeDefinition {
+    pub fn from_toml(table: toml::Value) -> std::result::Result<ThemeDefinition, String> {
+        let defs: HashMap<String, StyleDefinition> = table.try_into().map_err(|e| e.to_string())?;
+
+        let mut styles = HashMap::new();
+        for (name, def) in defs {
+            let face = FACES
+                .iter()
+                .find(|(n, _)| *n == name)
+                .map(|(_, f)| *f)
+                .ok_or_else(|| format!("Unknown element '{}'", name))?;
+            let color = |c: Option<ColorDefinition>| match c {
+                None => Ok(None),
+                Some(ColorDefinition::Index(i)) => Ok(Some(ThemeColor::Indexed(i))),
+                Some(ColorDefinition::Name(s)) => ThemeColor::parse(&s)
+                    .map(Some)
+                    .ok_or_else(|| format!("Invalid color '{}' for '{}'", s, name)),
+            };
+            let style = Style {
+                fg: color(def.fg)?,
+                bg: color(def.bg)?,
+                bold: def.bold,
+                italic: def.italic,
+                underline: def.underline,
+            };
+            styles.insert(face, style);
+        }
+
+        Ok(ThemeDefinition { styles })
+    }
+
+    // Parse standalone theme file such as 'themes/gruvbox.toml'
+    pub fn parse(path: &Path) -> Result<ThemeDefinition> {
+        let invalid = |msg: String| Error::InvalidThemeFile(path.to_path_buf(), msg);
+        let content = fs::read_to_string(path).map_err(|e| invalid(e.to_string()))?;
+        let table: toml::Value = toml::from_str(&content).map_err(|e| invalid(e.to_string()))?;
+        Self::from_toml(table).map_err(invalid)
+    }
+}
+
+static BACKGROUND: OnceLock<Background> = OnceLock::new();
+
+// Background is given by option or detected by querying to terminal
+// Returns keys input while detecting background. They should be handled by editor
+pub fn detect_background<I>(background: Option<Background>, input: I) -> Result<Vec<InputSeq>>
+where
+    I: Iterator<Item = Result<InputSeq>>,
+{
+    let (background, pending) = match background {
+        Some(bg) => (bg, vec![]),
+        None => input::with_read_timeout(|| Background::detect(input, io::stdout()))??,
+    };
+    let _ = BACKGROUND.set(background);
+    Ok(pending)
+}
+
+static NO_COLOR: AtomicBool = AtomicBool::new(false);
+
+// Render screen without colors even if the terminal supports colors
+pub fn disable_colors() {
+    NO_COLOR.store(true, Ordering::Relaxed);
+}
+
+// Escape sequences of all faces calculated for the terminal
+pub struct Theme {
+    reset: Vec<u8>,
+    faces: HashMap<Face, Vec<u8>>,
+}
+
+impl Theme {
+    pub fn new(
+        term_color: TermColor,
+        background: Background,
+        def: Option<&ThemeDefinition>,
+    ) -> Theme {
+        let style = |face| def.and_then(|d| d.styles.get(&face));
+
+        // Every sequence starts with resetting attributes not to inherit the previous face
+        let reset = match style(Face::Text(Highlight::Normal)) {
+            Some(s) => [b"\x1b[0m".as_ref(), &s.sequence(term_color)].concat(),
+            None => term_color.sequence(Color::Reset, background).to_vec(),
+        };
+
+        let faces = FACES
+            .iter()
+            .map(|&(_, face)| {
+                let seq = match style(face).map(|s| s.sequence(term_color)) {
+                    // Style only with colors is lost in monochrome mode. Use the default attributes
+                    // instead not to make the face indistinguishable from normal text
+                    Some(seq) if !seq.is_empty() || term_color != TermColor::NoColor => seq,
+                    _ => Self::default_sequence(term_color, background, face).to_vec(),
+                };
+                (face, [reset.as_slice(), &seq].concat())
+            })
+            .collect();
+
+        Theme { reset, faces }
+    }
+
+    pub fn from_env(def: Option<&ThemeDefinition>) -> Theme {
+        let background = BACKGROUND.get().copied().unwrap_or(Background::Dark);
+        let term_color = if NO_COLOR.load(Ordering::Relaxed) {
+            TermColor::NoColor
+        } else {
+            TermColor::from_env()
+        };
+        Self::new(term_color, background, def)
+    }
+
+    fn default_sequence(
+        term_color: TermColor,
+        background: Background,
+        face: Face,
+    ) -> &'static [u8] {
+        let color = match face {
+            Face::Text(Highlight::Normal) | Face::Message => return b"",
+            Face::Text(hl) => hl.color(),
+            Face::NonText => Color::NonText,
+            Face::StatusBar => Color::Invert,
+            Face::ErrorMessage => Color::RedBg,
+        };
+        term_color.sequence(color, background)
+    }
+
+    pub fn reset(&self) -> &[u8] {
+        &self.reset
+    }
+
+    pub fn sequence(&self, face: Face) -> &[u8] {
+        &self.faces[&face]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(content: &str) -> std::result::Result<ThemeDefinition, String> {
+        ThemeDefinition::from_toml(toml::from_str(content).unwrap())
+    }
+
+    #[test]
+    fn theme_definition() {
+        let def = parse(
+            r##"
+[keyword]
+fg = "#ff8000"
+bold = true
+
+[comment]
+fg = "bright-black"
+italic = true
+
+[status_bar]
+fg = 0
+bg = 153
+"##,
+        )
+        .unwrap();
+
+        let contains = |seq: &[u8], sub: &str| {
+            let seq = String::from_utf8(seq.to_vec()).unwrap();
+            assert!(seq.contains(sub), "{:?} does not contain {:?}", seq, sub);
+        };
+
+        let keyword = Face::Text(Highlight::Keyword);
+        let comment = Face::Text(Highlight::Comment);
+        for (term_color, expected) in &[
+            (TermColor::TrueColors, "\x1b[38;2;255;128;0m"),
+            (TermColor::Colors256, "\x1b[38;5;208m"),
+            (TermColor::Colors16, "\x1b[33m"),
+        ] {
+            let theme = Theme::new(*term_color, Background::Dark, Some(&def));
+            contains(theme.sequence(keyword), expected);
+            contains(theme.sequence(keyword), "\x1b[1m");
+            contains(theme.sequence(comment), "\x1b[90m");
+            contains(theme.sequence(comment), "\x1b[3m");
+            contains(theme.sequence(Face::StatusBar), "\x1b[30m");
+        }
+
+        let theme = Theme::new(TermColor::Colors256, Background::Dark, Some(&def));
+        contains(theme.sequence(Face::StatusBar), "\x1b[48;5;153m");
+        let theme = Theme::new(TermColor::Colors16, Background::Dark, Some(&def));
+        contains(theme.sequence(Face::StatusBar), "\x1b[47m");
+
+        // Faces not in the definition use default colors
+        let default = Theme::new(TermColor::TrueColors, Background::Dark, None);
+        let theme = Theme::new(TermColor::TrueColors, Background::Dark, Some(&def));
+        let string = Face::Text(Highlight::String);
+        assert_eq!(theme.sequence(string), default.sequence(string));
+
+        for broken in &[
+            "[keywords]\nfg = \"red\"",
+            "[keyword]\nfg = \"reddish\"",
+            "[keyword]\nfg = \"#12345\"",
+            "[keyword]\nbold = 1",
+            "[keyword]\nblink = true",
+        ] {
+            assert!(parse(broken).is_err(), "{:?}", broken);
+        }
+    }
+
+    #[test]
+    fn default_theme_for_background() {
+        let dark = Theme::new(TermColor::Colors16, Background::Dark, None);
+        let light = Theme::new(TermColor::Colors16, Background::Light, None);
+        let face = Face::Text(Highlight::Definition);
+        assert_ne!(dark.sequence(face), light.sequence(face));
+    }
+
+    #[test]
+    fn monochrome_theme() {
+        let theme = Theme::new(TermColor::NoColor, Background::Dark, None);
+        let faces = [
+            Face::Text(Highlight::Search),
+            Face::Text(Highlight::Match),
+            Face::Text(Highlight::Bracket),
+            Face::StatusBar,
+            Face::ErrorMessage,
+        ];
+        for (i, face) in faces.iter().enumerate() {
+            let seq = String::from_utf8(theme.sequence(*face).to_vec()).unwrap();
+            let attrs = seq.trim_start_matches("\x1b[0m");
+            assert!(!attrs.is_empty(), "{:?} is not distinguishable", face);
+            for c in ["\x1b[3", "\x1b[4", "\x1b[9", "\x1b[10"].iter() {
+                let has_color = attrs
+                    .split('m')
+                    .any(|a| a.starts_with(c) && a.len() > c.len());
+                assert!(!has_color, "{:?} has color: {:?}", face, seq);
+            }
+            // Highlights in text area must be distinguishable from each other
+            for other in faces.iter().take(3).skip(i + 1) {
+                assert_ne!(theme.sequence(*face), theme.sequence(*other), "{:?}", other);
+            }
+        }
+
+        let def = parse("[keyword]\nfg = \"red\"\nunderline = true\n").unwrap();
+        let theme = Theme::new(TermColor::NoColor, Background::Dark, Some(&def));
+        assert_eq!(
+            theme.sequence(Face::Text(Highlight::Keyword)),
+            b"\x1b[0m\x1b[4m"
+        );
+    }
+
+    #[test]
+    fn monochrome_theme_without_attributes() {
+        let def = parse(
+            "[search]
+bg = \"yellow\"\n[status_bar]
+fg = 0
+bg = 153
+",
+        )
+        .unwrap();
+        let default = Theme::new(TermColor::NoColor, Background::Dark, None);
+        let theme = Theme::new(TermColor::NoColor, Background::Dark, Some(&def));
+        for face in &[Face::Text(Highlight::Search), Face::StatusBar] {
+            assert_ne!(theme.sequence(*face), b"\x1b[0m", "{:?}", face);
+            assert_eq!(theme.sequence(*face), default.sequence(*face), "{:?}", face);
+        }
+
+        // Colors are not degraded when the terminal supports them
+        let theme = Theme::new(TermColor::Colors256, Background::Dark, Some(&def));
+        assert!(theme.sequence(Face::StatusBar).ends_with(b"\x1b[48;5;153m"));
+    }
+}
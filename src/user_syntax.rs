@@ -0,0 +1,213 @@
+// Syntax definitions written by users. Each definition is put in 'syntax' directory in the config
+// directory as a TOML (*.toml) or JSON (*.json) file and registered as a language at startup.
+//
+// Example of TOML file:
+//
+//   name = "zig"
+//   file_extensions = ["zig"]
+//   string_quotes = ['"']
+//   character = true
+//   line_comment = "//"
+//   keywords = ["const", "fn", "pub", "var"]
+//   control_statements = ["if", "else", "return", "while"]
+//   boolean_constants = ["true", "false"]
+//   number = true
+//   hex_number = true
+//   indent_style = "space"
+//   indent_size = 4
+use crate::config;
+use crate::editorconfig::IndentStyle;
+use crate::error::{Error, Result};
+use crate::language::{Indent, Language, UserLanguages};
+use serde::Deserialize;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::Path;
+
+#[derive(Deserialize, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct SyntaxDefinition {
+    pub name: String,
+    pub file_extensions: Vec<String>,
+    pub string_quotes: Vec<char>,
+    pub multiline_strings: Vec<StringDelimDefinition>,
+    pub character: bool,
+    pub line_comment: Option<String>,
+    pub block_comment: Option<(String, String)>,
+    pub keywords: Vec<String>,
+    pub control_statements: Vec<String>,
+    pub builtin_types: Vec<String>,
+    pub boolean_constants: Vec<String>,
+    pub special_vars: Vec<String>,
+    pub definition_keywords: Vec<String>,
+    pub number: bool,
+    pub hex_number: bool,
+    pub bin_number: bool,
+    pub number_delim: Option<char>,
+    pub variables: bool,
+    pub key_separator: Option<char>,
+    pub table_header: bool,
+    pub heading: bool,
+    pub code_fence: Option<String>,
+    pub indent_style: Option<IndentStyle>,
+    pub indent_size: Option<usize>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct StringDelimDefinition {
+    pub start: String,
+    pub end: String,
+    #[serde(default)]
+    pub escape: bool,
+}
+
+impl SyntaxDefinition {
+    pub fn parse(path: &Path) -> Result<SyntaxDefinition> {
+        let content = fs::read_to_string(path)?;
+        let invalid = |msg: String| Error::InvalidSyntaxFile(path.to_path_buf(), msg);
+        let def: SyntaxDefinition = match path.extension().and_then(OsStr::to_str) {
+            Some("json") => serde_json::from_str(&content).map_err(|e| invalid(e.to_string()))?,
+            _ => toml::from_str(&content).map_err(|e| invalid(e.to_string()))?,
+        };
+        if def.name.is_empty() {
+            return Err(invalid("'name' must not be empty".to_string()));
+        }
+        if def.indent_size == Some(0) {
+            return Err(invalid("'indent_size' must be greater than 0".to_string()));
+        }
+        // Empty delimiters would match at every position
+        let mut delims = vec![];
+        if let Some(comment) = &def.line_comment {
+            delims.push(("line_comment", comment));
+        }
+        if let Some((start, end)) = &def.block_comment {
+            delims.push(("block_comment", start));
+            delims.push(("block_comment", end));
+        }
+        if let Some(fence) = &def.code_fence {
+            delims.push(("code_fence", fence));
+        }
+        for delim in def.multiline_strings.iter() {
+            delims.push(("multiline_strings", &delim.start));
+            delims.push(("multiline_strings", &delim.end));
+        }
+        if let Some((key, _)) = delims.iter().find(|(_, d)| d.is_empty()) {
+            return Err(invalid(format!("Delimiter in '{}' must not be empty", key)));
+        }
+        Ok(def)
+    }
+
+    // Default indentation of the language. 4 spaces is used when it is not defined as most of
+    // built-in languages do
+    pub fn indent(&self) -> Indent {
+        match (self.indent_style, self.indent_size) {
+            (Some(IndentStyle::Tab), _) => Indent::Tab,
+            (_, Some(width)) => Indent::Spaces(width),
+            _ => Indent::Spaces(4),
+        }
+    }
+}
+
+// Definitions live until the process exits. Leak strings to use them in the same way as built-in
+// syntax definitions which consist of static strings
+pub fn leak_str(s: String) -> &'static str {
+    Box::leak(s.into_boxed_str())
+}
+
+pub fn leak_strs(v: Vec<String>) -> &'static [&'static str] {
+    let v: Vec<_> = v.into_iter().map(leak_str).collect();
+    Box::leak(v.into_boxed_slice())
+}
+
+// Load all syntax definition files in the directory
+pub fn load_dir(dir: &Path) -> Result<UserLanguages> {
+    let mut paths = vec![];
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        match path.extension().and_then(OsStr::to_str) {
+            Some("toml") | Some("json") if path.is_file() => paths.push(path),
+            _ => {}
+        }
+    }
+    // Sort for stable order of detecting languages
+    paths.sort();
+
+    let defs = paths
+        .iter()
+        .map(|p| SyntaxDefinition::parse(p))
+        .collect::<Result<Vec<_>>>()?;
+    Ok(UserLanguages::new(defs))
+}
+
+// Register syntax definitions in the config directory as languages. This must be called only once
+pub fn load_user_syntaxes() -> Result<()> {
+    match config::config_dir().map(|d| d.join("syntax")) {
+        Some(dir) if dir.is_dir() => Language::register_user_languages(load_dir(&dir)?),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::highlight::{Highlight, Highlighting};
+    use crate::row::{Row, TAB_STOP};
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn load_syntax_files() {
+        let dir = TempDir::new("load-syntax");
+        dir.write(
+            "foo.toml",
+            r#"
+name = "foo"
+file_extensions = ["kirofoo"]
+string_quotes = ['"']
+line_comment = "--"
+keywords = ["let"]
+number = true
+indent_size = 2
+"#,
+        );
+        dir.write(
+            "bar.json",
+            r#"{"name": "bar", "file_extensions": ["kirobar"], "control_statements": ["if"]}"#,
+        );
+
+        // Languages are not registered globally so that other tests are not affected
+        let langs: &'static UserLanguages = Box::leak(Box::new(load_dir(&dir).unwrap()));
+        let names: Vec<_> = langs.languages().iter().map(|l| l.name()).collect();
+        assert_eq!(names, vec!["bar", "foo"]);
+        assert_eq!(langs.languages()[1].file_exts(), &["kirofoo"]);
+        assert_eq!(langs.languages()[0].indent(), Indent::Spaces(4));
+        assert_eq!(langs.languages()[1].indent(), Indent::Spaces(2));
+
+        let rows = vec![Row::new("let x = \"a\" -- 1", TAB_STOP).unwrap()];
+        let mut hl = Highlighting::with_syntax(&langs.languages()[1].syntax, &rows);
+        hl.update(&rows, 1);
+        let hl = &hl.lines[0];
+        assert_eq!(hl[0], Highlight::Keyword);
+        assert_eq!(hl[8], Highlight::String);
+        assert_eq!(hl[13], Highlight::Comment);
+    }
+
+    #[test]
+    fn broken_syntax_files() {
+        let dir = TempDir::new("broken-syntax");
+        for content in &[
+            "nam = \"typo\"",
+            "name = \"\"",
+            "name = \"x\"\nmultiline_strings = [{ start = \"\", end = \"x\" }]",
+            "name = \"x\"\nmultiline_strings = [{ start = \"x\", end = \"\" }]",
+            "name = \"x\"\nblock_comment = [\"\", \"x\"]",
+            "name = \"x\"\nblock_comment = [\"x\", \"\"]",
+            "name = \"x\"\nline_comment = \"\"",
+            "name = \"x\"\ncode_fence = \"\"",
+            "name = \"x\"\nindent_size = 0",
+        ] {
+            let path = dir.write("broken.toml", content);
+            assert!(SyntaxDefinition::parse(&path).is_err(), "{:?}", content);
+        }
+    }
+}